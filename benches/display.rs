@@ -0,0 +1,97 @@
+// DRW on the bit-packed display against the byte per pixel display it replaced, whose
+// drawing loop is kept here as it was: the digit sprites, and 15 rows sprites crossing
+// the right and bottom edges, wrapped and clipped.
+//
+// cargo bench --bench display
+
+use chip_8::chip8::{HEIGHT, WIDTH};
+use chip_8::display::{self, Display};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const SPRITE: [u8; 15] = [
+    0x3C, 0x42, 0x81, 0xA5, 0x81, 0x99, 0x42, 0x3C, 0xFF, 0x00, 0xFF, 0x18, 0x24, 0x42, 0x81,
+];
+
+// (x, y, rows)
+fn sprites() -> Vec<(u8, u8, usize)> {
+    let mut sprites = vec![];
+    for i in 0..16 {
+        sprites.push((i * 4, (i % 6) * 5, 5));
+        sprites.push((WIDTH - 4 + i % 4, HEIGHT - 8 + i % 8, 15));
+    }
+    sprites
+}
+
+fn draw_bytes(display: &mut [u8; display::SIZE], x: u8, y: u8, sprite: &[u8], clip: bool) -> bool {
+    let start_x = x % WIDTH;
+    let start_y = y % HEIGHT;
+    let mut collision = false;
+
+    for (line, sprite_line) in sprite.iter().enumerate() {
+        let line = line as u8;
+        for column in 0..8 {
+            if clip && (start_x + column >= WIDTH || start_y + line >= HEIGHT) {
+                continue;
+            }
+
+            let pos_x = (start_x + column) % WIDTH;
+            let pos_y = (start_y + line) % HEIGHT;
+
+            let px_index = (pos_y as usize) * 64 + (pos_x as usize);
+            let sprite_column_px = if (sprite_line << column) & 0b10000000 == 0b10000000 {
+                1
+            } else {
+                0
+            };
+            let old_px = display[px_index];
+            let new_px = old_px ^ sprite_column_px;
+            display[px_index] = new_px;
+
+            if old_px == 1 && new_px == 0 {
+                collision = true;
+            }
+        }
+    }
+    collision
+}
+
+fn bench_drw(c: &mut Criterion) {
+    let sprites = sprites();
+    let mut group = c.benchmark_group("drw");
+
+    for clip in [false, true].iter() {
+        let name = if *clip { "clip" } else { "wrap" };
+
+        group.bench_function(format!("bytes/{}", name), |bencher| {
+            let mut display = [0; display::SIZE];
+            bencher.iter(|| {
+                let mut collisions = 0;
+                for (x, y, rows) in sprites.iter() {
+                    collisions +=
+                        draw_bytes(&mut display, *x, *y, black_box(&SPRITE[..*rows]), *clip) as u32;
+                }
+                collisions
+            })
+        });
+
+        group.bench_function(format!("packed/{}", name), |bencher| {
+            let mut display = Display::new();
+            bencher.iter(|| {
+                let mut collisions = 0;
+                for (x, y, rows) in sprites.iter() {
+                    collisions += display.draw_sprite(
+                        *x as usize,
+                        *y as usize,
+                        black_box(&SPRITE[..*rows]),
+                        *clip,
+                    ) as u32;
+                }
+                collisions
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_drw);
+criterion_main!(benches);
@@ -0,0 +1,99 @@
+// Frames of the test programs at a high speed, decoding every instruction at its fetch
+// against taking it from the decoded instruction cache. numbers waits for a key after a
+// few thousand cycles spread over most of memory, the cost of a cache that stays cold.
+// With the jit feature, against the compiled blocks too, counting their compilation or
+// not.
+//
+// cargo bench --bench interpreter --features jit
+
+use chip_8::assembler;
+use chip_8::cache::{self, DecodeCache};
+use chip_8::chip8::{self, Chip8};
+#[cfg(feature = "jit")]
+use chip_8::jit::{self, Jit};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const CYCLES_PER_FRAME: u32 = 10_000;
+const FRAMES: u32 = 10;
+
+fn bench_interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpreter");
+
+    for filepath in [
+        "tests/roms/clock.ch8",
+        "tests/roms/numbers.ch8",
+        "tests/roms/random.ch8",
+        "tests/conformance/opcodes.cp8asm",
+    ]
+    .iter()
+    {
+        let rom = if filepath.ends_with(".cp8asm") {
+            assembler::assemble(filepath).unwrap()
+        } else {
+            std::fs::read(filepath).unwrap()
+        };
+        let name = filepath
+            .rsplit('/')
+            .next()
+            .unwrap()
+            .split('.')
+            .next()
+            .unwrap();
+        let new_chip8 = || {
+            let mut chip8 = Chip8::new(&rom);
+            chip8.cycles_per_frame = CYCLES_PER_FRAME;
+            chip8
+        };
+
+        group.bench_function(format!("decode/{}", name), |bencher| {
+            bencher.iter(|| {
+                let mut chip8 = new_chip8();
+                for _frame in 0..FRAMES {
+                    chip8::run_frame(&mut chip8);
+                }
+                chip8.pc
+            })
+        });
+
+        group.bench_function(format!("cache/{}", name), |bencher| {
+            bencher.iter(|| {
+                let mut chip8 = new_chip8();
+                let mut cache = DecodeCache::new();
+                for _frame in 0..FRAMES {
+                    cache::run_frame(&mut chip8, &mut cache);
+                }
+                chip8.pc
+            })
+        });
+
+        #[cfg(feature = "jit")]
+        {
+            group.bench_function(format!("jit/{}", name), |bencher| {
+                bencher.iter(|| {
+                    let mut chip8 = new_chip8();
+                    let mut jit = Jit::new();
+                    for _frame in 0..FRAMES {
+                        jit::run_frame(&mut chip8, &mut jit);
+                    }
+                    chip8.pc
+                })
+            });
+
+            // The blocks compiled by the first run
+            let mut jit = Jit::new();
+            group.bench_function(format!("jit-warm/{}", name), |bencher| {
+                bencher.iter(|| {
+                    let mut chip8 = new_chip8();
+                    for _frame in 0..FRAMES {
+                        jit::run_frame(&mut chip8, &mut jit);
+                    }
+                    chip8.pc
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_interpreter);
+criterion_main!(benches);
@@ -0,0 +1,95 @@
+// The CPU side of drawing the display, once per window frame: a rect per lit pixel as the
+// view used to do, against the texture's change check and the pixel conversion when it
+// changed. Only that CPU work is timed: tessellation, the texture upload and the GPU draw
+// need a window, so these numbers don't tell the frame time.
+//
+// cargo bench --bench render
+
+use chip_8::chip8::{self, Chip8, HEIGHT, WIDTH};
+use chip_8::palette::{self, Palette};
+use chip_8::phosphor::{PersistenceMode, Phosphor};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nannou::prelude::*;
+
+// Window points per display cell, as in a 640x320 window
+const CELL_SIZE: f32 = 10.0;
+
+// clock.ch8 after a second, and every pixel lit
+fn displays() -> Vec<(&'static str, Vec<u64>, Vec<u8>)> {
+    let mut chip8 = Chip8::new(include_bytes!("../tests/roms/clock.ch8"));
+    for _frame in 0..60 {
+        chip8::run_frame(&mut chip8);
+    }
+    let mut phosphor = Phosphor::new(PersistenceMode::Off);
+    phosphor.update(&chip8.display);
+
+    vec![
+        (
+            "clock",
+            chip8.display.rows().to_vec(),
+            phosphor.intensity().to_vec(),
+        ),
+        (
+            "full",
+            vec![u64::MAX; HEIGHT as usize],
+            vec![255; WIDTH as usize * HEIGHT as usize],
+        ),
+    ]
+}
+
+fn draw_rects(draw: &Draw, intensity: &[u8], palette: &Palette) {
+    let display_width = CELL_SIZE * WIDTH as f32;
+    let display_height = CELL_SIZE * HEIGHT as f32;
+
+    let [r, g, b] = palette.background;
+    draw.rect()
+        .w_h(display_width, display_height)
+        .color(rgb8(r, g, b));
+
+    for (i, px_intensity) in intensity.iter().enumerate() {
+        if *px_intensity > 0 {
+            let window_x = -display_width / 2.0 + (i % 64) as f32 * CELL_SIZE + CELL_SIZE / 2.0;
+            let window_y = display_height / 2.0 - (i / 64) as f32 * CELL_SIZE - CELL_SIZE / 2.0;
+
+            let [r, g, b] = palette::blend(palette, *px_intensity);
+            draw.rect()
+                .x_y(window_x, window_y)
+                .w_h(CELL_SIZE, CELL_SIZE)
+                .color(rgb8(r, g, b));
+        }
+    }
+}
+
+fn bench_display(c: &mut Criterion) {
+    let palette = &palette::presets()[0];
+    let mut group = c.benchmark_group("display");
+
+    for (name, rows, intensity) in displays() {
+        group.bench_function(format!("rects/{}", name), |bencher| {
+            let draw = Draw::new();
+            bencher.iter(|| {
+                draw_rects(&draw, black_box(&intensity), palette);
+                // What the renderer takes from the draw to tessellate
+                let commands = draw.drain_commands().count();
+                draw.reset();
+                commands
+            })
+        });
+
+        // As renderer.rs: the rows, then the intensities, against what was uploaded
+        group.bench_function(format!("texture-check/{}", name), |bencher| {
+            let (uploaded_rows, uploaded_intensity) = (rows.clone(), intensity.clone());
+            bencher.iter(|| {
+                black_box(&rows) == &uploaded_rows && black_box(&intensity) == &uploaded_intensity
+            })
+        });
+
+        group.bench_function(format!("texture-convert/{}", name), |bencher| {
+            bencher.iter(|| palette::to_rgba(palette, black_box(&intensity)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_display);
+criterion_main!(benches);
@@ -0,0 +1,12 @@
+#![no_main]
+// Arbitrary source text: the assembler must return an error, never panic.
+// Crashers go in tests/crashers.rs.
+
+use chip_8::assembler;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = assembler::assemble_text(text, "fuzz.cp8asm");
+    }
+});
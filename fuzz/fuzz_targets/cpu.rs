@@ -0,0 +1,42 @@
+#![no_main]
+// Arbitrary programs for a second of emulated time: the cpu must never panic.
+// Crashers go in tests/crashers.rs.
+
+use chip_8::chip8::{self, Chip8, Quirks, LOAD_ADDRESS, MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks the quirks and the memory size, the rest is the program
+    let (config, program) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let memory_size = if config & 0x40 != 0 {
+        XO_CHIP_MEMORY_SIZE
+    } else {
+        MEMORY_SIZE
+    };
+    let mut chip8 = match Chip8::load(program, LOAD_ADDRESS, memory_size) {
+        Ok(chip8) => chip8,
+        Err(_) => return,
+    };
+    chip8.quirks = Quirks {
+        shift_vx: config & 0x01 != 0,
+        load_store_increment_i: config & 0x02 != 0,
+        jump_vx: config & 0x04 != 0,
+        clip_sprites: config & 0x08 != 0,
+        vf_reset: config & 0x10 != 0,
+        display_wait: config & 0x20 != 0,
+    };
+    chip8.cycles_per_frame = 100;
+
+    for frame in 0..60 {
+        // A key for Fx0A and the skips, then the frame, then an instruction at whatever pc
+        // the frame ended on
+        let key = frame % 16;
+        chip8::key_pressed(&mut chip8, key);
+        chip8::run_frame(&mut chip8);
+        chip8::key_released(&mut chip8, key);
+        chip8::run_next_cpu_cycle(&mut chip8);
+    }
+});
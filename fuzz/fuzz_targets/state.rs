@@ -0,0 +1,22 @@
+#![no_main]
+// Arbitrary save states, as loaded from files, remote requests and libretro frontends:
+// load_state must return an error or a machine that runs without panicking.
+// Crashers go in tests/crashers.rs.
+
+use chip_8::chip8::{self, Chip8};
+use chip_8::state;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut chip8 = Chip8::new(&[0x12, 0x00]);
+    if state::load_state(&mut chip8, data).is_err() {
+        return;
+    }
+    chip8.cycles_per_frame = 100;
+
+    for key in 0..16 {
+        chip8::key_pressed(&mut chip8, key);
+        chip8::run_frame(&mut chip8);
+        chip8::key_released(&mut chip8, key);
+    }
+});
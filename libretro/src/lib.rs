@@ -0,0 +1,610 @@
+// libretro core, to run the emulator in RetroArch or any other libretro frontend:
+//     cargo build --release -p chip-8-libretro
+// then load target/release/libchip_8_libretro.so (.dll, .dylib) as a core.
+// https://docs.libretro.com/development/cores/developing-cores/
+//
+// The frontend drives everything: it calls retro_run() once per 60hz frame, and the core
+// answers through the callbacks it was given. Quirks, speed, palette, anti-flicker and the
+// joypad layout are core options, changed from the frontend's menu.
+
+// Every export is called by the frontend with the pointers the libretro API promises
+#![allow(clippy::missing_safety_doc)]
+
+use chip_8::cheat::{self, Cheat};
+use chip_8::chip8::{
+    self, Chip8, Quirks, HEIGHT, LOAD_ADDRESS, MEMORY_SIZE, WIDTH, XO_CHIP_MEMORY_SIZE,
+};
+use chip_8::palette::{self, Palette};
+use chip_8::phosphor::{PersistenceMode, Phosphor};
+use chip_8::state;
+use chip_8::tone::{Tone, SAMPLES_PER_FRAME, SAMPLE_RATE};
+use libretro_sys::*;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::sync::Mutex;
+
+const WAVE_LENGTH: u32 = 440;
+
+// Frames for a pixel to fade out with the fade anti-flicker option
+const FADE_FRAMES: u8 = 4;
+
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+struct Core {
+    // Kept for retro_reset
+    rom: Vec<u8>,
+
+    chip8: Chip8,
+    phosphor: Phosphor,
+    tone: Tone,
+    palette: Palette,
+
+    // Chip-8 key for each joypad button, indexed by DEVICE_ID_JOYPAD_*
+    layout: [u8; 12],
+
+    // XRGB8888, handed to the frontend every frame
+    framebuffer: Vec<u32>,
+
+    // From the frontend's cheat menu, by index
+    cheats: Vec<Cheat>,
+}
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+// Core options: key, then "Description; default|other values"
+const VARIABLES: [(&[u8], &[u8]); 5] = [
+    (b"chip8_quirks\0", b"Quirks; modern|cosmac|schip\0"),
+    (
+        b"chip8_speed\0",
+        b"Instructions per frame; 8|4|10|15|20|30|50|100|200|500|1000\0",
+    ),
+    (
+        b"chip8_palette\0",
+        b"Palette; white|green|amber|lcd|high-contrast|colorblind\0",
+    ),
+    (b"chip8_persistence\0", b"Anti-flicker; off|fade|or\0"),
+    (b"chip8_layout\0", b"Joypad layout; numpad|wasd\0"),
+];
+
+// Joypad buttons in DEVICE_ID_JOYPAD_* order:
+//     B Y Select Start Up Down Left Right A X L R
+// numpad: the d-pad is 2 4 6 8 around 5, what most games use for directions.
+// wasd: the d-pad is 5 7 8 9, the keys under W A S D on the desktop frontend.
+const LAYOUT_NUMPAD: [u8; 12] = [0x0, 0x1, 0xA, 0xB, 0x2, 0x8, 0x4, 0x6, 0x5, 0x3, 0x7, 0x9];
+const LAYOUT_WASD: [u8; 12] = [0x4, 0x1, 0x0, 0xF, 0x5, 0x8, 0x7, 0x9, 0x6, 0x2, 0x3, 0xC];
+
+/*
+Keyboard, same as the desktop frontend
+1 2 3 C  =>  1 2 3 4
+4 5 6 D  =>  q w e r
+7 8 9 E  =>  a s d f
+A 0 B F  =>  z x c v
+*/
+const KEYBOARD: [(Key, u8); 16] = [
+    (Key::Number_1, 0x1),
+    (Key::Number_2, 0x2),
+    (Key::Number_3, 0x3),
+    (Key::Number_4, 0xC),
+    (Key::Q, 0x4),
+    (Key::W, 0x5),
+    (Key::E, 0x6),
+    (Key::R, 0xD),
+    (Key::A, 0x7),
+    (Key::S, 0x8),
+    (Key::D, 0x9),
+    (Key::F, 0xE),
+    (Key::Z, 0xA),
+    (Key::X, 0x0),
+    (Key::C, 0xB),
+    (Key::V, 0xF),
+];
+
+fn callbacks() -> Callbacks {
+    *CALLBACKS.lock().unwrap()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: b"Chip-8\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8|c8\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: WIDTH as c_uint,
+            base_height: HEIGHT as c_uint,
+            max_width: WIDTH as c_uint,
+            max_height: HEIGHT as c_uint,
+            aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+        },
+        timing: SystemTiming {
+            fps: 60.0,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_environment(environment: EnvironmentFn) {
+    CALLBACKS.lock().unwrap().environment = Some(environment);
+
+    let mut variables: Vec<Variable> = VARIABLES
+        .iter()
+        .map(|(key, value)| Variable {
+            key: key.as_ptr() as *const c_char,
+            value: value.as_ptr() as *const c_char,
+        })
+        .collect();
+    variables.push(Variable {
+        key: std::ptr::null(),
+        value: std::ptr::null(),
+    });
+    environment(
+        ENVIRONMENT_SET_VARIABLES,
+        variables.as_mut_ptr() as *mut c_void,
+    );
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(video_refresh);
+}
+
+// Audio is pushed in batches, see retro_set_audio_sample_batch
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(audio_sample_batch);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(input_poll);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(input_state);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+// Only the joypad (and the keyboard, always read) is supported
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+    let rom = std::slice::from_raw_parts((*game).data as *const u8, (*game).size).to_vec();
+    let chip8 = match load_rom(&rom) {
+        Some(chip8) => chip8,
+        None => return false,
+    };
+
+    let environment = match callbacks().environment {
+        Some(environment) => environment,
+        None => return false,
+    };
+    let mut pixel_format = PixelFormat::ARGB8888;
+    if !environment(
+        ENVIRONMENT_SET_PIXEL_FORMAT,
+        &mut pixel_format as *mut PixelFormat as *mut c_void,
+    ) {
+        return false;
+    }
+
+    let mut core = Core {
+        chip8,
+        rom,
+        phosphor: Phosphor::new(PersistenceMode::Off),
+        tone: Tone::new(WAVE_LENGTH),
+        palette: palette::presets().remove(0),
+        layout: LAYOUT_NUMPAD,
+        framebuffer: vec![0; WIDTH as usize * HEIGHT as usize],
+        cheats: vec![],
+    };
+    chip8::seed_rng(&mut core.chip8, time_seed());
+    apply_variables(&mut core, environment);
+
+    *CORE.lock().unwrap() = Some(core);
+    true
+}
+
+// 4KB of memory, or 64KB for bigger XO-CHIP programs
+fn load_rom(rom: &[u8]) -> Option<Chip8> {
+    Chip8::load(rom, LOAD_ADDRESS, MEMORY_SIZE)
+        .or_else(|_| Chip8::load(rom, LOAD_ADDRESS, XO_CHIP_MEMORY_SIZE))
+        .ok()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const GameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    Region::NTSC.to_uint()
+}
+
+// Back to the state right after loading, keeping the core options
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        let (quirks, cycles_per_frame) = (core.chip8.quirks, core.chip8.cycles_per_frame);
+        // The ROM was loaded once already, it still fits in memory of the same size. The
+        // frontend keeps a pointer to it, see retro_get_memory_data.
+        let mut memory = std::mem::take(&mut core.chip8.memory);
+        core.chip8 = load_rom(&core.rom).unwrap();
+        memory.copy_from_slice(&core.chip8.memory);
+        core.chip8.memory = memory;
+        core.chip8.quirks = quirks;
+        core.chip8.cycles_per_frame = cycles_per_frame;
+        chip8::seed_rng(&mut core.chip8, time_seed());
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_run() {
+    let callbacks = callbacks();
+    let mut core_lock = CORE.lock().unwrap();
+    let core = match core_lock.as_mut() {
+        Some(core) => core,
+        None => return,
+    };
+
+    if let Some(environment) = callbacks.environment {
+        let mut updated = false;
+        if environment(
+            ENVIRONMENT_GET_VARIABLE_UPDATE,
+            &mut updated as *mut bool as *mut c_void,
+        ) && updated
+        {
+            apply_variables(core, environment);
+        }
+    }
+
+    if let (Some(input_poll), Some(input_state)) = (callbacks.input_poll, callbacks.input_state) {
+        input_poll();
+        update_keys(core, input_state);
+    }
+
+    cheat::apply(&core.cheats, &mut core.chip8);
+    chip8::run_frame(&mut core.chip8);
+    core.phosphor.update(&core.chip8.display);
+
+    if let Some(video_refresh) = callbacks.video_refresh {
+        for (pixel, intensity) in core
+            .framebuffer
+            .iter_mut()
+            .zip(core.phosphor.intensity().iter())
+        {
+            let [r, g, b] = palette::blend(&core.palette, *intensity);
+            *pixel = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        }
+        video_refresh(
+            core.framebuffer.as_ptr() as *const c_void,
+            WIDTH as c_uint,
+            HEIGHT as c_uint,
+            WIDTH as usize * 4,
+        );
+    }
+
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        let samples = core.tone.next_frame(chip8::is_buzzer_on(&core.chip8));
+        let mut stereo = [0i16; SAMPLES_PER_FRAME * 2];
+        for (frame, sample) in stereo.chunks_mut(2).zip(samples.iter()) {
+            let sample = (sample * i16::MAX as f32) as i16;
+            frame[0] = sample;
+            frame[1] = sample;
+        }
+        audio_sample_batch(stereo.as_ptr(), SAMPLES_PER_FRAME);
+    }
+}
+
+// The same for the whole session, the memory size never changes
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    match CORE.lock().unwrap().as_ref() {
+        Some(core) => state::state_size(&core.chip8),
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let core_lock = CORE.lock().unwrap();
+    match core_lock.as_ref() {
+        Some(core) if size >= state::state_size(&core.chip8) => {
+            let state = state::save_state(&core.chip8);
+            std::ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+            true
+        }
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let mut core_lock = CORE.lock().unwrap();
+    match core_lock.as_mut() {
+        // Memory of another size would be a new buffer, see retro_get_memory_data
+        Some(core) if size == state::state_size(&core.chip8) => {
+            let state = std::slice::from_raw_parts(data as *const u8, size);
+            state::load_state(&mut core.chip8, state).is_ok()
+        }
+        Some(_core) => false,
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.cheats.clear();
+    }
+}
+
+// Codes in the format of cheat.rs, e.g. 0x3A0 = 3 or 0x3A1 == 0 ? 0x3A2 = 5, several
+// separated by +. Codes that don't parse are left disabled.
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_set(index: c_uint, enabled: bool, code: *const c_char) {
+    let mut core_lock = CORE.lock().unwrap();
+    let core = match core_lock.as_mut() {
+        Some(core) if !code.is_null() => core,
+        _ => return,
+    };
+
+    let code = CStr::from_ptr(code).to_string_lossy();
+    let codes = cheat::parse_codes(&code);
+    let index = index as usize;
+    while core.cheats.len() <= index {
+        core.cheats.push(Cheat {
+            name: format!("Cheat {}", core.cheats.len()),
+            enabled: false,
+            codes: vec![],
+        });
+    }
+    core.cheats[index].enabled = enabled && codes.is_ok();
+    core.cheats[index].codes = codes.unwrap_or_default();
+}
+
+// The 4KB of RAM, for the frontend's memory viewer and achievements. Frontends keep the
+// pointer for the session: memory is only written in place, and its size never changes.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match CORE.lock().unwrap().as_mut() {
+        Some(core) if id == MEMORY_SYSTEM_RAM => core.chip8.memory.as_mut_ptr() as *mut c_void,
+        _ => std::ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    match CORE.lock().unwrap().as_ref() {
+        Some(core) if id == MEMORY_SYSTEM_RAM => core.chip8.memory.len(),
+        _ => 0,
+    }
+}
+
+unsafe fn apply_variables(core: &mut Core, environment: EnvironmentFn) {
+    if let Some(quirks) = get_variable(environment, b"chip8_quirks\0")
+        .and_then(|profile| Quirks::from_profile(&profile))
+    {
+        core.chip8.quirks = quirks;
+    }
+
+    if let Some(speed) =
+        get_variable(environment, b"chip8_speed\0").and_then(|speed| speed.parse().ok())
+    {
+        core.chip8.cycles_per_frame = speed;
+    }
+
+    if let Some(name) = get_variable(environment, b"chip8_palette\0") {
+        if let Some(palette) = palette::presets()
+            .into_iter()
+            .find(|palette| palette.name == name)
+        {
+            core.palette = palette;
+        }
+    }
+
+    let persistence = match get_variable(environment, b"chip8_persistence\0").as_deref() {
+        Some("fade") => PersistenceMode::Fade(FADE_FRAMES),
+        Some("or") => PersistenceMode::OrLastTwo,
+        _ => PersistenceMode::Off,
+    };
+    core.phosphor = Phosphor::new(persistence);
+
+    core.layout = match get_variable(environment, b"chip8_layout\0").as_deref() {
+        Some("wasd") => LAYOUT_WASD,
+        _ => LAYOUT_NUMPAD,
+    };
+}
+
+unsafe fn get_variable(environment: EnvironmentFn, key: &[u8]) -> Option<String> {
+    let mut variable = Variable {
+        key: key.as_ptr() as *const c_char,
+        value: std::ptr::null(),
+    };
+    if !environment(
+        ENVIRONMENT_GET_VARIABLE,
+        &mut variable as *mut Variable as *mut c_void,
+    ) || variable.value.is_null()
+    {
+        return None;
+    }
+
+    Some(
+        CStr::from_ptr(variable.value)
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+// A Chip-8 key is down while any joypad button or keyboard key mapped to it is held
+unsafe fn update_keys(core: &mut Core, input_state: InputStateFn) {
+    let mut pressed = [false; 16];
+
+    for (button, key_index) in core.layout.iter().enumerate() {
+        if input_state(0, DEVICE_JOYPAD, 0, button as c_uint) != 0 {
+            pressed[*key_index as usize] = true;
+        }
+    }
+    for (key, key_index) in KEYBOARD.iter() {
+        if input_state(0, DEVICE_KEYBOARD, 0, key.to_uint()) != 0 {
+            pressed[*key_index as usize] = true;
+        }
+    }
+
+    for (key_index, is_pressed) in pressed.iter().enumerate() {
+        if *is_pressed && !core.chip8.keys[key_index] {
+            chip8::key_pressed(&mut core.chip8, key_index as u8);
+        } else if !*is_pressed && core.chip8.keys[key_index] {
+            chip8::key_released(&mut core.chip8, key_index as u8);
+        }
+    }
+}
+
+// No host randomness is needed: the RND state is part of save states, so netplay
+// and rewind stay in sync no matter where the seed came from
+fn time_seed() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+static LAST_FRAME: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+#[cfg(test)]
+unsafe extern "C" fn test_environment(cmd: c_uint, _data: *mut c_void) -> bool {
+    cmd == ENVIRONMENT_SET_PIXEL_FORMAT || cmd == ENVIRONMENT_SET_VARIABLES
+}
+
+#[cfg(test)]
+unsafe extern "C" fn test_video_refresh(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    _pitch: usize,
+) {
+    let pixels = std::slice::from_raw_parts(data as *const u32, (width * height) as usize);
+    *LAST_FRAME.lock().unwrap() = pixels.to_vec();
+}
+
+#[cfg(test)]
+fn last_frame_to_text() -> String {
+    LAST_FRAME
+        .lock()
+        .unwrap()
+        .chunks(WIDTH as usize)
+        .map(|row| {
+            let line: String = row
+                .iter()
+                .map(|px| if *px == 0xFFFFFF { '#' } else { '.' })
+                .collect();
+            line + "\n"
+        })
+        .collect()
+}
+
+#[test]
+fn test_run_and_serialize() {
+    let rom: &[u8] = include_bytes!("../../tests/roms/clock.ch8");
+    let expected = include_str!("../../tests/framebuffers/clock.txt");
+
+    unsafe {
+        retro_set_environment(test_environment);
+        retro_set_video_refresh(test_video_refresh);
+        let game = GameInfo {
+            path: std::ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: std::ptr::null(),
+        };
+        assert!(retro_load_game(&game));
+
+        let mut state = vec![0u8; retro_serialize_size()];
+        assert!(retro_serialize(
+            state.as_mut_ptr() as *mut c_void,
+            state.len()
+        ));
+
+        // Same picture as the other frontends, white on black
+        for _ in 0..200 {
+            retro_run();
+        }
+        assert_eq!(last_frame_to_text(), expected);
+
+        // Back to the start, then the same 200 frames again
+        let memory = retro_get_memory_data(MEMORY_SYSTEM_RAM);
+        assert!(retro_unserialize(
+            state.as_ptr() as *const c_void,
+            state.len()
+        ));
+        for _ in 0..200 {
+            retro_run();
+        }
+        assert_eq!(last_frame_to_text(), expected);
+
+        // The frontend's pointer to memory stays valid, and states of another size are refused
+        retro_reset();
+        assert_eq!(retro_get_memory_data(MEMORY_SYSTEM_RAM), memory);
+        assert_eq!(retro_serialize_size(), state.len());
+        assert!(!retro_unserialize(
+            state.as_ptr() as *const c_void,
+            state.len() - 1
+        ));
+
+        retro_unload_game();
+    }
+}
@@ -1,14 +1,62 @@
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 
 const DEBUG: bool = false;
 
+// `INCLUDE other.cp8asm` inserts the lines of another source file, relative to the
+// directory of the file that includes it
+const INCLUDE_DIRECTIVE: &str = "INCLUDE ";
+
 struct AsmLine {
     line: String,
     opcode: Option<u16>,
     memory_position: u16,
     state: AsmLineState,
+    source: SourceLine,
+}
+
+// Where a line comes from, for error messages
+#[derive(Clone)]
+struct SourceLine {
+    filename: String,
+    // 1-based
+    line_number: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AssembleError {
+    Io {
+        filename: String,
+        message: String,
+    },
+    InvalidLine {
+        filename: String,
+        line_number: usize,
+        line: String,
+    },
+    RecursiveInclude(String),
+}
+
+impl std::fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AssembleError::Io { filename, message } => write!(f, "{} : {}", filename, message),
+            AssembleError::InvalidLine {
+                filename,
+                line_number,
+                line,
+            } => write!(
+                f,
+                "{}:{} : invalid line: {}",
+                filename,
+                line_number,
+                line.trim()
+            ),
+            AssembleError::RecursiveInclude(filename) => {
+                write!(f, "{} includes itself", filename)
+            }
+        }
+    }
 }
 
 #[derive(PartialEq)]
@@ -21,6 +69,7 @@ enum AsmLineState {
 enum OpcodeError {
     NoOpcode,
     Incomplete,
+    Invalid,
 }
 
 #[derive(PartialEq)]
@@ -28,61 +77,127 @@ enum AddressError {
     UnknownLabel,
 }
 
-#[allow(dead_code)]
-pub fn assemble(filename: &str) -> Vec<u8> {
-    let mut instructions: Vec<u8> = vec![];
+pub fn assemble(filename: &str) -> Result<Vec<u8>, AssembleError> {
+    assemble_with_sources(filename).map(|(instructions, _sources)| instructions)
+}
+
+// Also returns the source files read: filename first, then the included ones
+pub fn assemble_with_sources(filename: &str) -> Result<(Vec<u8>, Vec<String>), AssembleError> {
+    let mut lines: Vec<(SourceLine, String)> = vec![];
+    let mut sources: Vec<String> = vec![];
+    read_source(Path::new(filename), &mut vec![], &mut sources, &mut lines)?;
+    let instructions = assemble_lines(lines)?;
+    Ok((instructions, sources))
+}
+
+// Source text that isn't in a file, so without INCLUDE directives. Errors name the file
+// as filename.
+pub fn assemble_text(text: &str, filename: &str) -> Result<Vec<u8>, AssembleError> {
+    let lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let source = SourceLine {
+                filename: String::from(filename),
+                line_number: i + 1,
+            };
+            (source, String::from(line))
+        })
+        .collect();
+    assemble_lines(lines)
+}
 
-    let file = File::open(filename).unwrap();
-    let reader = BufReader::new(file);
+fn assemble_lines(lines: Vec<(SourceLine, String)>) -> Result<Vec<u8>, AssembleError> {
+    let mut instructions: Vec<u8> = vec![];
     let mut labels: HashMap<String, u16> = HashMap::new();
 
     let mut asm_lines: Vec<AsmLine> = vec![];
-    let mut memory_position = 0x200;
-
-    for (_index, line) in reader.lines().enumerate() {
-        if let Ok(line) = line {
-            match parse_asm_line(&line, &mut labels, memory_position) {
-                Ok(opcode) => {
-                    asm_lines.push(AsmLine {
-                        line,
-                        opcode: Some(opcode),
-                        memory_position,
-                        state: AsmLineState::Complete,
-                    });
-                    memory_position += 2;
-                }
-                Err(err) => match err {
-                    OpcodeError::NoOpcode => (),
-                    OpcodeError::Incomplete => {
-                        asm_lines.push(AsmLine {
-                            line,
-                            opcode: None,
-                            memory_position,
-                            state: AsmLineState::Incomplete,
-                        });
-                        memory_position += 2;
-                    }
-                },
-            }
-        }
+    let mut memory_position: u16 = 0x200;
+
+    for (source, line) in lines {
+        let (opcode, state) = match parse_asm_line(&line, &mut labels, memory_position) {
+            Ok(opcode) => (Some(opcode), AsmLineState::Complete),
+            Err(OpcodeError::NoOpcode) => continue,
+            Err(OpcodeError::Incomplete) => (None, AsmLineState::Incomplete),
+            Err(OpcodeError::Invalid) => return Err(invalid_line(&source, &line)),
+        };
+        asm_lines.push(AsmLine {
+            line,
+            opcode,
+            memory_position,
+            state,
+            source,
+        });
+        memory_position = memory_position.wrapping_add(2);
     }
 
     for mut asm_line in asm_lines {
         if asm_line.state == AsmLineState::Incomplete {
-            if let Ok(opcode) =
-                parse_asm_line(&asm_line.line, &mut labels, asm_line.memory_position)
-            {
-                asm_line.opcode = Some(opcode);
-            } else {
-                panic!("Invalid asm line: {}", asm_line.line);
+            match parse_asm_line(&asm_line.line, &mut labels, asm_line.memory_position) {
+                Ok(opcode) => asm_line.opcode = Some(opcode),
+                Err(_) => return Err(invalid_line(&asm_line.source, &asm_line.line)),
             }
         }
 
-        instructions.push(((asm_line.opcode.unwrap() & 0xFF00) >> 8) as u8);
-        instructions.push((asm_line.opcode.unwrap() & 0x00FF) as u8);
+        let opcode = asm_line.opcode.unwrap_or_default();
+        instructions.push(((opcode & 0xFF00) >> 8) as u8);
+        instructions.push((opcode & 0x00FF) as u8);
     }
 
-    instructions
+    Ok(instructions)
+}
+
+fn invalid_line(source: &SourceLine, line: &str) -> AssembleError {
+    AssembleError::InvalidLine {
+        filename: source.filename.clone(),
+        line_number: source.line_number,
+        line: String::from(line),
+    }
+}
+
+// Append the lines of path to lines, replacing INCLUDE directives by the included lines.
+// include_stack holds the files being read, to catch files that include themselves.
+fn read_source(
+    path: &Path,
+    include_stack: &mut Vec<PathBuf>,
+    sources: &mut Vec<String>,
+    lines: &mut Vec<(SourceLine, String)>,
+) -> Result<(), AssembleError> {
+    let filename = path.to_string_lossy().into_owned();
+    let text = std::fs::read_to_string(path).map_err(|err| AssembleError::Io {
+        filename: filename.clone(),
+        message: err.to_string(),
+    })?;
+
+    let canonical_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if include_stack.contains(&canonical_path) {
+        return Err(AssembleError::RecursiveInclude(filename));
+    }
+    include_stack.push(canonical_path);
+    if !sources.contains(&filename) {
+        sources.push(filename.clone());
+    }
+
+    for (i, line) in text.lines().enumerate() {
+        let source = SourceLine {
+            filename: filename.clone(),
+            line_number: i + 1,
+        };
+        let code = line.split(';').next().unwrap_or_default().trim();
+        match code.strip_prefix(INCLUDE_DIRECTIVE) {
+            Some(included) => {
+                let included_path = path
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(included.trim());
+                read_source(&included_path, include_stack, sources, lines)?;
+            }
+            None => lines.push((source, String::from(line))),
+        }
+    }
+
+    include_stack.pop();
+    Ok(())
 }
 
 #[allow(dead_code)]
@@ -102,26 +217,25 @@ fn parse_asm_line(
         return Err(OpcodeError::NoOpcode);
     }
 
-    let parts: Vec<&str> = line
-        .trim_start_matches(" ")
-        .trim_end_matches(" ")
-        .split(" ")
-        .collect();
+    // Tabs and CRLF line endings too
+    let parts: Vec<&str> = line.trim().split(" ").collect();
+    // Missing operands are empty, and make the line invalid
+    let part = |i: usize| parts.get(i).copied().unwrap_or_default();
 
-    let command = parts[0];
+    let command = part(0);
 
-    let x = if parts.len() >= 2 && parts[1].starts_with('V') {
-        parts[1].trim_start_matches("V").trim_end_matches(",")
+    let x = if parts.len() >= 2 && part(1).starts_with('V') {
+        part(1).trim_start_matches("V").trim_end_matches(",")
     } else {
         ""
     };
-    let y = if parts.len() >= 3 && parts[2].starts_with('V') {
-        parts[2].trim_start_matches("V").trim_end_matches(",")
+    let y = if parts.len() >= 3 && part(2).starts_with('V') {
+        part(2).trim_start_matches("V").trim_end_matches(",")
     } else {
         ""
     };
     let kk = if parts.len() >= 3 && y.is_empty() {
-        match get_hex_str(parts[2]) {
+        match get_hex_str(part(2)) {
             Some(n) => format!("{:0>2}", n),
             None => String::default(),
         }
@@ -129,7 +243,7 @@ fn parse_asm_line(
         String::default()
     };
     let nnn = if x.is_empty() && y.is_empty() && parts.len() == 2 {
-        match get_address(parts[1], &labels) {
+        match get_address(part(1), labels) {
             Ok(v) => v,
             Err(_e) => return Err(OpcodeError::Incomplete),
         }
@@ -137,17 +251,14 @@ fn parse_asm_line(
         String::default()
     };
     let n = if parts.len() >= 4 {
-        match get_hex_str(parts[3]) {
-            Some(n) => format!("{}", n),
-            None => String::default(),
-        }
+        get_hex_str(part(3)).unwrap_or_default()
     } else {
         String::default()
     };
 
     // Label
-    if command.ends_with(":") {
-        let label_name = String::from(&command[..(command.len() - 1)]);
+    if let Some(label_name) = command.strip_suffix(':') {
+        let label_name = String::from(label_name);
         if DEBUG {
             println!("Label {} : {:#x}", label_name, memory_index);
         }
@@ -168,7 +279,7 @@ fn parse_asm_line(
             if parts.len() == 2 {
                 Some(format!("1{}", nnn))
             } else {
-                let addr = match get_address(parts[2], &labels) {
+                let addr = match get_address(part(2), labels) {
                     Ok(v) => v,
                     Err(_e) => return Err(OpcodeError::Incomplete),
                 };
@@ -215,41 +326,41 @@ fn parse_asm_line(
             if !x.is_empty() && !y.is_empty() {
                 Some(format!("8{}{}0", x, y))
             // I, Vx
-            } else if parts[1] == "I," && !y.is_empty() {
+            } else if part(1) == "I," && !y.is_empty() {
                 Some(format!("F{}55", y))
             // I, addr
-            } else if parts[1] == "I," {
-                let addr = match get_address(parts[2], &labels) {
+            } else if part(1) == "I," {
+                let addr = match get_address(part(2), labels) {
                     Ok(v) => v,
                     Err(_e) => return Err(OpcodeError::Incomplete),
                 };
                 Some(format!("A{}", addr))
             // Vx, DT
-            } else if !x.is_empty() && parts[2] == "DT" {
+            } else if !x.is_empty() && part(2) == "DT" {
                 Some(format!("F{}07", x))
             // Vx, K
-            } else if !x.is_empty() && parts[2] == "K" {
+            } else if !x.is_empty() && part(2) == "K" {
                 Some(format!("F{}0A", x))
             // DT, Vx
-            } else if parts[1] == "DT," && !y.is_empty() {
+            } else if part(1) == "DT," && !y.is_empty() {
                 Some(format!("F{}15", y))
             // ST, Vx
-            } else if parts[1] == "ST," && !y.is_empty() {
+            } else if part(1) == "ST," && !y.is_empty() {
                 Some(format!("F{}18", y))
             // F, Vx
-            } else if parts[1] == "F," && !y.is_empty() {
+            } else if part(1) == "F," && !y.is_empty() {
                 Some(format!("F{}29", y))
             // B, Vx
-            } else if parts[1] == "B," && !y.is_empty() {
+            } else if part(1) == "B," && !y.is_empty() {
                 Some(format!("F{}33", y))
             // Vx, I
-            } else if !x.is_empty() && parts[2] == "I" {
+            } else if !x.is_empty() && part(2) == "I" {
                 Some(format!("F{}65", x))
             // Vx, byte
             } else if !x.is_empty() && !kk.is_empty() {
                 Some(format!("6{}{}", x, kk))
             } else {
-                panic!("Unknown line: {}", line);
+                return Err(OpcodeError::Invalid);
             }
         }
         // 7xkk - ADD Vx, byte
@@ -302,7 +413,9 @@ fn parse_asm_line(
         "SKP" => Some(format!("E{}9E", x)),
         // ExA1 - SKNP Vx
         "SKNP" => Some(format!("E{}A1", x)),
-        _ => None,
+        // Blank line
+        "" => None,
+        _ => return Err(OpcodeError::Invalid),
     };
 
     if let Some(opcode_str) = opcode_str {
@@ -312,17 +425,19 @@ fn parse_asm_line(
             }
             Ok(opcode)
         } else {
-            println!("{}", line);
-            println!("x: {}", x);
-            println!("y: {}", y);
-            println!("n: {}", n);
-            println!("kk: {}", kk);
-            println!("nnn: {}", nnn);
-            println!("Wrong opcode format : {}", opcode_str);
-            panic!();
+            if DEBUG {
+                println!("{}", line);
+                println!("x: {}", x);
+                println!("y: {}", y);
+                println!("n: {}", n);
+                println!("kk: {}", kk);
+                println!("nnn: {}", nnn);
+                println!("Wrong opcode format : {}", opcode_str);
+            }
+            Err(OpcodeError::Invalid)
         }
     } else {
-        return Err(OpcodeError::NoOpcode);
+        Err(OpcodeError::NoOpcode)
     }
 }
 
@@ -343,7 +458,7 @@ fn get_hex_str(text: &str) -> Option<String> {
     if text.starts_with("0x") {
         Some(String::from(text.trim_start_matches("0x")))
     } else {
-        if let Ok(n) = u16::from_str_radix(text, 10) {
+        if let Ok(n) = text.parse::<u16>() {
             Some(String::from(format!("{:#x}", n).trim_start_matches("0x")))
         } else {
             None
@@ -352,10 +467,10 @@ fn get_hex_str(text: &str) -> Option<String> {
 }
 
 #[test]
+#[rustfmt::skip]
 fn test_parse_asm_line() {
     let mut labels: HashMap<String, u16> = HashMap::new();
     // Default tests
-    #[cfg_attr(rustfmt, rustfmt_skip)]
     {
         assert_eq!(parse_asm_line(&String::from("SYS 0xFE9"), &mut labels, 0x200).ok(), Some(0x0FE9));
         assert_eq!(parse_asm_line(&String::from("CLS"), &mut labels, 0x200).ok(), Some(0x00E0));
@@ -402,3 +517,44 @@ fn test_parse_asm_line() {
         assert_eq!(parse_asm_line(&String::from("some_label:"), &mut labels, 0x200).ok(), None);
     }
 }
+
+#[test]
+fn test_assemble() {
+    assert_eq!(
+        assemble("assembly_programs/clock.cp8asm").unwrap(),
+        include_bytes!("../tests/roms/clock.ch8")
+    );
+
+    let (instructions, sources) = assemble_with_sources("tests/asm/include.cp8asm").unwrap();
+    assert_eq!(
+        instructions,
+        [0x60, 0x07, 0x22, 0x06, 0x12, 0x04, 0xF0, 0x29, 0xD1, 0x25, 0x00, 0xEE]
+    );
+    assert_eq!(
+        sources,
+        ["tests/asm/include.cp8asm", "tests/asm/include_draw.cp8asm"]
+    );
+
+    assert_eq!(
+        assemble("tests/asm/invalid.cp8asm"),
+        Err(AssembleError::InvalidLine {
+            filename: String::from("tests/asm/invalid.cp8asm"),
+            line_number: 2,
+            line: String::from("LDX V0, 0x1")
+        })
+    );
+    assert_eq!(
+        assemble_text("LD V0, 0x7\nINCLUDE include_draw.cp8asm", "text"),
+        Err(AssembleError::InvalidLine {
+            filename: String::from("text"),
+            line_number: 2,
+            line: String::from("INCLUDE include_draw.cp8asm")
+        })
+    );
+    assert_eq!(
+        assemble("tests/asm/recursive.cp8asm"),
+        Err(AssembleError::RecursiveInclude(String::from(
+            "tests/asm/recursive.cp8asm"
+        )))
+    );
+}
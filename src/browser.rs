@@ -0,0 +1,265 @@
+use crate::database::{Database, RomInfo};
+use crate::rom;
+use chip_8::palette::Palette;
+use nannou::prelude::*;
+use std::path::{Path, PathBuf};
+
+// In-window menu listing the programs of a directory, with their title and description
+// from the ROM database. A PNG with the same name as a ROM (pong.ch8 => pong.png), e.g.
+// from a headless run with --screenshot, is shown as its thumbnail.
+
+// Program files, in the formats rom.rs or the assembler reads. Not .txt, that would list
+// every README.
+const EXTENSIONS: [&str; 6] = ["ch8", "c8", "cp8asm", "gif", "hex", "zip"];
+
+// Looked up in the database while scanning, by their bytes as they are. The other
+// formats are decoded when selected: cartridges run octo-cli, archives are decompressed.
+const PLAIN_EXTENSIONS: [&str; 2] = ["ch8", "c8"];
+
+// Lines of the list shown at once, the list scrolls to keep the selection in view
+const VISIBLE_ENTRIES: usize = 12;
+
+pub struct Entry {
+    pub filepath: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub thumbnail_path: Option<PathBuf>,
+    // Whether the title and description were looked up
+    described: bool,
+}
+
+pub struct Browser {
+    pub directory: String,
+    pub entries: Vec<Entry>,
+    selected: usize,
+    database: Database,
+}
+
+impl Browser {
+    pub fn scan(directory: &str, database: Database) -> Result<Browser, String> {
+        let read_dir = std::fs::read_dir(directory)
+            .map_err(|err| format!("Error reading directory {} : {}", directory, err))?;
+
+        let mut filepaths: Vec<PathBuf> = read_dir
+            .filter_map(|dir_entry| dir_entry.ok().map(|dir_entry| dir_entry.path()))
+            .filter(|path| {
+                let extension = path
+                    .extension()
+                    .map(|extension| extension.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                path.is_file() && EXTENSIONS.contains(&extension.as_str())
+            })
+            .collect();
+        filepaths.sort();
+
+        let mut browser = Browser {
+            directory: String::from(directory),
+            entries: filepaths
+                .iter()
+                .map(|filepath| create_entry(filepath, &database))
+                .collect(),
+            selected: 0,
+            database,
+        };
+        browser.select(0);
+        Ok(browser)
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.selected = index;
+        let database = &self.database;
+        if let Some(entry) = self.entries.get_mut(index) {
+            if !entry.described {
+                let rom_info = rom::load(&entry.filepath)
+                    .ok()
+                    .and_then(|rom| database.lookup(&rom.bytes));
+                describe(entry, rom_info);
+            }
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.entries.is_empty() {
+            self.select((self.selected + self.entries.len() - 1) % self.entries.len());
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.entries.is_empty() {
+            self.select((self.selected + 1) % self.entries.len());
+        }
+    }
+
+    pub fn selected_entry(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+}
+
+fn create_entry(filepath: &Path, database: &Database) -> Entry {
+    let extension = filepath
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let file_stem = filepath
+        .file_stem()
+        .map(|file_stem| file_stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let thumbnail_path = filepath.with_extension("png");
+
+    let mut entry = Entry {
+        filepath: filepath.to_string_lossy().into_owned(),
+        title: file_stem,
+        description: None,
+        thumbnail_path: Some(thumbnail_path).filter(|path| path.is_file()),
+        // Source files are only assembled when opened
+        described: extension == "cp8asm",
+    };
+    if PLAIN_EXTENSIONS.contains(&extension.as_str()) {
+        let rom_info = std::fs::read(filepath)
+            .ok()
+            .and_then(|bytes| database.lookup(&bytes));
+        describe(&mut entry, rom_info);
+    }
+    entry
+}
+
+// The title from the database, or the file name
+fn describe(entry: &mut Entry, rom_info: Option<RomInfo>) {
+    entry.described = true;
+    if let Some(rom_info) = rom_info {
+        entry.title = rom_info.title;
+        entry.description = rom_info.description;
+    }
+}
+
+// The list on the left, the selected program's thumbnail and description on the right
+pub fn draw_menu(
+    draw: &Draw,
+    rect: Rect,
+    browser: &Browser,
+    thumbnail: Option<&wgpu::Texture>,
+    palette: &Palette,
+) {
+    let [r, g, b] = palette.background;
+    draw.rect().xy(rect.xy()).wh(rect.wh()).color(rgb8(r, g, b));
+    let [r, g, b] = palette.foreground;
+    let foreground = rgb8(r, g, b);
+
+    let margin = rect.h() / 20.0;
+    let font_size = (rect.h() / 24.0) as u32;
+    let line_height = rect.h() / 16.0;
+    let list_rect = Rect::from_x_y_w_h(
+        rect.left() + rect.w() / 4.0,
+        rect.y(),
+        rect.w() / 2.0 - margin * 2.0,
+        rect.h() - margin * 2.0,
+    );
+
+    draw.text(&format!("{}  (Up/Down, Enter)", browser.directory))
+        .x_y(list_rect.x(), list_rect.top() - line_height / 2.0)
+        .w_h(list_rect.w(), line_height)
+        .font_size(font_size)
+        .left_justify()
+        .no_line_wrap()
+        .color(foreground);
+
+    if browser.entries.is_empty() {
+        draw.text("No programs in this directory")
+            .x_y(list_rect.x(), list_rect.top() - line_height * 2.5)
+            .w_h(list_rect.w(), line_height)
+            .font_size(font_size)
+            .left_justify()
+            .color(foreground);
+        return;
+    }
+
+    let first = browser
+        .selected
+        .saturating_sub(VISIBLE_ENTRIES / 2)
+        .min(browser.entries.len().saturating_sub(VISIBLE_ENTRIES));
+    for (row, (i, entry)) in browser
+        .entries
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(VISIBLE_ENTRIES)
+        .enumerate()
+    {
+        let y = list_rect.top() - line_height * (row as f32 + 2.5);
+        let color = if i == browser.selected {
+            // Selected entry in reverse video
+            draw.rect()
+                .x_y(list_rect.x(), y)
+                .w_h(list_rect.w(), line_height)
+                .color(foreground);
+            let [r, g, b] = palette.background;
+            rgb8(r, g, b)
+        } else {
+            foreground
+        };
+        draw.text(&entry.title)
+            .x_y(list_rect.x(), y)
+            .w_h(list_rect.w() - margin, line_height)
+            .font_size(font_size)
+            .left_justify()
+            .no_line_wrap()
+            .color(color);
+    }
+
+    let entry = &browser.entries[browser.selected];
+    let details_x = rect.x() + rect.w() / 4.0;
+    let details_w = rect.w() / 2.0 - margin * 2.0;
+    // Same aspect ratio as the display
+    let thumbnail_h = details_w / 2.0;
+    let thumbnail_y = rect.top() - margin - thumbnail_h / 2.0;
+    match thumbnail {
+        Some(thumbnail) => {
+            draw.texture(thumbnail)
+                .x_y(details_x, thumbnail_y)
+                .w_h(details_w, thumbnail_h);
+        }
+        None => {
+            draw.rect()
+                .x_y(details_x, thumbnail_y)
+                .w_h(details_w, thumbnail_h)
+                .no_fill()
+                .stroke(foreground)
+                .stroke_weight(1.0);
+        }
+    }
+
+    let description = entry.description.as_deref().unwrap_or("No description");
+    let description_h = rect.h() - margin * 3.0 - thumbnail_h;
+    draw.text(description)
+        .x_y(details_x, rect.bottom() + margin + description_h / 2.0)
+        .w_h(details_w, description_h)
+        .font_size(font_size)
+        .left_justify()
+        .align_text_top()
+        .color(foreground);
+}
+
+#[test]
+fn test_scan() {
+    let mut browser = Browser::scan("tests/roms", Database::bundled()).unwrap();
+    let titles: Vec<&str> = browser
+        .entries
+        .iter()
+        .map(|entry| entry.title.as_str())
+        .collect();
+    assert!(titles.contains(&"Clock"));
+    // Not in the database, named after the file
+    assert!(titles.contains(&"random"));
+
+    // Cartridges are only decoded once selected
+    let index = browser
+        .entries
+        .iter()
+        .position(|entry| entry.filepath.ends_with("octo-cart.gif"))
+        .unwrap();
+    assert!(!browser.entries[index].described);
+    browser.select(index);
+    assert!(browser.entries[index].described);
+
+    assert!(Browser::scan("tests/missing", Database::bundled()).is_err());
+}
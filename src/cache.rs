@@ -0,0 +1,100 @@
+use crate::chip8::{self, Chip8, Instruction};
+
+// Instructions decoded once per address instead of at every fetch, for running programs
+// far faster than real time (headless runs, tests, fuzzing, benchmarks). A frame runs
+// the same instructions as chip8::run_frame, with the same results.
+//
+// The cache only sees the program's own writes: Fx33 and Fx55 drop the instructions
+// overlapping the bytes they wrote, so self-modifying code runs its new instructions.
+// After writing memory from outside the cpu (cheats, scripts, chip8::patch_program,
+// state::load_state), call forget for each byte written, or invalidate. A new memory size
+// resets the cache on its own.
+pub struct DecodeCache {
+    // One entry per address, instructions can start on odd ones
+    instructions: Vec<Option<Instruction>>,
+}
+
+impl Default for DecodeCache {
+    fn default() -> DecodeCache {
+        DecodeCache::new()
+    }
+}
+
+impl DecodeCache {
+    pub fn new() -> DecodeCache {
+        DecodeCache {
+            instructions: vec![],
+        }
+    }
+
+    pub fn invalidate(&mut self) {
+        self.instructions.clear();
+    }
+
+    fn fetch(&mut self, chip8: &Chip8, pc: usize) -> Instruction {
+        if self.instructions.len() != chip8.memory.len() {
+            self.instructions = vec![None; chip8.memory.len()];
+        }
+        *self.instructions[pc].get_or_insert_with(|| {
+            chip8::decode(u16::from_be_bytes([chip8.memory[pc], chip8.memory[pc + 1]]))
+        })
+    }
+
+    // The byte at address changed: drop the instructions starting there and just before
+    pub fn forget(&mut self, address: usize) {
+        let len = self.instructions.len();
+        if address >= len {
+            return;
+        }
+        self.instructions[address] = None;
+        self.instructions[(address + len - 1) % len] = None;
+    }
+}
+
+// chip8::run_frame, with the instructions from the cache
+pub fn run_frame(chip8: &mut Chip8, cache: &mut DecodeCache) {
+    chip8::run_frame_with(
+        chip8,
+        |_chip8| {},
+        |chip8, _left| {
+            run_next_cpu_cycle(chip8, cache);
+            1
+        },
+    );
+}
+
+// chip8::step_instruction, with the instruction from the cache
+pub fn step_instruction(chip8: &mut Chip8, cache: &mut DecodeCache) {
+    chip8::step_with(chip8, |chip8| run_next_cpu_cycle(chip8, cache));
+}
+
+// chip8::run_next_cpu_cycle, with the instruction from the cache
+pub fn run_next_cpu_cycle(chip8: &mut Chip8, cache: &mut DecodeCache) {
+    let pc = chip8.pc as usize;
+    if pc + 1 >= chip8.memory.len() {
+        return;
+    }
+    let instruction = cache.fetch(chip8, pc);
+    chip8.pc = chip8.pc.wrapping_add(2);
+
+    match memory_written(chip8, instruction) {
+        Some((first_written, written)) => {
+            chip8::execute(chip8, instruction);
+            for offset in 0..written {
+                cache.forget((first_written + offset) % chip8.memory.len());
+            }
+        }
+        None => chip8::execute(chip8, instruction),
+    }
+}
+
+// The first address and the number of bytes the instruction is about to write, from I
+// before it runs: Fx55 can move it. Only Fx33 and Fx55 write memory.
+pub(crate) fn memory_written(chip8: &Chip8, instruction: Instruction) -> Option<(usize, usize)> {
+    let written = match instruction {
+        Instruction::LdB(_x) => 3,
+        Instruction::LdIVx(x) => x as usize + 1,
+        _ => return None,
+    };
+    Some((chip8::address_from_i(chip8, 0), written))
+}
@@ -0,0 +1,235 @@
+use chip_8::chip8::{HEIGHT, WIDTH};
+use chip_8::palette::{self, Palette};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Captures work on the phosphor intensities (0 to 255 per display pixel) rather than on
+// the window, so they look the same in windowed and headless mode and don't depend on
+// the window size. Every pushed frame is one emulated frame, recordings are always 60 fps.
+
+pub fn save_screenshot(
+    filepath: &str,
+    intensity: &[u8],
+    palette: &Palette,
+    scale: u32,
+) -> Result<(), String> {
+    let (width, height) = scaled_size(scale);
+    let file = File::create(filepath).map_err(|err| err.to_string())?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let rgb: Vec<u8> = scale_pixels(intensity, scale)
+        .iter()
+        .flat_map(|px_intensity| palette::blend(palette, *px_intensity).to_vec())
+        .collect();
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&rgb))
+        .map_err(|err| err.to_string())
+}
+
+pub enum Recorder {
+    Gif(GifRecorder),
+    Y4m(Y4mRecorder),
+}
+
+impl Recorder {
+    // The format is picked from the file extension: .gif or .y4m
+    pub fn create(filepath: &str, palette: &Palette, scale: u32) -> Result<Recorder, String> {
+        let file = File::create(filepath).map_err(|err| err.to_string())?;
+        let writer = BufWriter::new(file);
+
+        if filepath.ends_with(".gif") {
+            GifRecorder::new(writer, palette, scale).map(Recorder::Gif)
+        } else if filepath.ends_with(".y4m") {
+            Y4mRecorder::new(writer, scale).map(Recorder::Y4m)
+        } else {
+            Err(format!(
+                "Unknown recording format for {}, use .gif or .y4m",
+                filepath
+            ))
+        }
+    }
+
+    pub fn push_frame(&mut self, intensity: &[u8], palette: &Palette) -> Result<(), String> {
+        match self {
+            Recorder::Gif(recorder) => recorder.push_frame(intensity, palette),
+            Recorder::Y4m(recorder) => recorder.push_frame(intensity, palette),
+        }
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        match self {
+            Recorder::Gif(recorder) => recorder.finish(),
+            Recorder::Y4m(mut recorder) => recorder.writer.flush().map_err(|err| err.to_string()),
+        }
+    }
+}
+
+// GIF delays are in hundredths of a second and viewers slow down anything under 2,
+// so 60 fps can't be stored as is. Identical frames are merged, each written frame
+// lasts until the next change on the 60 fps timeline, and changes lasting less than
+// 2/100s are dropped. The total duration always matches the emulated time.
+pub struct GifRecorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    scale: u32,
+    global_palette: Vec<u8>,
+
+    // Last pushed frame, not written until we know how long it lasts
+    pending: Option<(Vec<u8>, Vec<u8>)>, // intensity, palette
+
+    frame_count: u32,
+    written_centiseconds: u32,
+}
+
+impl GifRecorder {
+    fn new(writer: BufWriter<File>, palette: &Palette, scale: u32) -> Result<GifRecorder, String> {
+        let (width, height) = scaled_size(scale);
+        let global_palette = gif_palette(palette);
+
+        let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &global_palette)
+            .map_err(|err| err.to_string())?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(|err| err.to_string())?;
+
+        Ok(GifRecorder {
+            encoder,
+            scale,
+            global_palette,
+            pending: None,
+            frame_count: 0,
+            written_centiseconds: 0,
+        })
+    }
+
+    fn push_frame(&mut self, intensity: &[u8], palette: &Palette) -> Result<(), String> {
+        let frame = (intensity.to_vec(), gif_palette(palette));
+
+        if self.pending.as_ref() != Some(&frame) {
+            let end_centiseconds = self.centiseconds_at(self.frame_count);
+            if self.pending.is_some() && end_centiseconds - self.written_centiseconds >= 2 {
+                self.write_pending(end_centiseconds)?;
+            }
+            self.pending = Some(frame);
+        }
+
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        let end_centiseconds = self
+            .centiseconds_at(self.frame_count)
+            .max(self.written_centiseconds + 2);
+        if self.pending.is_some() {
+            self.write_pending(end_centiseconds)?;
+        }
+        Ok(())
+    }
+
+    fn centiseconds_at(&self, frame_count: u32) -> u32 {
+        (frame_count * 100 + 30) / 60
+    }
+
+    fn write_pending(&mut self, end_centiseconds: u32) -> Result<(), String> {
+        let (intensity, palette) = self.pending.as_ref().unwrap();
+        let (width, height) = scaled_size(self.scale);
+
+        let frame = gif::Frame {
+            width: width as u16,
+            height: height as u16,
+            delay: (end_centiseconds - self.written_centiseconds) as u16,
+            // The palette index is the intensity
+            buffer: scale_pixels(intensity, self.scale).into(),
+            palette: if *palette != self.global_palette {
+                Some(palette.clone())
+            } else {
+                None
+            },
+            ..gif::Frame::default()
+        };
+
+        self.encoder
+            .write_frame(&frame)
+            .map_err(|err| err.to_string())?;
+        self.written_centiseconds = end_centiseconds;
+        Ok(())
+    }
+}
+
+// One color per intensity level
+fn gif_palette(palette: &Palette) -> Vec<u8> {
+    (0..=255)
+        .flat_map(|intensity| palette::blend(palette, intensity).to_vec())
+        .collect()
+}
+
+// Raw YUV4MPEG2 stream at exactly 60 fps, for ffmpeg and other video tools.
+// 4:4:4 so single pixels keep their color.
+pub struct Y4mRecorder {
+    writer: BufWriter<File>,
+    scale: u32,
+}
+
+impl Y4mRecorder {
+    fn new(mut writer: BufWriter<File>, scale: u32) -> Result<Y4mRecorder, String> {
+        let (width, height) = scaled_size(scale);
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F60:1 Ip A1:1 C444",
+            width, height
+        )
+        .map_err(|err| err.to_string())?;
+
+        Ok(Y4mRecorder { writer, scale })
+    }
+
+    fn push_frame(&mut self, intensity: &[u8], palette: &Palette) -> Result<(), String> {
+        let pixels = scale_pixels(intensity, self.scale);
+
+        // BT.601 limited range, for each intensity level
+        let yuv_levels: Vec<[u8; 3]> = (0..=255)
+            .map(|intensity| {
+                let [r, g, b] = palette::blend(palette, intensity);
+                let (r, g, b) = (r as f32, g as f32, b as f32);
+                [
+                    (16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8,
+                    (128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8,
+                    (128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8,
+                ]
+            })
+            .collect();
+
+        let mut data = Vec::with_capacity(pixels.len() * 3 + 6);
+        data.extend_from_slice(b"FRAME\n");
+        // Planar: all Y, then all U, then all V
+        data.extend(pixels.iter().map(|px| yuv_levels[*px as usize][0]));
+        data.extend(pixels.iter().map(|px| yuv_levels[*px as usize][1]));
+        data.extend(pixels.iter().map(|px| yuv_levels[*px as usize][2]));
+
+        self.writer.write_all(&data).map_err(|err| err.to_string())
+    }
+}
+
+fn scaled_size(scale: u32) -> (u32, u32) {
+    (WIDTH as u32 * scale, HEIGHT as u32 * scale)
+}
+
+// Nearest neighbor upscale of the display
+fn scale_pixels(pixels: &[u8], scale: u32) -> Vec<u8> {
+    let (width, height) = scaled_size(scale);
+    let mut scaled = Vec::with_capacity((width * height) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            let px_index = (y / scale) * WIDTH as u32 + (x / scale);
+            scaled.push(pixels[px_index as usize]);
+        }
+    }
+
+    scaled
+}
@@ -0,0 +1,256 @@
+use crate::chip8::Chip8;
+use std::fmt;
+
+// Cheats freeze memory: their writes are applied at the start of every frame, before the
+// frame's cpu cycles, so the program always reads the cheated values.
+//
+// Cheat files have one cheat per line, a name then its codes separated by commas:
+//
+//   # Comment
+//   Infinite lives: 0x3A0 = 3
+//   Start on level 5: 0x3A1 == 0 ? 0x3A2 = 5, 0x3A3 = 1
+//
+// ADDR = VALUE always writes, CONDITION ? ADDR = VALUE only writes while the byte at the
+// condition's address is (==) or isn't (!=) the given value. Numbers are decimal or 0x hex.
+//
+// To find the addresses, a Search starts from a snapshot of the whole memory and keeps
+// the addresses whose value changed, or didn't, since the previous snapshot.
+
+pub struct Cheat {
+    pub name: String,
+    pub enabled: bool,
+    pub codes: Vec<Code>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Code {
+    pub condition: Option<Condition>,
+    pub address: u16,
+    pub value: u8,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Condition {
+    pub address: u16,
+    pub value: u8,
+    // == when true, != when false
+    pub equal: bool,
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(condition) = self.condition.as_ref() {
+            let operator = if condition.equal { "==" } else { "!=" };
+            write!(
+                f,
+                "{:#05X} {} {} ? ",
+                condition.address, operator, condition.value
+            )?;
+        }
+        write!(f, "{:#05X} = {}", self.address, self.value)
+    }
+}
+
+// A cheat file, all cheats enabled
+pub fn parse(text: &str) -> Result<Vec<Cheat>, String> {
+    let mut cheats = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, codes) = line
+            .split_once(':')
+            .ok_or_else(|| format!("line {}: expected NAME: CODES", i + 1))?;
+        cheats.push(Cheat {
+            name: String::from(name.trim()),
+            enabled: true,
+            codes: parse_codes(codes).map_err(|err| format!("line {}: {}", i + 1, err))?,
+        });
+    }
+    Ok(cheats)
+}
+
+// Codes separated by commas, or by + as in RetroArch cheat files
+pub fn parse_codes(text: &str) -> Result<Vec<Code>, String> {
+    text.split([',', '+']).map(parse_code).collect()
+}
+
+fn parse_code(text: &str) -> Result<Code, String> {
+    let (condition, write) = match text.split_once('?') {
+        Some((condition, write)) => (Some(parse_condition(condition)?), write),
+        None => (None, text),
+    };
+
+    let (address, value) = write
+        .split_once('=')
+        .ok_or_else(|| format!("expected ADDR = VALUE in {}", text.trim()))?;
+    Ok(Code {
+        condition,
+        address: parse_number(address)?,
+        value: parse_value(value)?,
+    })
+}
+
+fn parse_condition(text: &str) -> Result<Condition, String> {
+    let (address, value, equal) = if let Some((address, value)) = text.split_once("==") {
+        (address, value, true)
+    } else if let Some((address, value)) = text.split_once("!=") {
+        (address, value, false)
+    } else {
+        return Err(format!(
+            "expected ADDR == VALUE or ADDR != VALUE in {}",
+            text.trim()
+        ));
+    };
+    Ok(Condition {
+        address: parse_number(address)?,
+        value: parse_value(value)?,
+        equal,
+    })
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    let text = text.trim();
+    let number = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    number.map_err(|_| format!("invalid number {}", text))
+}
+
+fn parse_value(text: &str) -> Result<u8, String> {
+    let value = parse_number(text)?;
+    if value > 0xFF {
+        return Err(format!("value {} doesn't fit in a byte", value));
+    }
+    Ok(value as u8)
+}
+
+// Write the enabled cheats into memory, frontends call it right before chip8::run_frame.
+// Addresses beyond the machine's memory are skipped.
+pub fn apply(cheats: &[Cheat], chip8: &mut Chip8) {
+    for code in enabled_codes(cheats) {
+        if let Some(condition) = code.condition.as_ref() {
+            match chip8.memory.get(condition.address as usize) {
+                Some(value) if (*value == condition.value) == condition.equal => {}
+                _ => continue,
+            }
+        }
+        if let Some(value) = chip8.memory.get_mut(code.address as usize) {
+            *value = code.value;
+        }
+    }
+}
+
+// Where apply can write, for the runners that keep instructions: see cache::DecodeCache
+pub fn addresses(cheats: &[Cheat]) -> impl Iterator<Item = usize> + '_ {
+    enabled_codes(cheats).map(|code| code.address as usize)
+}
+
+fn enabled_codes(cheats: &[Cheat]) -> impl Iterator<Item = &Code> {
+    cheats
+        .iter()
+        .filter(|cheat| cheat.enabled)
+        .flat_map(|cheat| cheat.codes.iter())
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+}
+
+pub struct Search {
+    // Memory when the search started or was last filtered
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl Search {
+    // Every address is a candidate
+    pub fn new(chip8: &Chip8) -> Search {
+        Search {
+            snapshot: chip8.memory.clone(),
+            candidates: (0..chip8.memory.len())
+                .map(|address| address as u16)
+                .collect(),
+        }
+    }
+
+    // Keep the candidates whose value compares to the previous snapshot as filter says,
+    // then take a new snapshot
+    pub fn filter(&mut self, chip8: &Chip8, filter: Filter) {
+        // Another program with another memory size, nothing to compare with
+        if chip8.memory.len() != self.snapshot.len() {
+            *self = Search::new(chip8);
+            return;
+        }
+
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|address| {
+            let previous = snapshot[*address as usize];
+            let current = chip8.memory[*address as usize];
+            match filter {
+                Filter::Equal => current == previous,
+                Filter::Changed => current != previous,
+                Filter::Increased => current > previous,
+                Filter::Decreased => current < previous,
+            }
+        });
+        self.snapshot.copy_from_slice(&chip8.memory);
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+}
+
+#[test]
+fn test_cheats() {
+    let mut cheats = parse(
+        "# Comment\n\
+         \n\
+         Lives: 0x300 = 3\n\
+         Level: 0x301 == 0 ? 0x302 = 5, 0x301 != 0 ? 0x303 = 0xFF + 0xFFFF = 1\n",
+    )
+    .unwrap();
+    assert_eq!(cheats.len(), 2);
+    assert_eq!(cheats[1].name, "Level");
+    assert_eq!(cheats[1].codes.len(), 3);
+    assert_eq!(cheats[1].codes[0].to_string(), "0x301 == 0 ? 0x302 = 5");
+
+    let mut chip8 = Chip8::new(&[0x00, 0xE0]);
+    apply(&cheats, &mut chip8);
+    assert_eq!(chip8.memory[0x300..0x304], [3, 0, 5, 0]);
+    chip8.memory[0x301] = 1;
+    cheats[0].enabled = false;
+    chip8.memory[0x300] = 2;
+    apply(&cheats, &mut chip8);
+    assert_eq!(chip8.memory[0x300..0x304], [2, 1, 5, 0xFF]);
+
+    assert!(parse("Lives 0x300 = 3").is_err());
+    assert!(parse("Lives: 0x300 = 256").is_err());
+    assert!(parse("Lives: 0x300 < 2 ? 0x300 = 3").is_err());
+}
+
+#[test]
+fn test_search() {
+    let mut chip8 = Chip8::new(&[0x00, 0xE0]);
+    chip8.memory[0x300] = 3;
+    chip8.memory[0x301] = 3;
+    let mut search = Search::new(&chip8);
+
+    chip8.memory[0x300] = 2;
+    chip8.memory[0x301] = 4;
+    search.filter(&chip8, Filter::Decreased);
+    assert_eq!(search.candidates(), [0x300]);
+
+    search.filter(&chip8, Filter::Equal);
+    assert_eq!(search.candidates(), [0x300]);
+    search.filter(&chip8, Filter::Changed);
+    assert!(search.candidates().is_empty());
+}
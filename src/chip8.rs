@@ -0,0 +1,949 @@
+// http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#2.1
+// https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
+
+use crate::display::Display;
+
+pub const WIDTH: u8 = 64;
+pub const HEIGHT: u8 = 32;
+
+// 500hz / 60fps = ~8 instructions per frame
+pub const CYCLES_PER_FRAME: u32 = 8;
+
+// Where programs are loaded and start. ETI-660 programs start at 0x600 instead.
+pub const LOAD_ADDRESS: u16 = 0x200;
+
+// 4KB on the COSMAC VIP, XO-CHIP programs can use up to 64KB
+pub const MEMORY_SIZE: usize = 4096;
+pub const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
+
+// Why a program can't be loaded
+#[derive(Debug, PartialEq)]
+pub enum ProgramError {
+    Empty,
+    TooLarge { size: usize, max: usize },
+    // The digit sprites live below 0x50
+    InvalidLoadAddress(u16),
+}
+
+impl std::fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ProgramError::Empty => write!(f, "the program is empty"),
+            ProgramError::TooLarge { size, max } => write!(
+                f,
+                "the program is {} bytes, only {} fit in memory",
+                size, max
+            ),
+            ProgramError::InvalidLoadAddress(address) => {
+                write!(f, "can't load a program at {:#05X}", address)
+            }
+        }
+    }
+}
+
+// Instructions that behave differently between interpreters.
+// Programs are usually written for one of them and break subtly on the others.
+// https://github.com/Timendus/chip8-test-suite#quirks-test
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quirks {
+    // 8xy6 / 8xyE shift Vx in place (CHIP-48, SCHIP) instead of setting Vx = Vy shifted (COSMAC VIP)
+    pub shift_vx: bool,
+    // Fx55 / Fx65 leave I pointing after the last register (COSMAC VIP)
+    pub load_store_increment_i: bool,
+    // Bnnn jumps to nnn + Vx instead of nnn + V0, x being the highest nibble of nnn (SCHIP)
+    pub jump_vx: bool,
+    // Sprites are cut at the screen edges instead of wrapping around
+    pub clip_sprites: bool,
+    // 8xy1 / 8xy2 / 8xy3 reset VF to 0 (COSMAC VIP)
+    pub vf_reset: bool,
+    // Dxyn waits for the next frame, so at most one sprite is drawn per frame (COSMAC VIP)
+    pub display_wait: bool,
+}
+
+pub const QUIRK_PROFILES: [&str; 3] = ["modern", "cosmac", "schip"];
+
+impl Quirks {
+    // Profiles by name, see QUIRK_PROFILES
+    pub fn from_profile(name: &str) -> Option<Quirks> {
+        match name {
+            "modern" => Some(Quirks::default()),
+            "cosmac" => Some(Quirks {
+                shift_vx: false,
+                load_store_increment_i: true,
+                jump_vx: false,
+                clip_sprites: true,
+                vf_reset: true,
+                display_wait: true,
+            }),
+            "schip" => Some(Quirks {
+                shift_vx: true,
+                load_store_increment_i: false,
+                jump_vx: true,
+                clip_sprites: true,
+                vf_reset: false,
+                display_wait: false,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// What most programs written today expect
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks {
+            shift_vx: true,
+            load_store_increment_i: false,
+            jump_vx: false,
+            clip_sprites: false,
+            vf_reset: false,
+            display_wait: false,
+        }
+    }
+}
+
+pub struct Chip8 {
+    pub display: Display,
+
+    // 0x200 to 0xFFF : Chip-8 program / data
+    // 0x000 to 0x1FF : Interpreter (do not use)
+    // MEMORY_SIZE bytes, or up to XO_CHIP_MEMORY_SIZE
+    pub memory: Vec<u8>,
+
+    /*
+    1 2	3 C  =>  1 2 3 4
+    4 5	6 D  =>  q w e r
+    7 8	9 E  =>  a s d t
+    A 0	B F  =>  z x c v
+    */
+    pub keys: [bool; 16],
+
+    // V0 to VF
+    pub registers: [u8; 16],
+
+    // Store memory addresses
+    // Only 12 first lower bits are used
+    pub register_i: u16,
+
+    // Decrement at 60hz
+    pub timer_sound: u8, // ST
+    pub timer_delay: u8, // DT
+
+    // Currently executing address
+    pub pc: u16,
+
+    // Point to the topmost level of the stack
+    pub sp: u16,
+
+    // Store the address that the interpreter shoud return to when finished with a subroutine.
+    // Chip-8 allows for up to 16 levels of nested subroutines.
+    pub stack: [u16; 16],
+
+    // Transfer clear request from cpu to run_frame
+    pub needs_clear: bool,
+
+    // Request a cpu hold until a key is pressed. Value of key (0x0..0xF) is stored in register
+    pub hold_for_key: Option<u8>,
+
+    // xorshift32 state for RND. Seeded, so a run can be reproduced exactly on any platform
+    pub rng_state: u32,
+
+    // Set by DRW with the display_wait quirk, ends the current frame's cpu cycles
+    pub wait_for_frame: bool,
+
+    // Configuration, not machine state: kept out of save states
+    pub quirks: Quirks,
+    pub cycles_per_frame: u32,
+}
+
+impl Chip8 {
+    // A program at 0x200 in 4KB of memory. Panics if it doesn't fit, see load()
+    pub fn new(instructions: &[u8]) -> Chip8 {
+        Chip8::with_memory(instructions, LOAD_ADDRESS, MEMORY_SIZE)
+    }
+
+    // A program at load_address in memory_size bytes of memory (from MEMORY_SIZE to
+    // XO_CHIP_MEMORY_SIZE), checking that it fits
+    pub fn load(
+        instructions: &[u8],
+        load_address: u16,
+        memory_size: usize,
+    ) -> Result<Chip8, ProgramError> {
+        let memory_size = memory_size.clamp(MEMORY_SIZE, XO_CHIP_MEMORY_SIZE);
+        let digit_sprites_size = get_digit_sprites().len();
+        if (load_address as usize) < digit_sprites_size || load_address as usize >= memory_size {
+            return Err(ProgramError::InvalidLoadAddress(load_address));
+        }
+        if instructions.is_empty() {
+            return Err(ProgramError::Empty);
+        }
+        let max = memory_size - load_address as usize;
+        if instructions.len() > max {
+            return Err(ProgramError::TooLarge {
+                size: instructions.len(),
+                max,
+            });
+        }
+
+        Ok(Chip8::with_memory(instructions, load_address, memory_size))
+    }
+
+    fn with_memory(instructions: &[u8], load_address: u16, memory_size: usize) -> Chip8 {
+        let mut memory = vec![0; memory_size];
+        let load_address = load_address as usize;
+
+        let digit_sprites = get_digit_sprites();
+        memory[..digit_sprites.len()].copy_from_slice(&digit_sprites);
+        memory[load_address..load_address + instructions.len()].copy_from_slice(instructions);
+
+        Chip8 {
+            display: Display::new(),
+            memory,
+            keys: [false; 16],
+            registers: [0; 16],
+            register_i: 0,
+            timer_sound: 0,
+            timer_delay: 0,
+            pc: load_address as u16,
+            sp: 0,
+            stack: [0; 16],
+            needs_clear: false,
+            hold_for_key: None,
+            rng_state: rng_state_from_seed(0),
+            wait_for_frame: false,
+            quirks: Quirks::default(),
+            cycles_per_frame: CYCLES_PER_FRAME,
+        }
+    }
+}
+
+// Run one 60hz frame: tick the timers, then execute the frame's cpu cycles.
+// Frontends call this once per displayed frame, so one call is 1/60s of emulated time.
+pub fn run_frame(chip8: &mut Chip8) {
+    run_frame_with_hook(chip8, &mut |_chip8| {});
+}
+
+// run_frame, calling hook before each instruction with the machine about to execute the
+// one at pc. For scripts and debuggers.
+pub fn run_frame_with_hook(chip8: &mut Chip8, hook: &mut dyn FnMut(&mut Chip8)) {
+    run_frame_with(chip8, hook, |chip8, _left| {
+        run_next_cpu_cycle(chip8);
+        1
+    });
+}
+
+// The frame loop, with run executing instructions from pc: at least one and at most the
+// cycles left in the frame, returning how many. cache.rs runs one instruction decoded
+// ahead of time, jit.rs whole blocks of them.
+pub(crate) fn run_frame_with(
+    chip8: &mut Chip8,
+    mut hook: impl FnMut(&mut Chip8),
+    mut run: impl FnMut(&mut Chip8, u32) -> u32,
+) {
+    if chip8.timer_delay > 0 {
+        chip8.timer_delay -= 1;
+    }
+    if chip8.timer_sound > 0 {
+        chip8.timer_sound -= 1;
+    }
+
+    chip8.wait_for_frame = false;
+
+    if chip8.hold_for_key.is_none() {
+        let mut cycles = 0;
+        while cycles < chip8.cycles_per_frame {
+            if (chip8.pc as usize) < chip8.memory.len() - 2 {
+                hook(chip8);
+            }
+            // The hook can move pc
+            if (chip8.pc as usize) < chip8.memory.len() - 2 {
+                cycles += run(chip8, chip8.cycles_per_frame - cycles);
+            } else {
+                cycles += 1;
+            }
+
+            if chip8.needs_clear {
+                chip8.display.clear();
+                chip8.needs_clear = false;
+            }
+
+            // Fx0A stops the cpu right away, not at the end of the frame
+            if chip8.wait_for_frame || chip8.hold_for_key.is_some() {
+                break;
+            }
+        }
+    }
+}
+
+// Execute a single instruction, to step through a paused program. Timers don't tick and a
+// program waiting for a key stays on it.
+pub fn step_instruction(chip8: &mut Chip8) {
+    step_with(chip8, run_next_cpu_cycle);
+}
+
+pub(crate) fn step_with(chip8: &mut Chip8, cycle: impl FnOnce(&mut Chip8)) {
+    if chip8.hold_for_key.is_some() {
+        return;
+    }
+
+    if (chip8.pc as usize) < chip8.memory.len() - 2 {
+        cycle(chip8);
+    }
+
+    if chip8.needs_clear {
+        chip8.display.clear();
+        chip8.needs_clear = false;
+    }
+}
+
+// Restart the program like the reset switch of a VIP: memory stays as the program left it,
+// registers, timers, stack, keys and display are cleared. Quirks, speed and RND are kept.
+pub fn soft_reset(chip8: &mut Chip8, load_address: u16) {
+    chip8.display.clear();
+    chip8.keys = [false; 16];
+    chip8.registers = [0; 16];
+    chip8.register_i = 0;
+    chip8.timer_sound = 0;
+    chip8.timer_delay = 0;
+    chip8.pc = load_address;
+    chip8.sp = 0;
+    chip8.stack = [0; 16];
+    chip8.needs_clear = false;
+    chip8.hold_for_key = None;
+    chip8.wait_for_frame = false;
+}
+
+// Replace the program in memory while it runs, keeping registers, stack, timers and display.
+// For hot reloading: the new code takes over on the next instruction fetched from it.
+pub fn patch_program(
+    chip8: &mut Chip8,
+    load_address: u16,
+    instructions: &[u8],
+) -> Result<(), ProgramError> {
+    if instructions.is_empty() {
+        return Err(ProgramError::Empty);
+    }
+    let load_address = load_address as usize;
+    let max = chip8.memory.len().saturating_sub(load_address);
+    if instructions.len() > max {
+        return Err(ProgramError::TooLarge {
+            size: instructions.len(),
+            max,
+        });
+    }
+
+    chip8.memory[load_address..load_address + instructions.len()].copy_from_slice(instructions);
+    Ok(())
+}
+
+pub fn seed_rng(chip8: &mut Chip8, seed: u32) {
+    chip8.rng_state = rng_state_from_seed(seed);
+}
+
+fn rng_state_from_seed(seed: u32) -> u32 {
+    // xorshift never leaves the all-zero state
+    match seed ^ 0x9E37_79B9 {
+        0 => 1,
+        state => state,
+    }
+}
+
+fn next_random_byte(chip8: &mut Chip8) -> u8 {
+    let mut state = chip8.rng_state;
+    state ^= state << 13;
+    state ^= state >> 17;
+    state ^= state << 5;
+    chip8.rng_state = state;
+    (state >> 24) as u8
+}
+
+// The buzzer sounds for as long as the sound timer is non-zero
+pub fn is_buzzer_on(chip8: &Chip8) -> bool {
+    chip8.timer_sound > 0
+}
+
+pub fn key_pressed(chip8: &mut Chip8, key_index: u8) {
+    // Fx0A resumes with the key
+    if let Some(hold_for_key) = chip8.hold_for_key.take() {
+        chip8.registers[hold_for_key as usize] = key_index;
+    }
+    chip8.keys[key_index as usize] = true;
+}
+
+pub fn key_released(chip8: &mut Chip8, key_index: u8) {
+    chip8.keys[key_index as usize] = false;
+}
+
+// An opcode decoded, with its x and y register indexes, byte kk, nibble n or address nnn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    // 00E0
+    Cls,
+    // 00EE
+    Ret,
+    // 1nnn
+    Jp(u16),
+    // 2nnn
+    Call(u16),
+    // 3xkk
+    SeByte(u8, u8),
+    // 4xkk
+    SneByte(u8, u8),
+    // 5xy0
+    Se(u8, u8),
+    // 6xkk
+    LdByte(u8, u8),
+    // 7xkk
+    AddByte(u8, u8),
+    // 8xy0
+    Ld(u8, u8),
+    // 8xy1
+    Or(u8, u8),
+    // 8xy2
+    And(u8, u8),
+    // 8xy3
+    Xor(u8, u8),
+    // 8xy4
+    Add(u8, u8),
+    // 8xy5
+    Sub(u8, u8),
+    // 8xy6
+    Shr(u8, u8),
+    // 8xy7
+    Subn(u8, u8),
+    // 8xyE
+    Shl(u8, u8),
+    // 9xy0
+    Sne(u8, u8),
+    // Annn
+    LdI(u16),
+    // Bnnn
+    JpV0(u16),
+    // Cxkk
+    Rnd(u8, u8),
+    // Dxyn
+    Drw(u8, u8, u8),
+    // Ex9E
+    Skp(u8),
+    // ExA1
+    Sknp(u8),
+    // Fx07
+    LdVxDt(u8),
+    // Fx0A
+    LdVxK(u8),
+    // Fx15
+    LdDtVx(u8),
+    // Fx18
+    LdStVx(u8),
+    // Fx1E
+    AddI(u8),
+    // Fx29
+    LdF(u8),
+    // Fx33
+    LdB(u8),
+    // Fx55
+    LdIVx(u8),
+    // Fx65
+    LdVxI(u8),
+    // 0nnn - SYS addr, and the opcodes that aren't instructions
+    Nop,
+}
+
+pub fn decode(opcode: u16) -> Instruction {
+    let nnn: u16 = opcode & 0x0FFF;
+    let n: u8 = (opcode & 0x000F) as u8;
+    let x: u8 = (opcode >> 8) as u8 & 0x0F;
+    let y: u8 = (opcode >> 4) as u8 & 0x0F;
+    let kk: u8 = (opcode & 0x00FF) as u8;
+
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => Instruction::Cls,
+            0x00EE => Instruction::Ret,
+            // Jump to a machine code routine at nnn.
+            // This instruction is only used on the old computers on which Chip-8 was originally implemented. It is ignored by modern interpreters.
+            _ => Instruction::Nop,
+        },
+        0x1000 => Instruction::Jp(nnn),
+        0x2000 => Instruction::Call(nnn),
+        0x3000 => Instruction::SeByte(x, kk),
+        0x4000 => Instruction::SneByte(x, kk),
+        // As on the VIP, n isn't checked
+        0x5000 => Instruction::Se(x, y),
+        0x6000 => Instruction::LdByte(x, kk),
+        0x7000 => Instruction::AddByte(x, kk),
+        0x8000 => match n {
+            0x0 => Instruction::Ld(x, y),
+            0x1 => Instruction::Or(x, y),
+            0x2 => Instruction::And(x, y),
+            0x3 => Instruction::Xor(x, y),
+            0x4 => Instruction::Add(x, y),
+            0x5 => Instruction::Sub(x, y),
+            0x6 => Instruction::Shr(x, y),
+            0x7 => Instruction::Subn(x, y),
+            0xE => Instruction::Shl(x, y),
+            _ => Instruction::Nop,
+        },
+        0x9000 => Instruction::Sne(x, y),
+        0xA000 => Instruction::LdI(nnn),
+        0xB000 => Instruction::JpV0(nnn),
+        0xC000 => Instruction::Rnd(x, kk),
+        0xD000 => Instruction::Drw(x, y, n),
+        0xE000 => match kk {
+            0x9E => Instruction::Skp(x),
+            0xA1 => Instruction::Sknp(x),
+            _ => Instruction::Nop,
+        },
+        // 0xF000
+        _ => match kk {
+            0x07 => Instruction::LdVxDt(x),
+            0x0A => Instruction::LdVxK(x),
+            0x15 => Instruction::LdDtVx(x),
+            0x18 => Instruction::LdStVx(x),
+            0x1E => Instruction::AddI(x),
+            0x29 => Instruction::LdF(x),
+            0x33 => Instruction::LdB(x),
+            0x55 => Instruction::LdIVx(x),
+            0x65 => Instruction::LdVxI(x),
+            _ => Instruction::Nop,
+        },
+    }
+}
+
+// Whatever the program does, the cpu never panics: pc and I wrap around the 16 bits of
+// their registers, addresses from I wrap around memory and the stack wraps around its 16
+// levels. A pc with no instruction left in memory does nothing.
+pub fn run_next_cpu_cycle(chip8: &mut Chip8) {
+    if chip8.pc as usize + 1 >= chip8.memory.len() {
+        return;
+    }
+    let opcode = u16::from_be_bytes([
+        chip8.memory[chip8.pc as usize],
+        chip8.memory[chip8.pc as usize + 1],
+    ]);
+    chip8.pc = chip8.pc.wrapping_add(2);
+
+    execute(chip8, decode(opcode));
+}
+
+// Run an instruction, pc already pointing at the next one
+pub fn execute(chip8: &mut Chip8, instruction: Instruction) {
+    match instruction {
+        // 00E0 - CLS
+        Instruction::Cls => {
+            // Clear the display
+            chip8.needs_clear = true;
+        }
+        // 00EE - RET
+        Instruction::Ret => {
+            // Return from a subroutine
+            chip8.pc = chip8.stack[chip8.sp as usize % 16];
+            chip8.sp = chip8.sp.wrapping_sub(1) % 16;
+        }
+        // 1nnn - JP addr
+        Instruction::Jp(nnn) => {
+            // Jump to location nnn
+            chip8.pc = nnn;
+        }
+        // 2nnn - CALL addr
+        Instruction::Call(nnn) => {
+            // Call subroutine at nnn
+            chip8.sp = chip8.sp.wrapping_add(1) % 16;
+            chip8.stack[chip8.sp as usize] = chip8.pc;
+            chip8.pc = nnn;
+        }
+        // 3xkk - SE Vx, byte
+        Instruction::SeByte(x, kk) => {
+            // Skip next instruction if Vx = kk
+            if chip8.registers[x as usize] == kk {
+                chip8.pc = chip8.pc.wrapping_add(2);
+            }
+        }
+        // 4xkk - SNE Vx, byte
+        Instruction::SneByte(x, kk) => {
+            // Skip next instruction if Vx != kk
+            if chip8.registers[x as usize] != kk {
+                chip8.pc = chip8.pc.wrapping_add(2);
+            }
+        }
+        // 5xy0 - SE Vx, Vy
+        Instruction::Se(x, y) => {
+            // Skip next instruction if Vx = Vy
+            if chip8.registers[x as usize] == chip8.registers[y as usize] {
+                chip8.pc = chip8.pc.wrapping_add(2);
+            }
+        }
+        // 6xkk - LD Vx, byte
+        Instruction::LdByte(x, kk) => {
+            // Set Vx = kk
+            chip8.registers[x as usize] = kk;
+        }
+        // 7xkk - ADD Vx, byte
+        Instruction::AddByte(x, kk) => {
+            // Set Vx = Vx + kk
+            chip8.registers[x as usize] = chip8.registers[x as usize].wrapping_add(kk);
+        }
+        // 8xy0 - LD Vx, Vy
+        Instruction::Ld(x, y) => {
+            // Set Vx = Vy
+            chip8.registers[x as usize] = chip8.registers[y as usize];
+        }
+        // 8xy1 - OR Vx, Vy
+        Instruction::Or(x, y) => {
+            // Set Vx = Vx OR Vy
+            chip8.registers[x as usize] |= chip8.registers[y as usize];
+            if chip8.quirks.vf_reset {
+                chip8.registers[0xF] = 0;
+            }
+        }
+        // 8xy2 - AND Vx, Vy
+        Instruction::And(x, y) => {
+            // Set Vx = Vx AND Vy
+            chip8.registers[x as usize] &= chip8.registers[y as usize];
+            if chip8.quirks.vf_reset {
+                chip8.registers[0xF] = 0;
+            }
+        }
+        // 8xy3 - XOR Vx, Vy
+        Instruction::Xor(x, y) => {
+            // Set Vx = Vx XOR Vy
+            chip8.registers[x as usize] ^= chip8.registers[y as usize];
+            if chip8.quirks.vf_reset {
+                chip8.registers[0xF] = 0;
+            }
+        }
+        // 8xy4 - ADD Vx, Vy
+        Instruction::Add(x, y) => {
+            // Set Vx = Vx + Vy, set VF = carry
+            let result =
+                (chip8.registers[x as usize] as u16) + (chip8.registers[y as usize] as u16);
+            chip8.registers[x as usize] = (result & 0xFF) as u8;
+            chip8.registers[0xF] = if result > 0xFF { 1 } else { 0 }
+        }
+        // 8xy5 - SUB Vx, Vy
+        Instruction::Sub(x, y) => {
+            // Set Vx = Vx - Vy, set VF = NOT borrow
+            // VF is set last, it holds the flag when it's Vx too
+            let vx = chip8.registers[x as usize];
+            let vy = chip8.registers[y as usize];
+            chip8.registers[x as usize] = vx.wrapping_sub(vy);
+            chip8.registers[0xF] = if vx >= vy { 1 } else { 0 };
+        }
+        // 8xy6 - SHR Vx {, Vy}
+        Instruction::Shr(x, y) => {
+            // Set Vx = Vx SHR 1, set VF = the bit shifted out
+            let value = if chip8.quirks.shift_vx {
+                chip8.registers[x as usize]
+            } else {
+                chip8.registers[y as usize]
+            };
+            chip8.registers[x as usize] = value >> 1;
+            chip8.registers[0xF] = value & 0b00000001;
+        }
+        // 8xy7 - SUBN Vx, Vy
+        Instruction::Subn(x, y) => {
+            // Set Vx = Vy - Vx, set VF = NOT borrow
+            let vx = chip8.registers[x as usize];
+            let vy = chip8.registers[y as usize];
+            chip8.registers[x as usize] = vy.wrapping_sub(vx);
+            chip8.registers[0xF] = if vy >= vx { 1 } else { 0 };
+        }
+        // 8xyE - SHL Vx {, Vy}
+        Instruction::Shl(x, y) => {
+            // Set Vx = Vx SHL 1, set VF = the bit shifted out
+            let value = if chip8.quirks.shift_vx {
+                chip8.registers[x as usize]
+            } else {
+                chip8.registers[y as usize]
+            };
+            chip8.registers[x as usize] = value << 1;
+            chip8.registers[0xF] = value >> 7;
+        }
+        // 9xy0 - SNE Vx, Vy
+        Instruction::Sne(x, y) => {
+            // Skip next instruction if Vx != Vy
+            if chip8.registers[x as usize] != chip8.registers[y as usize] {
+                chip8.pc = chip8.pc.wrapping_add(2);
+            }
+        }
+        // Annn - LD I, addr
+        Instruction::LdI(nnn) => {
+            // Set I = nnn
+            chip8.register_i = nnn;
+        }
+        // Bnnn - JP V0, addr
+        Instruction::JpV0(nnn) => {
+            // Jump to location nnn + V0
+            let offset_register = if chip8.quirks.jump_vx { nnn >> 8 } else { 0 };
+            chip8.pc = nnn + (chip8.registers[offset_register as usize] as u16);
+        }
+        // Cxkk - RND Vx, byte
+        Instruction::Rnd(x, kk) => {
+            // Set Vx = random byte AND kk
+            chip8.registers[x as usize] = next_random_byte(chip8) & kk;
+        }
+        // Dxyn - DRW Vx, Vy, nibble
+        Instruction::Drw(x, y, n) => {
+            // Display n-byte sprite starting at chip8.memory location I at (Vx, Vy), set VF = collision.
+            /*
+            The interpreter reads n bytes from chip8.memory,
+            starting at the address stored in I.
+            These bytes are then displayed as sprites on screen
+            at coordinates (Vx, Vy). Sprites are XORed onto
+            the existing screen. If this causes any pixels
+            to be erased, VF is set to 1, otherwise it is
+            set to 0. If the sprite is positioned so part of it
+            is outside the coordinates of the display, it wraps
+            around to the opposite side of the screen.
+            */
+            let start_x = chip8.registers[x as usize] % WIDTH;
+            let start_y = chip8.registers[y as usize] % HEIGHT;
+
+            // Sprites are 8xN
+            let mut sprite = [0; 15];
+            for (line, sprite_line) in sprite.iter_mut().enumerate().take(n as usize) {
+                *sprite_line = chip8.memory[address_from_i(chip8, line)];
+            }
+            let collision = chip8.display.draw_sprite(
+                start_x as usize,
+                start_y as usize,
+                &sprite[..n as usize],
+                chip8.quirks.clip_sprites,
+            );
+            chip8.registers[0xF] = collision as u8;
+
+            if chip8.quirks.display_wait {
+                chip8.wait_for_frame = true;
+            }
+        }
+        // Ex9E - SKP Vx
+        Instruction::Skp(x) => {
+            // Skip next instruction if key with the value of Vx is pressed
+            // Only the low nibble of Vx selects a key, like on the VIP
+            if chip8.keys[(chip8.registers[x as usize] & 0xF) as usize] {
+                chip8.pc = chip8.pc.wrapping_add(2);
+            }
+        }
+        // ExA1 - SKNP Vx
+        Instruction::Sknp(x) => {
+            // Skip next instruction if key with the value of Vx is not pressed
+            if !chip8.keys[(chip8.registers[x as usize] & 0xF) as usize] {
+                chip8.pc = chip8.pc.wrapping_add(2);
+            }
+        }
+        // Fx07 - LD Vx, DT
+        Instruction::LdVxDt(x) => {
+            // Set Vx = delay timer value
+            chip8.registers[x as usize] = chip8.timer_delay;
+        }
+        // Fx0A - LD Vx, K
+        Instruction::LdVxK(x) => {
+            // Wait for a key press, store the value of the key in Vx
+            // All execution stops until a key is pressed
+            chip8.hold_for_key = Some(x);
+        }
+        // Fx15 - LD DT, Vx
+        Instruction::LdDtVx(x) => {
+            // Set delay timer = Vx
+            chip8.timer_delay = chip8.registers[x as usize];
+        }
+        // Fx18 - LD ST, Vx
+        Instruction::LdStVx(x) => {
+            // Set sound timer = Vx
+            chip8.timer_sound = chip8.registers[x as usize];
+        }
+        // Fx1E - ADD I, Vx
+        Instruction::AddI(x) => {
+            // Set I = I + Vx
+            chip8.register_i = chip8
+                .register_i
+                .wrapping_add(chip8.registers[x as usize] as u16);
+        }
+        // Fx29 - LD F, Vx
+        Instruction::LdF(x) => {
+            // Set I = location of sprite for digit Vx, the low nibble of Vx
+            chip8.register_i = (chip8.registers[x as usize] & 0xF) as u16 * 5;
+        }
+        // Fx33 - LD B, Vx
+        Instruction::LdB(x) => {
+            // Store BCD representation of Vx in chip8.memory locations I, I+1, and I+2
+            // The interpreter takes the decimal value of Vx, and places the hundreds digit in chip8.memory at location in I, the tens digit at location I+1, and the ones digit at location I+2.
+            let value = chip8.registers[x as usize];
+            for (i, digit) in [value / 100, (value % 100) / 10, value % 10]
+                .iter()
+                .enumerate()
+            {
+                let address = address_from_i(chip8, i);
+                chip8.memory[address] = *digit;
+            }
+        }
+        // Fx55 - LD [I], Vx
+        Instruction::LdIVx(x) => {
+            // Store chip8.registers V0 through Vx in chip8.memory starting at location I
+            // The interpreter copies the values of chip8.registers V0 through Vx into chip8.memory, starting at the address in I
+            // I itself is left unmodified, unless the load_store_increment_i quirk is on
+
+            for i in 0..=(x as usize) {
+                let address = address_from_i(chip8, i);
+                chip8.memory[address] = chip8.registers[i];
+            }
+            if chip8.quirks.load_store_increment_i {
+                chip8.register_i = chip8.register_i.wrapping_add(x as u16 + 1);
+            }
+        }
+        // Fx65 - LD Vx, [I]
+        Instruction::LdVxI(x) => {
+            // Read chip8.registers V0 through Vx from chip8.memory starting at location I
+            // The interpreter reads values from chip8.memory starting at location I into chip8.registers V0 through Vx
+            // I itself is left unmodified, unless the load_store_increment_i quirk is on
+
+            for i in 0..=(x as usize) {
+                chip8.registers[i] = chip8.memory[address_from_i(chip8, i)];
+            }
+            if chip8.quirks.load_store_increment_i {
+                chip8.register_i = chip8.register_i.wrapping_add(x as u16 + 1);
+            }
+        }
+        Instruction::Nop => {}
+    }
+}
+
+// The address offset bytes after I, wrapped around memory
+pub(crate) fn address_from_i(chip8: &Chip8, offset: usize) -> usize {
+    (chip8.register_i as usize + offset) % chip8.memory.len()
+}
+
+fn get_digit_sprites() -> [u8; 80] {
+    {
+        /*
+        ****  11110000  0xF0
+        *  *  10010000  0x90
+        *  *  10010000  0x90
+        *  *  10010000  0x90
+        ****  11110000  0xF0
+
+          *   00100000  0x20
+         **   01100000  0x60
+          *   00100000  0x20
+          *   00100000  0x20
+         ***  01110000  0x70
+
+        ****  11110000  0xF0
+           *  00010000  0x10
+        ****  11110000  0xF0
+        *     10000000  0x80
+        ****  11110000  0xF0
+
+        ****  11110000  0xF0
+           *  00010000  0x10
+        ****  11110000  0xF0
+           *  00010000  0x10
+        ****  11110000  0xF0
+
+        *  *  10010000  0x90
+        *  *  10010000  0x90
+        ****  11110000  0xF0
+           *  00010000  0x10
+           *  00010000  0x10
+
+        ****  11110000  0xF0
+        *     10000000  0x80
+        ****  11110000  0xF0
+           *  00010000  0x10
+        ****  11110000  0xF0
+
+        ****  11110000  0xF0
+        *     10000000  0x80
+        ****  11110000  0xF0
+        *  *  10010000  0x90
+        ****  11110000  0xF0
+
+        ****  11110000  0xF0
+           *  00010000  0x10
+          *   00100000  0x20
+         *    01000000  0x40
+         *    01000000  0x40
+
+        ****  11110000  0xF0
+        *  *  10010000  0x90
+        ****  11110000  0xF0
+        *  *  10010000  0x90
+        ****  11110000  0xF0
+
+        ****  11110000  0xF0
+        *  *  10010000  0x90
+        ****  11110000  0xF0
+           *  00010000  0x10
+        ****  11110000  0xF0
+
+        ****  11110000  0xF0
+        *  *  10010000  0x90
+        ****  11110000  0xF0
+        *  *  10010000  0x90
+        *  *  10010000  0x90
+
+        ***   11100000  0xE0
+        *  *  10010000  0x90
+        ***   11100000  0xE0
+        *  *  10010000  0x90
+        ***   11100000  0xE0
+
+        ****  11110000  0xF0
+        *     10000000  0x80
+        *     10000000  0x80
+        *     10000000  0x80
+        ****  11110000  0xF0
+
+        ***   11100000  0xE0
+        *  *  10010000  0x90
+        *  *  10010000  0x90
+        *  *  10010000  0x90
+        ***   11100000  0xE0
+
+        ****  11110000  0xF0
+        *     10000000  0x80
+        ****  11110000  0xF0
+        *     10000000  0x80
+        ****  11110000  0xF0
+
+        ****  11110000  0xF0
+        *     10000000  0x80
+        ****  11110000  0xF0
+        *     10000000  0x80
+        *     10000000  0x80
+        */
+    }
+
+    [
+        0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0,
+        0xF0, 0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0,
+        0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0,
+        0xF0, 0x90, 0xF0, 0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0,
+        0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0,
+        0xF0, 0x80, 0xF0, 0x80, 0x80,
+    ]
+}
+
+#[test]
+fn test_load() {
+    let chip8 = Chip8::load(&[0x12, 0x00], 0x600, MEMORY_SIZE).unwrap();
+    assert_eq!(chip8.pc, 0x600);
+    assert_eq!(chip8.memory[0x600..0x602], [0x12, 0x00]);
+
+    assert_eq!(
+        Chip8::load(&[], LOAD_ADDRESS, MEMORY_SIZE).err(),
+        Some(ProgramError::Empty)
+    );
+    assert_eq!(
+        Chip8::load(&[0; 3585], LOAD_ADDRESS, MEMORY_SIZE).err(),
+        Some(ProgramError::TooLarge {
+            size: 3585,
+            max: 3584
+        })
+    );
+    assert!(Chip8::load(&[0; 3585], LOAD_ADDRESS, XO_CHIP_MEMORY_SIZE).is_ok());
+    assert_eq!(
+        Chip8::load(&[0x12, 0x00], 0x10, MEMORY_SIZE).err(),
+        Some(ProgramError::InvalidLoadAddress(0x10))
+    );
+}
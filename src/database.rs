@@ -0,0 +1,218 @@
+use crate::rom::EmbeddedOptions;
+use chip_8::chip8::{Quirks, XO_CHIP_MEMORY_SIZE};
+use chip_8::palette::{self, Rgb};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+// ROM metadata looked up by SHA-1, in the format of the community CHIP-8 database.
+// A small database is bundled (see database/README), --database reads another one.
+// https://github.com/chip-8/chip-8-database
+
+pub struct Database {
+    hashes: HashMap<String, usize>,
+    programs: Vec<Program>,
+    platforms: Vec<Platform>,
+}
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    description: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    // The first one is the platform the ROM is meant for
+    #[serde(default)]
+    platforms: Vec<String>,
+    // Quirks of a platform that this ROM needs changed, by platform id
+    #[serde(default)]
+    quirky_platforms: HashMap<String, HashMap<String, bool>>,
+    tickrate: Option<u32>,
+    // Only set when it isn't 0x200, e.g. 0x600 for ETI-660 programs
+    start_address: Option<u16>,
+    colors: Option<Colors>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    // Background first, then foreground (more for XO-CHIP planes)
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Platform {
+    id: String,
+    name: String,
+    default_tickrate: Option<u32>,
+    #[serde(default)]
+    quirks: HashMap<String, bool>,
+}
+
+// What the database knows about one ROM, translated to this emulator's settings
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub description: Option<String>,
+    pub platform: Option<String>,
+    pub quirks: Option<Quirks>,
+    pub cycles_per_frame: Option<u32>,
+    // background, foreground
+    pub colors: Option<(Rgb, Rgb)>,
+    pub load_address: Option<u16>,
+    pub memory_size: Option<usize>,
+    // Chip-8 key for "up", "down", "left", "right", "a" and "b"
+    pub keys: HashMap<String, u8>,
+}
+
+impl RomInfo {
+    // For the window title, e.g. "Clock by Julien Duranleau - Hours, minutes and seconds ..."
+    pub fn title_line(&self) -> String {
+        let mut line = self.title.clone();
+        if !self.authors.is_empty() {
+            line += &format!(" by {}", self.authors.join(", "));
+        }
+        if let Some(description) = self.description.as_ref() {
+            // Some descriptions are whole paragraphs
+            line += &format!(" - {}", description.lines().next().unwrap_or_default());
+        }
+        line
+    }
+
+    pub fn options(&self) -> EmbeddedOptions {
+        EmbeddedOptions {
+            quirks: self.quirks,
+            cycles_per_frame: self.cycles_per_frame,
+            colors: self.colors,
+            load_address: self.load_address,
+            memory_size: self.memory_size,
+        }
+    }
+}
+
+impl Database {
+    pub fn bundled() -> Database {
+        Database::from_json(
+            include_str!("../database/sha1-hashes.json"),
+            include_str!("../database/programs.json"),
+            include_str!("../database/platforms.json"),
+        )
+        .unwrap()
+    }
+
+    // directory is the database/ directory of the community database, or one laid out the same
+    pub fn load(directory: &str) -> Result<Database, String> {
+        let read = |filename: &str| {
+            let filepath = Path::new(directory).join(filename);
+            std::fs::read_to_string(&filepath)
+                .map_err(|err| format!("{} : {}", filepath.display(), err))
+        };
+
+        Database::from_json(
+            &read("sha1-hashes.json")?,
+            &read("programs.json")?,
+            &read("platforms.json")?,
+        )
+        .map_err(|err| err.to_string())
+    }
+
+    fn from_json(hashes: &str, programs: &str, platforms: &str) -> serde_json::Result<Database> {
+        Ok(Database {
+            hashes: serde_json::from_str(hashes)?,
+            programs: serde_json::from_str(programs)?,
+            platforms: serde_json::from_str(platforms)?,
+        })
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<RomInfo> {
+        let hash = sha1_smol::Sha1::from(rom).digest().to_string();
+        let program = self.programs.get(*self.hashes.get(&hash)?)?;
+        let rom = program.roms.get(&hash)?;
+
+        let platform = rom
+            .platforms
+            .first()
+            .and_then(|id| self.platforms.iter().find(|platform| &platform.id == id));
+
+        let quirks = platform.map(|platform| {
+            let mut flags = platform.quirks.clone();
+            if let Some(overrides) = rom.quirky_platforms.get(&platform.id) {
+                flags.extend(overrides.iter().map(|(name, value)| (name.clone(), *value)));
+            }
+            quirks_from_flags(&flags)
+        });
+
+        let colors = rom.colors.as_ref().and_then(|colors| {
+            let background = palette::parse_color(colors.pixels.first()?)?;
+            let foreground = palette::parse_color(colors.pixels.get(1)?)?;
+            Some((background, foreground))
+        });
+
+        Some(RomInfo {
+            title: program.title.clone(),
+            authors: program.authors.clone(),
+            description: program.description.clone(),
+            platform: platform.map(|platform| platform.name.clone()),
+            quirks,
+            cycles_per_frame: rom
+                .tickrate
+                .or_else(|| platform.and_then(|platform| platform.default_tickrate)),
+            colors,
+            load_address: rom.start_address,
+            // The only platform with more than 4KB
+            memory_size: platform
+                .filter(|platform| platform.id == "xochip")
+                .map(|_| XO_CHIP_MEMORY_SIZE),
+            keys: rom.keys.clone(),
+        })
+    }
+}
+
+fn quirks_from_flags(flags: &HashMap<String, bool>) -> Quirks {
+    let flag = |name: &str| flags.get(name).copied().unwrap_or(false);
+
+    Quirks {
+        shift_vx: flag("shift"),
+        // CHIP-48 and SCHIP 1.0 add x instead of x + 1 to I (memoryIncrementByX).
+        // That isn't emulated, they get the full increment.
+        load_store_increment_i: !flag("memoryLeaveIUnchanged"),
+        jump_vx: flag("jump"),
+        clip_sprites: !flag("wrap"),
+        vf_reset: flag("logic"),
+        display_wait: flag("vblank"),
+    }
+}
+
+#[test]
+fn test_bundled_database() {
+    let database = Database::bundled();
+
+    let clock = database
+        .lookup(include_bytes!("../tests/roms/clock.ch8"))
+        .unwrap();
+    assert_eq!(clock.title, "Clock");
+    assert_eq!(clock.platform.as_deref(), Some("Modern CHIP-8"));
+    assert_eq!(clock.cycles_per_frame, Some(8));
+    assert!(clock.quirks.unwrap().clip_sprites);
+
+    // quirkyPlatforms overrides the platform's quirks
+    let random = database
+        .lookup(include_bytes!("../tests/roms/random.ch8"))
+        .unwrap();
+    assert!(!random.quirks.unwrap().clip_sprites);
+    assert_eq!(
+        random.colors,
+        Some(([0x0A, 0x1A, 0x0A], [0x33, 0xFF, 0x66]))
+    );
+
+    assert!(database.lookup(&[0x12, 0x00]).is_none());
+}
@@ -0,0 +1,114 @@
+use crate::chip8::{HEIGHT, WIDTH};
+
+pub const SIZE: usize = WIDTH as usize * HEIGHT as usize;
+
+// The monochrome display, one u64 per row with the leftmost pixel in the high bit. WIDTH
+// is 64, so a sprite row is its byte shifted right to its x, or rotated to wrap around
+// the edge, XORed onto the display row, and a collision is the AND of the two.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Display {
+    rows: [u64; HEIGHT as usize],
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display {
+            rows: [0; HEIGHT as usize],
+        }
+    }
+
+    // One byte per pixel, 0 or 1, row after row, as save states store the display
+    pub fn from_bytes(bytes: &[u8]) -> Display {
+        let mut display = Display::new();
+        for (i, px) in bytes.iter().take(SIZE).enumerate() {
+            display.set(i % WIDTH as usize, i / WIDTH as usize, *px != 0);
+        }
+        display
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pixels().map(|lit| lit as u8).collect()
+    }
+
+    // x < WIDTH, y < HEIGHT
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.rows[y] & pixel_bit(x) != 0
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, lit: bool) {
+        if lit {
+            self.rows[y] |= pixel_bit(x);
+        } else {
+            self.rows[y] &= !pixel_bit(x);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rows = [0; HEIGHT as usize];
+    }
+
+    // Whether each pixel is lit, row after row, left to right
+    pub fn pixels(&self) -> impl Iterator<Item = bool> + '_ {
+        self.rows
+            .iter()
+            .flat_map(|row| (0..WIDTH as usize).map(move |x| row & pixel_bit(x) != 0))
+    }
+
+    pub fn rows(&self) -> &[u64] {
+        &self.rows
+    }
+
+    // XOR a sprite, one byte per row, with its top left pixel at x < WIDTH, y < HEIGHT.
+    // What goes past the right or bottom edge wraps around, or is cut off with clip.
+    // Returns whether a lit pixel was turned off.
+    pub fn draw_sprite(&mut self, x: usize, y: usize, sprite: &[u8], clip: bool) -> bool {
+        let mut collision = 0;
+        for (line, byte) in sprite.iter().enumerate() {
+            if clip && y + line >= HEIGHT as usize {
+                break;
+            }
+            let row = &mut self.rows[(y + line) % HEIGHT as usize];
+
+            let bits = (*byte as u64) << (64 - 8);
+            let bits = if clip {
+                bits >> x
+            } else {
+                bits.rotate_right(x as u32)
+            };
+            collision |= *row & bits;
+            *row ^= bits;
+        }
+        collision != 0
+    }
+}
+
+fn pixel_bit(x: usize) -> u64 {
+    1 << (WIDTH as usize - 1 - x)
+}
+
+#[test]
+fn test_draw_sprite() {
+    let mut display = Display::new();
+    assert!(!display.draw_sprite(62, 31, &[0b1100_0001, 0b1000_0000], false));
+    assert!(display.get(62, 31) && display.get(63, 31) && display.get(5, 31));
+    assert!(display.get(62, 0));
+    assert_eq!(display.pixels().filter(|lit| *lit).count(), 4);
+
+    assert!(display.draw_sprite(63, 31, &[0b1000_0000], true));
+    assert!(!display.get(63, 31));
+    // Only the first row and two columns are drawn
+    assert!(display.draw_sprite(62, 31, &[0xFF, 0xFF], true));
+    assert_eq!(display.rows()[31], 1 << 58 | 1);
+    assert_eq!(display.rows()[0], 1 << 1);
+
+    let bytes = display.to_bytes();
+    assert_eq!(Display::from_bytes(&bytes), display);
+    display.clear();
+    assert_eq!(display, Display::new());
+}
@@ -0,0 +1,179 @@
+use crate::capture::{self, Recorder};
+use crate::options::Options;
+use crate::remote;
+use crate::script::Script;
+use crate::wav::WavRecorder;
+#[cfg(not(all(feature = "jit", not(target_arch = "wasm32"))))]
+use chip_8::cache::{self as runner, DecodeCache as Runner};
+use chip_8::cheat::{self, Cheat};
+use chip_8::chip8::{self, Chip8};
+#[cfg(all(feature = "jit", not(target_arch = "wasm32")))]
+use chip_8::jit::{self as runner, Jit as Runner};
+use chip_8::palette::Palette;
+use chip_8::phosphor::Phosphor;
+
+struct Headless {
+    chip8: Chip8,
+    // Frames run back to back: hot blocks compiled with the jit feature, otherwise
+    // instructions decoded once
+    runner: Runner,
+    phosphor: Phosphor,
+    palette: Palette,
+    wav_recorder: Option<WavRecorder>,
+    recorder: Option<Recorder>,
+    cheats: Vec<Cheat>,
+    script: Option<Script>,
+
+    // Before the ROM's settings were filled in, for programs loaded remotely
+    base_options: Options,
+
+    frames: u32,
+    quit: bool,
+}
+
+// Run the emulator for a fixed number of frames, without window or audio device.
+// Frames are emulated back to back, as fast as the host allows.
+// With --remote, frames only run when a client asks for them, until it asks to quit.
+pub fn run(mut options: Options) {
+    let base_options = options.clone();
+    let (mut chip8, _rom_info) =
+        crate::load_chip8(&mut options).unwrap_or_else(|err| crate::exit_with_error(&err));
+    let options = &options;
+    chip8::seed_rng(&mut chip8, options.seed.unwrap_or(0));
+
+    let (mut palettes, palette_index) = crate::create_palettes(options);
+    let palette = palettes.swap_remove(palette_index);
+
+    let recorder = options.record_path.as_ref().map(|filepath| {
+        crate::create_recorder(filepath, &palette, options.capture_scale)
+            .unwrap_or_else(|err| crate::exit_with_error(&err))
+    });
+
+    let cheats = crate::load_cheats(options).unwrap_or_else(|err| crate::exit_with_error(&err));
+    let script = crate::load_script(options).unwrap_or_else(|err| crate::exit_with_error(&err));
+
+    let mut headless = Headless {
+        chip8,
+        runner: Runner::new(),
+        phosphor: Phosphor::new(options.persistence),
+        palette,
+        wav_recorder: options.wav_path.as_ref().map(|filepath| {
+            crate::create_wav_recorder(filepath).unwrap_or_else(|err| crate::exit_with_error(&err))
+        }),
+        recorder,
+        cheats,
+        script,
+        base_options,
+        frames: 0,
+        quit: false,
+    };
+
+    match options.remote_address.as_ref() {
+        Some(address) => {
+            let requests =
+                remote::start(address).unwrap_or_else(|err| crate::exit_with_error(&err));
+            println!("Listening on {}", address);
+            for request in requests.iter() {
+                remote::handle(request, &mut headless);
+                if headless.quit {
+                    break;
+                }
+            }
+        }
+        None => {
+            for _frame in 0..options.frames {
+                remote::Frontend::run_frame(&mut headless);
+            }
+        }
+    }
+
+    if let Some(wav_recorder) = headless.wav_recorder {
+        wav_recorder.finalize().unwrap_or_else(|err| {
+            crate::exit_with_error(&format!("Error finishing WAV : {}", err))
+        });
+    }
+
+    if let Some(recorder) = headless.recorder {
+        recorder.finish().unwrap_or_else(|err| {
+            crate::exit_with_error(&format!("Error finishing recording : {}", err))
+        });
+    }
+
+    if let Some(filepath) = options.screenshot_path.as_ref() {
+        capture::save_screenshot(
+            filepath,
+            headless.phosphor.intensity(),
+            &headless.palette,
+            options.capture_scale,
+        )
+        .unwrap_or_else(|err| {
+            crate::exit_with_error(&format!(
+                "Error saving screenshot at {} : {}",
+                filepath, err
+            ))
+        });
+    }
+
+    println!("Emulated {} frames.", headless.frames);
+}
+
+impl remote::Frontend for Headless {
+    // Remote requests can write memory and load states
+    fn chip8(&mut self) -> &mut Chip8 {
+        self.runner.invalidate();
+        &mut self.chip8
+    }
+
+    fn run_frame(&mut self) {
+        cheat::apply(&self.cheats, &mut self.chip8);
+        for address in cheat::addresses(&self.cheats) {
+            self.runner.forget(address);
+        }
+        // Scripts see every instruction and can write memory: with one, every frame is
+        // interpreted and the runner stays unused
+        match self.script.as_mut() {
+            Some(script) => script.run_frame(&mut self.chip8),
+            None => runner::run_frame(&mut self.chip8, &mut self.runner),
+        }
+        self.phosphor.update(&self.chip8.display);
+        self.frames += 1;
+
+        if let Some(wav_recorder) = self.wav_recorder.as_mut() {
+            wav_recorder
+                .push_frame(chip8::is_buzzer_on(&self.chip8))
+                .unwrap_or_else(|err| {
+                    crate::exit_with_error(&format!("Error writing WAV : {}", err))
+                });
+        }
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder
+                .push_frame(self.phosphor.intensity(), &self.palette)
+                .unwrap_or_else(|err| {
+                    crate::exit_with_error(&format!("Error recording : {}", err))
+                });
+        }
+    }
+
+    // Captures carry on with the new program, in the colors they started with
+    fn load_program(&mut self, filepath: &str) -> Result<(), String> {
+        let mut options = self.base_options.clone();
+        options.program = String::from(filepath);
+        let (mut chip8, _rom_info) = crate::load_chip8(&mut options)?;
+        chip8::seed_rng(&mut chip8, options.seed.unwrap_or(0));
+        self.chip8 = chip8;
+        self.runner.invalidate();
+        Ok(())
+    }
+
+    fn set_paused(&mut self, _paused: bool) -> Result<(), String> {
+        Err(String::from(
+            "the headless frontend only runs frames when asked to",
+        ))
+    }
+
+    fn quit(&mut self) -> Result<(), String> {
+        self.quit = true;
+        Ok(())
+    }
+}
@@ -0,0 +1,669 @@
+use crate::cache;
+use crate::chip8::{self, Chip8, Instruction, Quirks};
+use cranelift_codegen::ir::{condcodes::IntCC, types, AbiParam, InstBuilder, MemFlags, Value};
+use cranelift_codegen::settings;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::Module;
+use std::mem::offset_of;
+
+// Blocks of instructions compiled to native code with Cranelift once they've run
+// HOT_RUNS times, for the highest speeds. A frame runs the same instructions as
+// chip8::run_frame, with the same results.
+//
+// A block runs up to the first jump, skip, call or return, which it ends with, or up to
+// the first DRW, Fx0A, Fx33 or Fx55, left to the interpreter: they draw, wait for a key or
+// write memory. Bytes the program wrote are never compiled, so self-modifying code runs in
+// the interpreter, and the blocks over them are dropped. After writing memory from outside
+// the cpu, call forget or invalidate, as with cache::DecodeCache. New quirks or a new memory size
+// drop the blocks on their own. Without a Cranelift backend for the host, everything runs
+// in the interpreter.
+pub struct Jit {
+    // None when Cranelift can't compile for the host
+    module: Option<JITModule>,
+    // One entry per address, instructions can start on odd ones
+    entries: Vec<Entry>,
+    // Per address, whether the program wrote it and whether a block was compiled from it
+    written: Vec<bool>,
+    compiled: Vec<bool>,
+    // What the blocks were compiled for
+    quirks: Quirks,
+}
+
+const HOT_RUNS: u8 = 16;
+const MAX_BLOCK_INSTRUCTIONS: usize = 64;
+
+// Runs the block with the machine and its memory, stopping after at most left
+// instructions. Returns how many ran, pc pointing after the last one.
+type BlockFunction = unsafe extern "C" fn(*mut Chip8, *const u8, u32) -> u32;
+
+#[derive(Clone, Copy)]
+enum Entry {
+    // Times the interpreter ran from there
+    Cold(u8),
+    Compiled { function: BlockFunction, end: usize },
+    // Starts with an instruction left to the interpreter
+    Interpreted,
+}
+
+impl Default for Jit {
+    fn default() -> Jit {
+        Jit::new()
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        self.entries.clear();
+        if let Some(module) = self.module.take() {
+            // No function pointer into the module is left
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+impl Jit {
+    pub fn new() -> Jit {
+        Jit {
+            module: new_module(),
+            entries: vec![],
+            written: vec![],
+            compiled: vec![],
+            quirks: Quirks::default(),
+        }
+    }
+
+    pub fn invalidate(&mut self) {
+        self.entries.clear();
+        self.written.clear();
+        self.compiled.clear();
+        if let Some(module) = self.module.take() {
+            unsafe { module.free_memory() };
+            self.module = new_module();
+        }
+    }
+
+    pub fn compiled_blocks(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry, Entry::Compiled { .. }))
+            .count()
+    }
+
+    fn prepare(&mut self, chip8: &Chip8) {
+        if self.entries.len() != chip8.memory.len() || self.quirks != chip8.quirks {
+            self.invalidate();
+            self.entries = vec![Entry::Cold(0); chip8.memory.len()];
+            self.written = vec![false; chip8.memory.len()];
+            self.compiled = vec![false; chip8.memory.len()];
+            self.quirks = chip8.quirks;
+        }
+    }
+
+    // The byte at address was written: it's never compiled again, and the blocks over it
+    // are dropped
+    pub fn forget(&mut self, address: usize) {
+        if address >= self.written.len() {
+            return;
+        }
+        self.written[address] = true;
+        if !self.compiled[address] {
+            return;
+        }
+        self.compiled[address] = false;
+        for start in address.saturating_sub(MAX_BLOCK_INSTRUCTIONS * 2)..=address {
+            if let Entry::Compiled { end, .. } = self.entries[start] {
+                if end > address {
+                    self.entries[start] = Entry::Cold(0);
+                }
+            }
+        }
+    }
+
+    fn compile(&mut self, chip8: &Chip8, start: usize) -> Entry {
+        let instructions = self.block_at(chip8, start);
+        let module = match &mut self.module {
+            Some(module) if !instructions.is_empty() => module,
+            _ => return Entry::Interpreted,
+        };
+        match translate(module, start, &instructions, chip8) {
+            Some(function) => {
+                let end = start + instructions.len() * 2;
+                for compiled in &mut self.compiled[start..end] {
+                    *compiled = true;
+                }
+                Entry::Compiled { function, end }
+            }
+            None => Entry::Interpreted,
+        }
+    }
+
+    // The instructions from start that can be compiled, up to the end of the block
+    fn block_at(&self, chip8: &Chip8, start: usize) -> Vec<Instruction> {
+        let mut instructions = vec![];
+        let mut address = start;
+        // Where the frame loop stops running instructions
+        while address < chip8.memory.len() - 2
+            && instructions.len() < MAX_BLOCK_INSTRUCTIONS
+            && !self.written[address]
+            && !self.written[address + 1]
+        {
+            let instruction = chip8::decode(u16::from_be_bytes([
+                chip8.memory[address],
+                chip8.memory[address + 1],
+            ]));
+            match instruction {
+                Instruction::Drw(..)
+                | Instruction::LdVxK(_)
+                | Instruction::LdB(_)
+                | Instruction::LdIVx(_) => break,
+                _ => instructions.push(instruction),
+            }
+            if ends_block(instruction) {
+                break;
+            }
+            address += 2;
+        }
+        instructions
+    }
+}
+
+fn ends_block(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Ret
+            | Instruction::Jp(_)
+            | Instruction::Call(_)
+            | Instruction::SeByte(..)
+            | Instruction::SneByte(..)
+            | Instruction::Se(..)
+            | Instruction::Sne(..)
+            | Instruction::JpV0(_)
+            | Instruction::Skp(_)
+            | Instruction::Sknp(_)
+    )
+}
+
+fn new_module() -> Option<JITModule> {
+    let mut flags = settings::builder();
+    settings::Configurable::set(&mut flags, "opt_level", "speed").ok()?;
+    let isa = cranelift_native::builder()
+        .ok()?
+        .finish(settings::Flags::new(flags))
+        .ok()?;
+    Some(JITModule::new(JITBuilder::with_isa(
+        isa,
+        cranelift_module::default_libcall_names(),
+    )))
+}
+
+// chip8::run_frame, with the blocks compiled once hot
+pub fn run_frame(chip8: &mut Chip8, jit: &mut Jit) {
+    chip8::run_frame_with(chip8, |_chip8| {}, |chip8, left| run(chip8, jit, left));
+}
+
+// chip8::step_instruction, with a compiled block stopped after its first instruction
+pub fn step_instruction(chip8: &mut Chip8, jit: &mut Jit) {
+    chip8::step_with(chip8, |chip8| {
+        run(chip8, jit, 1);
+    });
+}
+
+// The blocks from pc, or a single instruction in the interpreter. Returns how many ran.
+fn run(chip8: &mut Chip8, jit: &mut Jit, left: u32) -> u32 {
+    jit.prepare(chip8);
+    let pc = chip8.pc as usize;
+    if pc + 1 >= chip8.memory.len() {
+        return 1;
+    }
+
+    if let Entry::Cold(runs) = jit.entries[pc] {
+        jit.entries[pc] = if runs + 1 >= HOT_RUNS {
+            jit.compile(chip8, pc)
+        } else {
+            Entry::Cold(runs + 1)
+        };
+    }
+
+    let mut ran = 0;
+    // From block to block while they're compiled: none of them draws or waits for a key,
+    // the frame loop has nothing to do in between
+    while let Some(Entry::Compiled { function, .. }) = jit.entries.get(chip8.pc as usize) {
+        let memory = chip8.memory.as_ptr();
+        // The block reads memory, and the fields of chip8 at their offsets, within bounds
+        ran += unsafe { function(chip8, memory, left - ran) };
+        if ran == left {
+            return ran;
+        }
+    }
+    if ran > 0 {
+        return ran;
+    }
+
+    interpret(chip8, jit);
+    1
+}
+
+fn interpret(chip8: &mut Chip8, jit: &mut Jit) {
+    let pc = chip8.pc as usize;
+    let instruction = chip8::decode(u16::from_be_bytes([chip8.memory[pc], chip8.memory[pc + 1]]));
+    chip8.pc = chip8.pc.wrapping_add(2);
+
+    match cache::memory_written(chip8, instruction) {
+        Some((first_written, written)) => {
+            chip8::execute(chip8, instruction);
+            for offset in 0..written {
+                jit.forget((first_written + offset) % chip8.memory.len());
+            }
+        }
+        None => chip8::execute(chip8, instruction),
+    }
+}
+
+// The block as a BlockFunction, None if Cranelift fails to compile it. Before each
+// instruction but the first, it stops if left instructions already ran.
+fn translate(
+    module: &mut JITModule,
+    start: usize,
+    instructions: &[Instruction],
+    chip8: &Chip8,
+) -> Option<BlockFunction> {
+    let pointer_type = module.target_config().pointer_type();
+    let mut context = module.make_context();
+    let signature = &mut context.func.signature;
+    signature.params.push(AbiParam::new(pointer_type));
+    signature.params.push(AbiParam::new(pointer_type));
+    signature.params.push(AbiParam::new(types::I32));
+    signature.returns.push(AbiParam::new(types::I32));
+
+    let mut builder_context = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+    let entry = builder.create_block();
+    builder.append_block_params_for_function_params(entry);
+    // With pc and the number of instructions run
+    let exit = builder.create_block();
+    builder.append_block_param(exit, types::I16);
+    builder.append_block_param(exit, types::I32);
+
+    builder.switch_to_block(entry);
+    builder.seal_block(entry);
+    let params = builder.block_params(entry).to_vec();
+    let left = params[2];
+    let mut translator = Translator {
+        builder,
+        pointer_type,
+        chip8: params[0],
+        memory: params[1],
+        memory_size: chip8.memory.len(),
+        quirks: chip8.quirks,
+        registers: [None; 16],
+        register_i: None,
+    };
+
+    let mut address = start;
+    for (index, instruction) in instructions.iter().enumerate() {
+        let builder = &mut translator.builder;
+        if index > 0 {
+            let next = builder.create_block();
+            let done = builder
+                .ins()
+                .icmp_imm(IntCC::UnsignedLessThanOrEqual, left, index as i64);
+            let pc = builder.ins().iconst(types::I16, address as i64);
+            let count = builder.ins().iconst(types::I32, index as i64);
+            builder.ins().brif(done, exit, &[pc, count], next, &[]);
+            builder.switch_to_block(next);
+            builder.seal_block(next);
+        }
+
+        address += 2;
+        let pc = match translator.instruction(*instruction, address as u16) {
+            Some(pc) => pc,
+            None => translator.builder.ins().iconst(types::I16, address as i64),
+        };
+        if index == instructions.len() - 1 {
+            let builder = &mut translator.builder;
+            let count = builder.ins().iconst(types::I32, instructions.len() as i64);
+            builder.ins().jump(exit, &[pc, count]);
+        }
+    }
+
+    let mut builder = translator.builder;
+    builder.switch_to_block(exit);
+    builder.seal_block(exit);
+    let results = builder.block_params(exit).to_vec();
+    let chip8_pointer = params[0];
+    builder.ins().store(
+        MemFlags::trusted(),
+        results[0],
+        chip8_pointer,
+        offset_of!(Chip8, pc) as i32,
+    );
+    builder.ins().return_(&[results[1]]);
+    builder.finalize();
+
+    let id = module
+        .declare_anonymous_function(&context.func.signature)
+        .ok()?;
+    module.define_function(id, &mut context).ok()?;
+    module.clear_context(&mut context);
+    module.finalize_definitions().ok()?;
+    let code = module.get_finalized_function(id);
+    // Compiled with the signature of BlockFunction
+    Some(unsafe { std::mem::transmute::<*const u8, BlockFunction>(code) })
+}
+
+// chip8::execute in Cranelift IR. Registers and I are stored as soon as they're set, so a
+// block can stop anywhere, and loaded once.
+struct Translator<'a> {
+    builder: FunctionBuilder<'a>,
+    pointer_type: types::Type,
+    chip8: Value,
+    memory: Value,
+    memory_size: usize,
+    quirks: Quirks,
+    registers: [Option<Value>; 16],
+    register_i: Option<Value>,
+}
+
+impl Translator<'_> {
+    // The instruction, pc pointing at next. Returns pc after it, if it can be another.
+    fn instruction(&mut self, instruction: Instruction, next: u16) -> Option<Value> {
+        match instruction {
+            Instruction::Cls => {
+                let one = self.builder.ins().iconst(types::I8, 1);
+                self.store(one, offset_of!(Chip8, needs_clear));
+                None
+            }
+            Instruction::Ret => {
+                let sp = self.load(types::I16, offset_of!(Chip8, sp));
+                let level = self.builder.ins().band_imm(sp, 15);
+                let pc = self.load_stack(level);
+                // sp - 1, wrapped around the 16 levels
+                let sp = self.builder.ins().iadd_imm(sp, 15);
+                let sp = self.builder.ins().band_imm(sp, 15);
+                self.store(sp, offset_of!(Chip8, sp));
+                Some(pc)
+            }
+            Instruction::Jp(nnn) => Some(self.builder.ins().iconst(types::I16, nnn as i64)),
+            Instruction::Call(nnn) => {
+                let sp = self.load(types::I16, offset_of!(Chip8, sp));
+                let sp = self.builder.ins().iadd_imm(sp, 1);
+                let sp = self.builder.ins().band_imm(sp, 15);
+                self.store(sp, offset_of!(Chip8, sp));
+                let next = self.builder.ins().iconst(types::I16, next as i64);
+                let address = self.stack_address(sp);
+                let offset = offset_of!(Chip8, stack) as i32;
+                self.builder
+                    .ins()
+                    .store(MemFlags::trusted(), next, address, offset);
+                Some(self.builder.ins().iconst(types::I16, nnn as i64))
+            }
+            Instruction::SeByte(x, kk) => {
+                let vx = self.register(x);
+                let equal = self.builder.ins().icmp_imm(IntCC::Equal, vx, kk as i64);
+                Some(self.skip_if(equal, next))
+            }
+            Instruction::SneByte(x, kk) => {
+                let vx = self.register(x);
+                let different = self.builder.ins().icmp_imm(IntCC::NotEqual, vx, kk as i64);
+                Some(self.skip_if(different, next))
+            }
+            Instruction::Se(x, y) => {
+                let (vx, vy) = (self.register(x), self.register(y));
+                let equal = self.builder.ins().icmp(IntCC::Equal, vx, vy);
+                Some(self.skip_if(equal, next))
+            }
+            Instruction::LdByte(x, kk) => {
+                let kk = self.builder.ins().iconst(types::I8, kk as i64);
+                self.set_register(x, kk);
+                None
+            }
+            Instruction::AddByte(x, kk) => {
+                let vx = self.register(x);
+                let kk = self.builder.ins().iconst(types::I8, kk as i64);
+                let sum = self.builder.ins().iadd(vx, kk);
+                self.set_register(x, sum);
+                None
+            }
+            Instruction::Ld(x, y) => {
+                let vy = self.register(y);
+                self.set_register(x, vy);
+                None
+            }
+            Instruction::Or(x, y) | Instruction::And(x, y) | Instruction::Xor(x, y) => {
+                let (vx, vy) = (self.register(x), self.register(y));
+                let result = match instruction {
+                    Instruction::Or(..) => self.builder.ins().bor(vx, vy),
+                    Instruction::And(..) => self.builder.ins().band(vx, vy),
+                    _ => self.builder.ins().bxor(vx, vy),
+                };
+                self.set_register(x, result);
+                if self.quirks.vf_reset {
+                    let zero = self.builder.ins().iconst(types::I8, 0);
+                    self.set_register(0xF, zero);
+                }
+                None
+            }
+            Instruction::Add(x, y) => {
+                let (vx, vy) = (self.register(x), self.register(y));
+                let vx = self.builder.ins().uextend(types::I16, vx);
+                let vy = self.builder.ins().uextend(types::I16, vy);
+                let result = self.builder.ins().iadd(vx, vy);
+                let carry = self
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::UnsignedGreaterThan, result, 0xFF);
+                let result = self.builder.ins().ireduce(types::I8, result);
+                self.set_register(x, result);
+                self.set_register(0xF, carry);
+                None
+            }
+            Instruction::Sub(x, y) | Instruction::Subn(x, y) => {
+                let (mut vx, mut vy) = (self.register(x), self.register(y));
+                if let Instruction::Subn(..) = instruction {
+                    std::mem::swap(&mut vx, &mut vy);
+                }
+                let result = self.builder.ins().isub(vx, vy);
+                let not_borrow = self
+                    .builder
+                    .ins()
+                    .icmp(IntCC::UnsignedGreaterThanOrEqual, vx, vy);
+                self.set_register(x, result);
+                self.set_register(0xF, not_borrow);
+                None
+            }
+            Instruction::Shr(x, y) => {
+                let value = self.register(if self.quirks.shift_vx { x } else { y });
+                let result = self.builder.ins().ushr_imm(value, 1);
+                let shifted_out = self.builder.ins().band_imm(value, 1);
+                self.set_register(x, result);
+                self.set_register(0xF, shifted_out);
+                None
+            }
+            Instruction::Shl(x, y) => {
+                let value = self.register(if self.quirks.shift_vx { x } else { y });
+                let result = self.builder.ins().ishl_imm(value, 1);
+                let shifted_out = self.builder.ins().ushr_imm(value, 7);
+                self.set_register(x, result);
+                self.set_register(0xF, shifted_out);
+                None
+            }
+            Instruction::Sne(x, y) => {
+                let (vx, vy) = (self.register(x), self.register(y));
+                let different = self.builder.ins().icmp(IntCC::NotEqual, vx, vy);
+                Some(self.skip_if(different, next))
+            }
+            Instruction::LdI(nnn) => {
+                let nnn = self.builder.ins().iconst(types::I16, nnn as i64);
+                self.set_register_i(nnn);
+                None
+            }
+            Instruction::JpV0(nnn) => {
+                let offset_register = if self.quirks.jump_vx { nnn >> 8 } else { 0 };
+                let offset = self.register(offset_register as u8);
+                let offset = self.builder.ins().uextend(types::I16, offset);
+                Some(self.builder.ins().iadd_imm(offset, nnn as i64))
+            }
+            Instruction::Rnd(x, kk) => {
+                // chip8::next_random_byte
+                let mut state = self.load(types::I32, offset_of!(Chip8, rng_state));
+                for (shift_left, amount) in [(true, 13), (false, 17), (true, 5)].iter() {
+                    let shifted = if *shift_left {
+                        self.builder.ins().ishl_imm(state, *amount)
+                    } else {
+                        self.builder.ins().ushr_imm(state, *amount)
+                    };
+                    state = self.builder.ins().bxor(state, shifted);
+                }
+                self.store(state, offset_of!(Chip8, rng_state));
+                let byte = self.builder.ins().ushr_imm(state, 24);
+                let byte = self.builder.ins().ireduce(types::I8, byte);
+                let result = self.builder.ins().band_imm(byte, kk as i64);
+                self.set_register(x, result);
+                None
+            }
+            Instruction::Skp(x) | Instruction::Sknp(x) => {
+                let vx = self.register(x);
+                let key = self.builder.ins().band_imm(vx, 0xF);
+                let key = self.builder.ins().uextend(self.pointer_type, key);
+                let address = self.builder.ins().iadd(self.chip8, key);
+                let offset = offset_of!(Chip8, keys) as i32;
+                let pressed =
+                    self.builder
+                        .ins()
+                        .load(types::I8, MemFlags::trusted(), address, offset);
+                let condition = match instruction {
+                    Instruction::Skp(_) => IntCC::NotEqual,
+                    _ => IntCC::Equal,
+                };
+                let skip = self.builder.ins().icmp_imm(condition, pressed, 0);
+                Some(self.skip_if(skip, next))
+            }
+            Instruction::LdVxDt(x) => {
+                let timer = self.load(types::I8, offset_of!(Chip8, timer_delay));
+                self.set_register(x, timer);
+                None
+            }
+            Instruction::LdDtVx(x) => {
+                let vx = self.register(x);
+                self.store(vx, offset_of!(Chip8, timer_delay));
+                None
+            }
+            Instruction::LdStVx(x) => {
+                let vx = self.register(x);
+                self.store(vx, offset_of!(Chip8, timer_sound));
+                None
+            }
+            Instruction::AddI(x) => {
+                let vx = self.register(x);
+                let vx = self.builder.ins().uextend(types::I16, vx);
+                let i = self.register_i();
+                let sum = self.builder.ins().iadd(i, vx);
+                self.set_register_i(sum);
+                None
+            }
+            Instruction::LdF(x) => {
+                let vx = self.register(x);
+                let digit = self.builder.ins().band_imm(vx, 0xF);
+                let digit = self.builder.ins().uextend(types::I16, digit);
+                let address = self.builder.ins().imul_imm(digit, 5);
+                self.set_register_i(address);
+                None
+            }
+            Instruction::LdVxI(x) => {
+                let i = self.register_i();
+                let first = self.builder.ins().uextend(types::I32, i);
+                for register in 0..=x {
+                    // chip8::address_from_i
+                    let address = self.builder.ins().iadd_imm(first, register as i64);
+                    let address = self
+                        .builder
+                        .ins()
+                        .urem_imm(address, self.memory_size as i64);
+                    let address = self.builder.ins().uextend(self.pointer_type, address);
+                    let address = self.builder.ins().iadd(self.memory, address);
+                    let value = self
+                        .builder
+                        .ins()
+                        .load(types::I8, MemFlags::trusted(), address, 0);
+                    self.set_register(register, value);
+                }
+                if self.quirks.load_store_increment_i {
+                    let i = self.builder.ins().iadd_imm(i, x as i64 + 1);
+                    self.set_register_i(i);
+                }
+                None
+            }
+            Instruction::Nop => None,
+            Instruction::Drw(..)
+            | Instruction::LdVxK(_)
+            | Instruction::LdB(_)
+            | Instruction::LdIVx(_) => unreachable!("left to the interpreter"),
+        }
+    }
+
+    fn skip_if(&mut self, condition: Value, next: u16) -> Value {
+        let skipped = self
+            .builder
+            .ins()
+            .iconst(types::I16, next.wrapping_add(2) as i64);
+        let next = self.builder.ins().iconst(types::I16, next as i64);
+        self.builder.ins().select(condition, skipped, next)
+    }
+
+    fn register(&mut self, x: u8) -> Value {
+        if let Some(value) = self.registers[x as usize] {
+            return value;
+        }
+        let value = self.load(types::I8, offset_of!(Chip8, registers) + x as usize);
+        self.registers[x as usize] = Some(value);
+        value
+    }
+
+    fn set_register(&mut self, x: u8, value: Value) {
+        self.store(value, offset_of!(Chip8, registers) + x as usize);
+        self.registers[x as usize] = Some(value);
+    }
+
+    fn register_i(&mut self) -> Value {
+        if let Some(value) = self.register_i {
+            return value;
+        }
+        let value = self.load(types::I16, offset_of!(Chip8, register_i));
+        self.register_i = Some(value);
+        value
+    }
+
+    fn set_register_i(&mut self, value: Value) {
+        self.store(value, offset_of!(Chip8, register_i));
+        self.register_i = Some(value);
+    }
+
+    // The address of stack[level], minus the offset of the stack in chip8
+    fn stack_address(&mut self, level: Value) -> Value {
+        let level = self.builder.ins().uextend(self.pointer_type, level);
+        let offset = self.builder.ins().ishl_imm(level, 1);
+        self.builder.ins().iadd(self.chip8, offset)
+    }
+
+    fn load_stack(&mut self, level: Value) -> Value {
+        let address = self.stack_address(level);
+        let offset = offset_of!(Chip8, stack) as i32;
+        self.builder
+            .ins()
+            .load(types::I16, MemFlags::trusted(), address, offset)
+    }
+
+    // The field of chip8 at offset
+    fn load(&mut self, field_type: types::Type, offset: usize) -> Value {
+        self.builder
+            .ins()
+            .load(field_type, MemFlags::trusted(), self.chip8, offset as i32)
+    }
+
+    fn store(&mut self, value: Value, offset: usize) {
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, self.chip8, offset as i32);
+    }
+}
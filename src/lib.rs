@@ -0,0 +1,18 @@
+// Emulator core, without any windowing or audio dependency, so it can be embedded:
+// the native frontends in main.rs, the browser through wasm-bindgen (see wasm.rs)
+// and RetroArch through the libretro crate.
+
+pub mod assembler;
+pub mod cache;
+pub mod cheat;
+pub mod chip8;
+pub mod display;
+#[cfg(all(feature = "jit", not(target_arch = "wasm32")))]
+pub mod jit;
+pub mod palette;
+pub mod phosphor;
+pub mod state;
+pub mod tone;
+
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
@@ -2,98 +2,178 @@
 // https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
 // https://github.com/nannou-org/nannou
 
-mod assembler;
-
+mod browser;
+mod capture;
+mod database;
+mod headless;
+mod options;
+mod playback;
+mod recent;
+mod remote;
+mod renderer;
+mod rom;
+mod script;
+mod tui;
+mod watcher;
+mod wav;
+
+use browser::Browser;
+use capture::Recorder;
+use chip_8::cheat::{self, Cheat, Filter, Search};
+use chip_8::chip8::{self, Chip8, HEIGHT, WIDTH};
+use chip_8::{assembler, palette, phosphor};
+use database::{Database, RomInfo};
 use nannou::prelude::*;
+use options::{ReloadMode, Scaling};
+use palette::Palette;
+use phosphor::Phosphor;
+use playback::{Frames, Playback};
+use renderer::DisplayTexture;
+use script::Script;
+use std::collections::HashMap;
+use watcher::SourceWatcher;
+use wav::WavRecorder;
 
-const WIDTH: u8 = 64;
-const HEIGHT: u8 = 32;
-const SCALE: u8 = 10;
-const WINDOW_WIDTH: u32 = WIDTH as u32 * SCALE as u32;
-const WINDOW_HEIGHT: u32 = HEIGHT as u32 * SCALE as u32;
 const VOLUME: f32 = 0.02;
 const WAVE_LENGTH: u32 = 440;
 
-struct Chip8 {
-    display: [u8; WIDTH as usize * HEIGHT as usize],
+struct Model {
+    chip8: Chip8,
 
-    // 0x200 to 0xFFF : Chip-8 program / data
-    // 0x000 to 0x1FF : Interpreter (do not use)
-    memory: [u8; 4096],
+    // Anti-flicker filter, what the view actually draws
+    phosphor: Phosphor,
+    display_texture: DisplayTexture,
 
-    /*
-    1 2	3 C  =>  1 2 3 4
-    4 5	6 D  =>  q w e r
-    7 8	9 E  =>  a s d t
-    A 0	B F  =>  z x c v
-    */
-    keys: [bool; 16],
+    // Pause, fast-forward and slow motion
+    playback: Playback,
 
-    // V0 to VF
-    registers: [u8; 16],
+    // Window title without the playback status
+    title: String,
 
-    // Store memory addresses
-    // Only 12 first lower bits are used
-    register_i: u16,
+    // Thread channel. Send true to play sound, false to stop it
+    audio_control_channel: std::sync::mpsc::Sender<bool>,
 
-    // Decrement at 60hz
-    timer_sound: u8, // ST
-    timer_delay: u8, // DT
+    // State variable for sound
+    audio_is_playing: bool,
 
-    // Currently executing address
-    pc: u16,
+    // Buzzer capture, fed once per emulated frame
+    wav_recorder: Option<WavRecorder>,
 
-    // Point to the topmost level of the stack
-    sp: u16,
+    // Chip-8 keys for the arrows, Space and Enter, from the ROM database
+    key_bindings: HashMap<String, u8>,
 
-    // Store the address that the interpreter shoud return to when finished with a subroutine.
-    // Chip-8 allows for up to 16 levels of nested subroutines.
-    stack: [u16; 16],
+    // Why the program couldn't be loaded, shown instead of the display
+    load_error: Option<String>,
 
-    // Transfer clear request from cpu to update
-    needs_clear: bool,
+    // Options from the command line and config file, before the ROM's own settings
+    // were filled in. Programs opened from the window start from these.
+    base_options: options::Options,
 
-    // Request a cpu hold until a key is pressed. Value of key (0x0..0xF) is stored in register
-    hold_for_key: Option<u8>,
+    // Path of the running program, reloaded by a hard reset (F6)
+    program: String,
 
-    // Thread channel. Send true to play sound, false to stop it
-    audio_control_channel: std::sync::mpsc::Sender<bool>,
+    // Where the program starts, for soft resets (F5)
+    load_address: u16,
 
-    // State variable for sound
-    audio_is_playing: bool,
+    // ROM menu, shown instead of the display while set (F2)
+    browser: Option<Browser>,
+    // Of the selected entry
+    thumbnail: Option<wgpu::Texture>,
+
+    // .cp8asm programs are reassembled when one of their sources is saved
+    source_watcher: Option<SourceWatcher>,
+    reload_mode: ReloadMode,
+
+    // Remote control requests, with --remote
+    remote_requests: Option<std::sync::mpsc::Receiver<remote::Request>>,
+
+    // --cheats, kept across program changes, and the memory search, listed with F7
+    cheats: Vec<Cheat>,
+    cheat_search: Option<Search>,
+    show_cheats: bool,
+
+    // --script, kept across program changes
+    script: Option<Script>,
+
+    // Most recent first, listed with F4
+    recent_files: Vec<String>,
+    show_recent_files: bool,
+
+    // Cycled with F1
+    palettes: Vec<Palette>,
+    palette_index: usize,
+
+    scaling: Scaling,
+
+    // Space between display cells, in window pixels
+    pixel_gap: f32,
+
+    // Display recording, toggled with F10
+    recorder: Option<Recorder>,
+    record_format: String,
+
+    // Screenshots (F9) and recordings
+    capture_scale: u32,
+    capture_dir: String,
 }
 
 fn main() {
-    nannou::app(model).update(update).view(view).run();
+    let options = options::from_args();
+
+    if options.headless {
+        headless::run(options);
+    } else if options.tui {
+        tui::run(options);
+    } else {
+        nannou::app(model)
+            .update(update)
+            .view(view)
+            .exit(exit)
+            .run();
+    }
 }
 
-fn model(app: &App) -> Chip8 {
-    let _window = app
+fn model(app: &App) -> Model {
+    let base_options = options::from_args();
+    let mut options = base_options.clone();
+    let mut recent_files = recent::load();
+
+    // The window still opens when the program can't be loaded, to show why.
+    // When browsing, nothing runs until a program is picked from the menu.
+    let (mut chip8, rom_info, mut load_error) = if options.browse_dir.is_some() {
+        (Chip8::new(&[]), None, None)
+    } else {
+        match load_chip8(&mut options) {
+            Ok((chip8, rom_info)) => {
+                println!("===================================");
+                println!("Starting emulation of {}.", options.program);
+                recent::add(&mut recent_files, &options.program);
+                (chip8, rom_info, None)
+            }
+            Err(err) => {
+                println!("{}", err);
+                (Chip8::new(&[]), None, Some(err))
+            }
+        }
+    };
+
+    let title = window_title(rom_info.as_ref());
+    let window = app
         .new_window()
-        .title("Chip-8")
-        .size(WINDOW_WIDTH, WINDOW_HEIGHT)
+        .title(&title)
+        .size(WIDTH as u32 * options.scale, HEIGHT as u32 * options.scale)
+        .min_size(WIDTH as u32, HEIGHT as u32)
+        .resizable(true)
         .key_pressed(key_pressed)
         .key_released(key_released)
+        .dropped_file(dropped_file)
         .build()
         .unwrap();
 
-    let mut memory = [0; 4096];
-
-    let digit_sprites = get_digit_sprites();
-
-    for i in 0..digit_sprites.len() {
-        memory[0x0 + i] = digit_sprites[i];
-    }
-
-    // let instructions = load_rom_from_file("roms/games/Pong 2 (Pong hack) [David Winter, 1997].ch8");
-    let instructions = assembler::assemble("assembly_programs/clock.cp8asm");
-
-    println!("===================================");
-    println!("Starting emulation with {} opcodes.", instructions.len());
-
-    for i in 0..instructions.len() {
-        memory[0x200 + i] = instructions[i];
+    if options.fullscreen {
+        app.window(window).unwrap().set_fullscreen(true);
     }
+    let display_texture = DisplayTexture::new(app.window(window).unwrap().swap_chain_device());
 
     let (tx, rx) = std::sync::mpsc::channel();
 
@@ -114,508 +194,708 @@ fn model(app: &App) -> Chip8 {
         }
     });
 
-    Chip8 {
-        display: [0; WIDTH as usize * HEIGHT as usize],
-        memory,
-        keys: [false; 16],
-        registers: [0; 16],
-        register_i: 0,
-        timer_sound: 0,
-        timer_delay: 0,
-        pc: 0x200,
-        sp: 0,
-        stack: [0; 16],
-        needs_clear: false,
-        hold_for_key: None,
+    chip8::seed_rng(&mut chip8, options.seed.unwrap_or_else(rand::random));
+
+    let browser = options.browse_dir.as_ref().and_then(|directory| {
+        match open_database(&options).and_then(|database| Browser::scan(directory, database)) {
+            Ok(browser) => Some(browser),
+            Err(err) => {
+                println!("{}", err);
+                load_error = Some(err);
+                None
+            }
+        }
+    });
+    let thumbnail = load_thumbnail(app, browser.as_ref());
+
+    let remote_requests =
+        options
+            .remote_address
+            .as_ref()
+            .and_then(|address| match remote::start(address) {
+                Ok(requests) => {
+                    println!("Listening on {}", address);
+                    Some(requests)
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    None
+                }
+            });
+
+    let cheats = load_cheats(&options).unwrap_or_else(|err| {
+        println!("{}", err);
+        load_error = Some(err);
+        vec![]
+    });
+
+    let script = load_script(&options).unwrap_or_else(|err| {
+        println!("{}", err);
+        load_error = Some(err);
+        None
+    });
+
+    let (palettes, palette_index) = create_palettes(&options);
+
+    let recorder = options.record_path.as_ref().map(|filepath| {
+        create_recorder(filepath, &palettes[palette_index], options.capture_scale)
+            .unwrap_or_else(|err| exit_with_error(&err))
+    });
+
+    Model {
+        chip8,
+        phosphor: Phosphor::new(options.persistence),
+        display_texture,
+        playback: Playback::new(options.fast_forward),
+        title,
         audio_control_channel: tx,
         audio_is_playing: false,
+        key_bindings: rom_info.map(|rom_info| rom_info.keys).unwrap_or_default(),
+        load_error,
+        program: options.program.clone(),
+        load_address: options.load_address.unwrap_or(chip8::LOAD_ADDRESS),
+        base_options,
+        browser,
+        thumbnail,
+        source_watcher: watch_sources(&options.program),
+        reload_mode: options.reload_mode,
+        remote_requests,
+        cheats,
+        cheat_search: None,
+        show_cheats: false,
+        script,
+        recent_files,
+        show_recent_files: false,
+        wav_recorder: options.wav_path.as_ref().map(|filepath| {
+            create_wav_recorder(filepath).unwrap_or_else(|err| exit_with_error(&err))
+        }),
+        palettes,
+        palette_index,
+        scaling: options.scaling,
+        pixel_gap: options.pixel_gap as f32,
+        recorder,
+        record_format: options.record_format,
+        capture_scale: options.capture_scale,
+        capture_dir: options.capture_dir,
     }
 }
 
-fn update(_app: &App, chip8: &mut Chip8, _update: Update) {
-    if chip8.timer_delay > 0 {
-        chip8.timer_delay -= 1;
-    }
-    if chip8.timer_sound > 0 {
-        chip8.timer_sound -= 1;
+fn window_title(rom_info: Option<&RomInfo>) -> String {
+    match rom_info {
+        Some(rom_info) => format!("Chip-8 - {}", rom_info.title_line()),
+        None => String::from("Chip-8"),
     }
+}
 
-    if chip8.hold_for_key.is_none() {
-        // 500hz / 60fps = ~8 instructions per frame
-        for _i in 0..8 {
-            if chip8.pc < (chip8.memory.len() as u16 - 2) {
-                run_next_cpu_cycle(chip8);
-            }
+// The title with the playback mode, e.g. "Chip-8 - Clock [Paused]"
+fn update_title(app: &App, model: &Model) {
+    let title = match model.playback.status() {
+        Some(status) => format!("{} [{}]", model.title, status),
+        None => model.title.clone(),
+    };
+    app.main_window().set_title(&title);
+}
+
+// Replace the running program, with the options of the command line and the new ROM's own.
+// On errors the display is blanked to show the error.
+fn open_program(app: &App, model: &mut Model, filepath: &str) {
+    let mut options = model.base_options.clone();
+    options.program = String::from(filepath);
+
+    let rom_info = match load_chip8(&mut options) {
+        Ok((chip8, rom_info)) => {
+            model.chip8 = chip8;
+            model.load_error = None;
+            model.cheat_search = None;
+            recent::add(&mut model.recent_files, filepath);
+            println!("Starting emulation of {}.", filepath);
+            rom_info
+        }
+        Err(err) => {
+            println!("{}", err);
+            model.chip8 = Chip8::new(&[]);
+            model.load_error = Some(err);
+            None
+        }
+    };
+    chip8::seed_rng(&mut model.chip8, options.seed.unwrap_or_else(rand::random));
+
+    model.title = window_title(rom_info.as_ref());
+    update_title(app, model);
+    model.key_bindings = rom_info.map(|rom_info| rom_info.keys).unwrap_or_default();
+    let (palettes, palette_index) = create_palettes(&options);
+    model.palettes = palettes;
+    model.palette_index = palette_index;
+    model.source_watcher = watch_sources(&options.program);
+    model.program = options.program;
+    model.load_address = options.load_address.unwrap_or(chip8::LOAD_ADDRESS);
+}
 
-            if !chip8.audio_is_playing && chip8.timer_sound > 0 {
-                chip8.audio_is_playing = true;
-                chip8.audio_control_channel.send(true).unwrap();
-            } else if chip8.audio_is_playing && chip8.timer_sound == 0 {
-                chip8.audio_is_playing = false;
-                chip8.audio_control_channel.send(false).unwrap();
+// Show the ROM menu, for the --browse directory or the one of the running program
+fn open_browser(app: &App, model: &mut Model) {
+    let directory = model.base_options.browse_dir.clone().unwrap_or_else(|| {
+        let parent = std::path::Path::new(&model.program).parent();
+        match parent.map(|parent| parent.to_string_lossy().into_owned()) {
+            Some(parent) if !parent.is_empty() => parent,
+            _ => String::from("."),
+        }
+    });
+
+    match open_database(&model.base_options)
+        .and_then(|database| Browser::scan(&directory, database))
+    {
+        Ok(mut browser) => {
+            // Start on the running program
+            if let Some(index) = browser.entries.iter().position(|entry| {
+                std::path::Path::new(&entry.filepath) == std::path::Path::new(&model.program)
+            }) {
+                browser.select(index);
             }
+            model.thumbnail = load_thumbnail(app, Some(&browser));
+            model.browser = Some(browser);
+        }
+        Err(err) => println!("{}", err),
+    }
+}
+
+fn load_thumbnail(app: &App, browser: Option<&Browser>) -> Option<wgpu::Texture> {
+    let thumbnail_path = browser?.selected_entry()?.thumbnail_path.as_ref()?;
+    match wgpu::Texture::from_path(app, thumbnail_path) {
+        Ok(texture) => Some(texture),
+        Err(err) => {
+            println!(
+                "Error loading thumbnail at {} : {}",
+                thumbnail_path.display(),
+                err
+            );
+            None
+        }
+    }
+}
 
-            if chip8.needs_clear {
-                chip8.display = [0; WIDTH as usize * HEIGHT as usize];
-                chip8.needs_clear = false;
+// Up and Down pick a program, Enter opens it, Escape goes back to the running one
+fn browser_key_pressed(app: &App, model: &mut Model, key: Key) {
+    let browser = match model.browser.as_mut() {
+        Some(browser) => browser,
+        None => return,
+    };
+
+    match key {
+        Key::Up => browser.select_previous(),
+        Key::Down => browser.select_next(),
+        Key::Return => {
+            if let Some(entry) = browser.selected_entry() {
+                let filepath = entry.filepath.clone();
+                model.browser = None;
+                model.thumbnail = None;
+                open_program(app, model, &filepath);
             }
+            return;
         }
+        Key::Escape | Key::F2 => {
+            model.browser = None;
+            model.thumbnail = None;
+            return;
+        }
+        _ => return,
     }
+    model.thumbnail = load_thumbnail(app, model.browser.as_ref());
 }
 
-fn view(app: &App, chip8: &Chip8, frame: Frame) {
-    frame.clear(BLACK);
-    let draw = app.draw();
+// Candidates listed, and frozen by Enter, once the search narrowed them down to this many
+const MAX_CHEAT_CANDIDATES: usize = 8;
 
-    for i in 0..chip8.display.len() {
-        let px = chip8.display[i];
-        if px == 1 {
-            let display_x = i % 64;
-            let display_y = i / 64;
+// 1-9 toggle cheats, S starts a memory search that E (equal), C (changed), Up (increased)
+// and Down (decreased) narrow down, Enter freezes the candidates left. Returns false for
+// the keys the panel doesn't use.
+fn cheats_key_pressed(model: &mut Model, key: Key) -> bool {
+    if let Some(index) = number_key_index(key) {
+        if let Some(cheat) = model.cheats.get_mut(index) {
+            cheat.enabled = !cheat.enabled;
+        }
+        return true;
+    }
 
-            let window_x = -(WINDOW_WIDTH as f32) / 2.0
-                + display_x as f32 * SCALE as f32
-                + (SCALE as f32) / 2.0;
-            let window_y = (WINDOW_HEIGHT as f32) / 2.0
-                - (display_y as f32) * SCALE as f32
-                - (SCALE as f32) / 2.0;
+    let filter = match key {
+        Key::S => {
+            model.cheat_search = Some(Search::new(&model.chip8));
+            return true;
+        }
+        Key::E => Filter::Equal,
+        Key::C => Filter::Changed,
+        Key::Up => Filter::Increased,
+        Key::Down => Filter::Decreased,
+        Key::Return => {
+            freeze_cheat_candidates(model);
+            return true;
+        }
+        Key::Escape | Key::F7 => {
+            model.show_cheats = false;
+            return true;
+        }
+        _ => return false,
+    };
+    if let Some(search) = model.cheat_search.as_mut() {
+        search.filter(&model.chip8, filter);
+    }
+    true
+}
 
-            draw.rect()
-                .x_y(window_x, window_y)
-                .w_h(SCALE as f32, SCALE as f32)
-                .color(WHITE);
+// A cheat per candidate, at its current value. Printed in the cheat file format, to keep.
+fn freeze_cheat_candidates(model: &mut Model) {
+    let candidates = match model.cheat_search.as_ref() {
+        Some(search) if search.candidates().len() <= MAX_CHEAT_CANDIDATES => search.candidates(),
+        _ => return,
+    };
+    for address in candidates {
+        // From before a hot reload changed the memory size
+        let value = match model.chip8.memory.get(*address as usize) {
+            Some(value) => *value,
+            None => continue,
+        };
+        let cheat = Cheat {
+            name: format!("Found {:#05X}", address),
+            enabled: true,
+            codes: vec![cheat::Code {
+                condition: None,
+                address: *address,
+                value,
+            }],
+        };
+        println!("{}: {}", cheat.name, cheat.codes[0]);
+        model.cheats.push(cheat);
+    }
+    model.cheat_search = None;
+}
+
+// Lines of the F7 panel
+fn cheats_panel(model: &Model) -> Vec<String> {
+    let mut lines = vec![String::from("Cheats (1-9 to toggle, Esc to close)")];
+    if model.cheats.is_empty() {
+        lines.push(String::from("None, see --cheats"));
+    }
+    for (i, cheat) in model.cheats.iter().enumerate().take(9) {
+        let check = if cheat.enabled { "x" } else { " " };
+        lines.push(format!("{}. [{}] {}", i + 1, check, cheat.name));
+    }
+
+    lines.push(String::new());
+    lines.push(String::from(
+        "Search: S new, E equal, C changed, Up increased, Down decreased",
+    ));
+    match model.cheat_search.as_ref() {
+        None => {}
+        Some(search) if search.candidates().len() <= MAX_CHEAT_CANDIDATES => {
+            let candidates: Vec<String> = search
+                .candidates()
+                .iter()
+                .map(|address| {
+                    let value = model.chip8.memory.get(*address as usize);
+                    format!("{:#05X}={}", address, value.copied().unwrap_or_default())
+                })
+                .collect();
+            lines.push(format!(
+                "{} candidates, Enter to freeze: {}",
+                candidates.len(),
+                candidates.join(" ")
+            ));
         }
+        Some(search) => lines.push(format!("{} candidates", search.candidates().len())),
     }
+    lines
+}
 
-    draw.to_frame(app, &frame).unwrap();
+// 0 to 8 for the 1 to 9 keys, for the lists of the recent files and cheats
+fn number_key_index(key: Key) -> Option<usize> {
+    match key {
+        Key::Key1 => Some(0),
+        Key::Key2 => Some(1),
+        Key::Key3 => Some(2),
+        Key::Key4 => Some(3),
+        Key::Key5 => Some(4),
+        Key::Key6 => Some(5),
+        Key::Key7 => Some(6),
+        Key::Key8 => Some(7),
+        Key::Key9 => Some(8),
+        _ => None,
+    }
 }
 
-fn run_next_cpu_cycle(chip8: &mut Chip8) {
-    // println!("PC: {}", chip8.pc);
-    let opcode_byte1 = chip8.memory[chip8.pc as usize];
-    let opcode_byte2 = chip8.memory[(chip8.pc + 1) as usize];
-    let opcode: u16 = ((opcode_byte1 as u16) << 8) | (opcode_byte2 as u16);
-    // println!("Opcode at {}: {:#018b} ({:#x})", chip8.pc, opcode, opcode);
-    chip8.pc += 2;
-
-    let nnn: u16 = opcode & 0x0FFF;
-    let n: u8 = (opcode & 0x000F) as u8;
-    let x: u8 = opcode_byte1 & 0x0f;
-    let y: u8 = opcode_byte2 >> 4;
-    let kk: u8 = (opcode & 0x00FF) as u8;
-
-    match opcode & 0xF000 {
-        0x0000 => {
-            match opcode {
-                // 00E0 - CLS
-                0x00E0 => {
-                    // Clear the display
-                    chip8.needs_clear = true;
-                }
-                // 00EE - RET
-                0x00EE => {
-                    // Return from a subroutine
-                    chip8.pc = chip8.stack[chip8.sp as usize];
-                    chip8.sp -= 1;
-                }
-                // 0nnn - SYS addr (ignored)
-                _ => {
-                    //Jump to a machine code routine at nnn.
-                    // This instruction is only used on the old computers on which Chip-8 was originally implemented. It is ignored by modern interpreters.
-                }
+fn watch_sources(program: &str) -> Option<SourceWatcher> {
+    if !program.ends_with(".cp8asm") {
+        return None;
+    }
+    // The includes are only known once the source assembles, until then only it is watched
+    let sources = assembler::assemble_with_sources(program)
+        .map(|(_instructions, sources)| sources)
+        .unwrap_or_else(|_| vec![String::from(program)]);
+    Some(SourceWatcher::new(sources))
+}
+
+// Reassemble the program after an edit. Errors are shown over the display while the
+// previous code keeps running, until the next save fixes them.
+fn hot_reload(model: &mut Model) {
+    let instructions = match assembler::assemble_with_sources(&model.program) {
+        Ok((instructions, sources)) => {
+            if let Some(source_watcher) = model.source_watcher.as_mut() {
+                source_watcher.set_files(sources);
             }
+            instructions
         }
-        // 1nnn - JP addr
-        0x1000 => {
-            // Jump to location nnn
-            chip8.pc = nnn;
-        }
-        // 2nnn - CALL addr
-        0x2000 => {
-            // Call subroutine at nnn
-            chip8.sp += 1;
-            chip8.stack[chip8.sp as usize] = chip8.pc;
-            chip8.pc = nnn;
-        }
-        // 3xkk - SE Vx, byte
-        0x3000 => {
-            // Skip next instruction if Vx = kk
-            if chip8.registers[x as usize] == kk {
-                chip8.pc += 2;
-            }
+        Err(err) => {
+            println!("Error assembling {}", err);
+            model.load_error = Some(format!("Error assembling {}", err));
+            return;
         }
-        // 4xkk - SNE Vx, byte
-        0x4000 => {
-            // Skip next instruction if Vx != kk
-            if chip8.registers[x as usize] != kk {
-                chip8.pc += 2;
-            }
+    };
+
+    let memory_size = model.chip8.memory.len();
+    let result = match model.reload_mode {
+        ReloadMode::Restart => {
+            Chip8::load(&instructions, model.load_address, memory_size).map(|mut chip8| {
+                chip8.quirks = model.chip8.quirks;
+                chip8.cycles_per_frame = model.chip8.cycles_per_frame;
+                chip8.rng_state = model.chip8.rng_state;
+                model.chip8 = chip8;
+            })
         }
-        // 5xy0 - SE Vx, Vy
-        0x5000 => {
-            // Skip next instruction if Vx = Vy
-            if chip8.registers[x as usize] == chip8.registers[y as usize] {
-                chip8.pc += 2;
-            }
+        ReloadMode::Patch => {
+            chip8::patch_program(&mut model.chip8, model.load_address, &instructions)
         }
-        // 6xkk - LD Vx, byte
-        0x6000 => {
-            // Set Vx = kk
-            chip8.registers[x as usize] = kk;
-        }
-        // 7xkk - ADD Vx, byte
-        0x7000 => {
-            // Set Vx = Vx + kk
-            let result = chip8.registers[x as usize] as u16 + kk as u16;
-            chip8.registers[x as usize] = (result & 0xFF) as u8
-        }
-        0x8000 => {
-            match opcode & 0x000F {
-                // 8xy0 - LD Vx, Vy
-                0x0 => {
-                    // Set Vx = Vy
-                    chip8.registers[x as usize] = chip8.registers[y as usize];
-                }
-                // 8xy1 - OR Vx, Vy
-                0x1 => {
-                    // Set Vx = Vx OR Vy
-                    chip8.registers[x as usize] |= chip8.registers[y as usize];
-                }
-                // 8xy2 - AND Vx, Vy
-                0x2 => {
-                    // Set Vx = Vx AND Vy
-                    chip8.registers[x as usize] &= chip8.registers[y as usize];
-                }
-                // 8xy3 - XOR Vx, Vy
-                0x3 => {
-                    // Set Vx = Vx XOR Vy
-                    chip8.registers[x as usize] ^= chip8.registers[y as usize];
-                }
-                // 8xy4 - ADD Vx, Vy
-                0x4 => {
-                    // Set Vx = Vx + Vy, set VF = carry
-                    let result =
-                        (chip8.registers[x as usize] as u16) + (chip8.registers[y as usize] as u16);
-                    chip8.registers[x as usize] = (result & 0xFF) as u8;
-                    chip8.registers[0xF] = if result > 0xFF { 1 } else { 0 }
-                }
-                // 8xy5 - SUB Vx, Vy
-                0x5 => {
-                    // Set Vx = Vx - Vy, set VF = NOT borrow
-                    chip8.registers[0xF] =
-                        if chip8.registers[x as usize] > chip8.registers[y as usize] {
-                            1
-                        } else {
-                            0
-                        };
-                    chip8.registers[x as usize] =
-                        match chip8.registers[x as usize].checked_sub(chip8.registers[y as usize]) {
-                            Some(n) => n,
-                            None => 0,
-                        }
-                }
-                // 8xy6 - SHR Vx {, Vy}
-                0x6 => {
-                    // Set Vx = Vx SHR 1
-                    chip8.registers[0xF] = chip8.registers[x as usize] & 0b00000001;
-                    chip8.registers[x as usize] /= 2;
-                }
-                // 8xy7 - SUBN Vx, Vy
-                0x7 => {
-                    // Set Vx = Vy - Vx, set VF = NOT borrow
-                    chip8.registers[0xF] =
-                        if chip8.registers[y as usize] > chip8.registers[x as usize] {
-                            1
-                        } else {
-                            0
-                        };
-
-                    chip8.registers[x as usize] =
-                        match chip8.registers[y as usize].checked_sub(chip8.registers[x as usize]) {
-                            Some(n) => n,
-                            None => 0,
-                        }
-                }
-                // 8xyE - SHL Vx {, Vy}
-                0xE => {
-                    // Set Vx = Vx SHL 1
-                    chip8.registers[0xF] = if chip8.registers[x as usize] & 0b10000000 == 0b10000000
-                    {
-                        1
-                    } else {
-                        0
-                    };
-                    chip8.registers[x as usize] /= 2;
-                }
-                _ => {}
-            }
+    };
+
+    match result {
+        Ok(()) => {
+            println!("Reloaded {}", model.program);
+            model.load_error = None;
         }
-        // 9xy0 - SNE Vx, Vy
-        0x9000 => {
-            // Skip next instruction if Vx != Vy
-            if chip8.registers[x as usize] != chip8.registers[y as usize] {
-                chip8.pc += 2;
-            }
+        Err(err) => {
+            println!("Error reloading {} : {}", model.program, err);
+            model.load_error = Some(format!("Error reloading {} : {}", model.program, err));
         }
-        // Annn - LD I, addr
-        0xA000 => {
-            // Set I = nnn
-            chip8.register_i = nnn;
-        }
-        // Bnnn - JP V0, addr
-        0xB000 => {
-            // Jump to location nnn + V0
-            chip8.pc = nnn + (chip8.registers[0] as u16);
-        }
-        // Cxkk - RND Vx, byte
-        0xC000 => {
-            // Set Vx = random byte AND kk
-            chip8.registers[x as usize] = rand::random::<u8>() & kk;
-        }
-        // Dxyn - DRW Vx, Vy, nibble
-        0xD000 => {
-            // Display n-byte sprite starting at chip8.memory location I at (Vx, Vy), set VF = collision.
-            /*
-            The interpreter reads n bytes from chip8.memory,
-            starting at the address stored in I.
-            These bytes are then displayed as sprites on screen
-            at coordinates (Vx, Vy). Sprites are XORed onto
-            the existing screen. If this causes any pixels
-            to be erased, VF is set to 1, otherwise it is
-            set to 0. If the sprite is positioned so part of it
-            is outside the coordinates of the display, it wraps
-            around to the opposite side of the screen.
-            */
-            let start_x = chip8.registers[x as usize];
-            let start_y = chip8.registers[y as usize];
-
-            // Sprites are 8xN
-            for line in 0..n {
-                let sprite_line = chip8.memory[(chip8.register_i + line as u16) as usize];
-                for column in 0..8 {
-                    // wrap around with %
-                    let pos_x = ((start_x % WIDTH) + column) % WIDTH;
-                    let pos_y = ((start_y % HEIGHT) + line) % HEIGHT;
-                    // println!("Pixel at {}({}),{}({})", pos_x, column, pos_y, line);
-
-                    let px_index = (pos_y as usize) * 64 + (pos_x as usize);
-                    let sprite_column_px = if (sprite_line << column) & 0b10000000 == 0b10000000 {
-                        1
-                    } else {
-                        0
-                    };
-                    let old_px = chip8.display[px_index];
-                    let new_px = old_px ^ sprite_column_px;
-                    chip8.display[px_index] = new_px;
-
-                    if old_px == 1 && new_px == 0 {
-                        chip8.registers[0xF] = 1;
-                    }
-                }
+    }
+}
+
+fn dropped_file(app: &App, model: &mut Model, path: std::path::PathBuf) {
+    open_program(app, model, &path.to_string_lossy());
+}
+
+// Preset palettes, plus a custom one when colors are set by the user.
+// Returns the palettes and the index of the one to start with.
+fn create_palettes(options: &options::Options) -> (Vec<Palette>, usize) {
+    let mut palettes = palette::presets();
+    let mut palette_index = palettes
+        .iter()
+        .position(|palette| palette.name == options.palette)
+        .unwrap_or(0);
+
+    if options.foreground.is_some() || options.background.is_some() {
+        let base = &palettes[palette_index];
+        palettes.push(Palette {
+            name: String::from("custom"),
+            background: options.background.unwrap_or(base.background),
+            foreground: options.foreground.unwrap_or(base.foreground),
+        });
+        palette_index = palettes.len() - 1;
+    }
+
+    (palettes, palette_index)
+}
+
+fn update(app: &App, model: &mut Model, _update: Update) {
+    while let Some(request) = model
+        .remote_requests
+        .as_ref()
+        .and_then(|requests| requests.try_recv().ok())
+    {
+        remote::handle(request, &mut WindowFrontend { app, model });
+    }
+
+    let source_changed = model
+        .source_watcher
+        .as_mut()
+        .is_some_and(|source_watcher| source_watcher.changed());
+    if source_changed {
+        hot_reload(model);
+    }
+
+    // The program is paused while the menu is shown
+    let frames = match model.browser {
+        Some(_) => Frames::Count(0),
+        None => model.playback.frames_to_run(),
+    };
+    match frames {
+        Frames::Count(count) => {
+            for _frame in 0..count {
+                run_emulated_frame(model);
             }
         }
-        0xE000 => {
-            match opcode & 0x00FF {
-                // Ex9E - SKP Vx
-                0x9E => {
-                    // Skip next instruction if key with the value of Vx is pressed
-                    if chip8.keys[chip8.registers[x as usize] as usize] {
-                        chip8.pc += 2;
-                    }
-                }
-                // ExA1 - SKNP Vx
-                0xA1 => {
-                    // Skip next instruction if key with the value of Vx is not pressed
-                    if !chip8.keys[chip8.registers[x as usize] as usize] {
-                        chip8.pc += 2;
-                    }
-                }
-                _ => {}
+        Frames::Uncapped => {
+            let start = std::time::Instant::now();
+            while start.elapsed() < playback::UNCAPPED_TIME_BUDGET {
+                run_emulated_frame(model);
             }
         }
-        0xF000 => {
-            match opcode & 0x00FF {
-                // Fx07 - LD Vx, DT
-                0x07 => {
-                    // Set Vx = delay timer value
-                    chip8.registers[x as usize] = chip8.timer_delay;
-                }
-                // Fx0A - LD Vx, K
-                0x0A => {
-                    // Wait for a key press, store the value of the key in Vx
-                    // All execution stops until a key is pressed
-                    chip8.hold_for_key = Some(x);
-                }
-                // Fx15 - LD DT, Vx
-                0x15 => {
-                    // Set delay timer = Vx
-                    chip8.timer_delay = chip8.registers[x as usize];
-                }
-                // Fx18 - LD ST, Vx
-                0x18 => {
-                    // Set sound timer = Vx
-                    chip8.timer_sound = chip8.registers[x as usize];
-                }
-                // Fx1E - ADD I, Vx
-                0x1E => {
-                    // Set I = I + Vx
-                    chip8.register_i += chip8.registers[x as usize] as u16;
-                }
-                // Fx29 - LD F, Vx
-                0x29 => {
-                    // Set I = location of sprite for digit Vx
-                    chip8.register_i = (chip8.registers[x as usize] * 5) as u16;
-                }
-                // Fx33 - LD B, Vx
-                0x33 => {
-                    // Store BCD representation of Vx in chip8.memory locations I, I+1, and I+2
-                    // The interpreter takes the decimal value of Vx, and places the hundreds digit in chip8.memory at location in I, the tens digit at location I+1, and the ones digit at location I+2.
-                    chip8.memory[(chip8.register_i + 0) as usize] =
-                        chip8.registers[x as usize] / 100;
-                    chip8.memory[(chip8.register_i + 1) as usize] =
-                        (chip8.registers[x as usize] % 100) / 10;
-                    chip8.memory[(chip8.register_i + 2) as usize] =
-                        chip8.registers[x as usize] % 10;
-                }
-                // Fx55 - LD [I], Vx
-                0x55 => {
-                    // Store chip8.registers V0 through Vx in chip8.memory starting at location I
-                    // The interpreter copies the values of chip8.registers V0 through Vx into chip8.memory, starting at the address in I
-                    // I itself is left unmodified
-
-                    for i in 0..=(x as usize) {
-                        chip8.memory[(chip8.register_i as usize) + i] = chip8.registers[i];
-                    }
-                }
-                // Fx65 - LD Vx, [I]
-                0x65 => {
-                    // Read chip8.registers V0 through Vx from chip8.memory starting at location I
-                    // The interpreter reads values from chip8.memory starting at location I into chip8.registers V0 through Vx
-                    // I itself is left unmodified
-
-                    for i in 0..=(x as usize) {
-                        chip8.registers[i] = chip8.memory[(chip8.register_i as usize) + i];
-                    }
-                }
-                _ => {}
+    }
+
+    let buzzer_on =
+        model.browser.is_none() && !model.playback.paused && chip8::is_buzzer_on(&model.chip8);
+
+    if !model.audio_is_playing && buzzer_on {
+        model.audio_is_playing = true;
+        model.audio_control_channel.send(true).unwrap();
+    } else if model.audio_is_playing && !buzzer_on {
+        model.audio_is_playing = false;
+        model.audio_control_channel.send(false).unwrap();
+    }
+}
+
+// Remote control of the window, requests run between updates
+struct WindowFrontend<'a> {
+    app: &'a App,
+    model: &'a mut Model,
+}
+
+impl remote::Frontend for WindowFrontend<'_> {
+    fn chip8(&mut self) -> &mut Chip8 {
+        &mut self.model.chip8
+    }
+
+    fn run_frame(&mut self) {
+        run_emulated_frame(self.model);
+    }
+
+    fn load_program(&mut self, filepath: &str) -> Result<(), String> {
+        self.model.browser = None;
+        open_program(self.app, self.model, filepath);
+        match self.model.load_error.as_ref() {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn set_paused(&mut self, paused: bool) -> Result<(), String> {
+        if self.model.playback.paused != paused {
+            self.model.playback.toggle_pause();
+            update_title(self.app, self.model);
+        }
+        Ok(())
+    }
+
+    fn quit(&mut self) -> Result<(), String> {
+        Err(String::from("the window is closed by its user"))
+    }
+}
+
+// 1/60s of emulated time. Captures follow emulated time, not the window's.
+fn run_emulated_frame(model: &mut Model) {
+    cheat::apply(&model.cheats, &mut model.chip8);
+    match model.script.as_mut() {
+        Some(script) => script.run_frame(&mut model.chip8),
+        None => chip8::run_frame(&mut model.chip8),
+    }
+    model.phosphor.update(&model.chip8.display);
+
+    if let Some(wav_recorder) = model.wav_recorder.as_mut() {
+        // Stops like the recordings, the emulation goes on
+        if let Err(err) = wav_recorder.push_frame(chip8::is_buzzer_on(&model.chip8)) {
+            println!("Error writing WAV : {}", err);
+            model.wav_recorder = None;
+        }
+    }
+
+    if let Some(recorder) = model.recorder.as_mut() {
+        let palette = &model.palettes[model.palette_index];
+        // The recording stops, as with the hotkey, and the emulation goes on
+        if let Err(err) = recorder.push_frame(model.phosphor.intensity(), palette) {
+            println!("Error recording : {}", err);
+            model.recorder = None;
+        }
+    }
+}
+
+fn exit(_app: &App, model: Model) {
+    if let Some(wav_recorder) = model.wav_recorder {
+        if let Err(err) = wav_recorder.finalize() {
+            println!("Error finishing WAV : {}", err);
+        }
+    }
+    if let Some(recorder) = model.recorder {
+        if let Err(err) = recorder.finish() {
+            println!("Error finishing recording : {}", err);
+        }
+    }
+}
+
+fn save_screenshot(model: &Model) {
+    let filepath = capture_filepath(&model.capture_dir, "png");
+    let palette = &model.palettes[model.palette_index];
+
+    match capture::save_screenshot(
+        &filepath,
+        model.phosphor.intensity(),
+        palette,
+        model.capture_scale,
+    ) {
+        Ok(()) => println!("Saved screenshot to {}", filepath),
+        Err(err) => println!("Error saving screenshot at {} : {}", filepath, err),
+    }
+}
+
+fn toggle_recording(model: &mut Model) {
+    if let Some(recorder) = model.recorder.take() {
+        match recorder.finish() {
+            Ok(()) => println!("Recording stopped"),
+            Err(err) => println!("Error finishing recording : {}", err),
+        }
+    } else {
+        let filepath = capture_filepath(&model.capture_dir, &model.record_format);
+        let palette = &model.palettes[model.palette_index];
+        match create_recorder(&filepath, palette, model.capture_scale) {
+            Ok(recorder) => {
+                model.recorder = Some(recorder);
+                println!("Recording to {}", filepath);
             }
+            Err(err) => println!("{}", err),
         }
-        _ => {}
     }
 }
 
-fn get_digit_sprites() -> [u8; 80] {
-    {
-        /*
-        ****  11110000  0xF0
-        *  *  10010000  0x90
-        *  *  10010000  0x90
-        *  *  10010000  0x90
-        ****  11110000  0xF0
-
-          *   00100000  0x20
-         **   01100000  0x60
-          *   00100000  0x20
-          *   00100000  0x20
-         ***  01110000  0x70
-
-        ****  11110000  0xF0
-           *  00010000  0x10
-        ****  11110000  0xF0
-        *     10000000  0x80
-        ****  11110000  0xF0
-
-        ****  11110000  0xF0
-           *  00010000  0x10
-        ****  11110000  0xF0
-           *  00010000  0x10
-        ****  11110000  0xF0
-
-        *  *  10010000  0x90
-        *  *  10010000  0x90
-        ****  11110000  0xF0
-           *  00010000  0x10
-           *  00010000  0x10
-
-        ****  11110000  0xF0
-        *     10000000  0x80
-        ****  11110000  0xF0
-           *  00010000  0x10
-        ****  11110000  0xF0
-
-        ****  11110000  0xF0
-        *     10000000  0x80
-        ****  11110000  0xF0
-        *  *  10010000  0x90
-        ****  11110000  0xF0
-
-        ****  11110000  0xF0
-           *  00010000  0x10
-          *   00100000  0x20
-         *    01000000  0x40
-         *    01000000  0x40
-
-        ****  11110000  0xF0
-        *  *  10010000  0x90
-        ****  11110000  0xF0
-        *  *  10010000  0x90
-        ****  11110000  0xF0
-
-        ****  11110000  0xF0
-        *  *  10010000  0x90
-        ****  11110000  0xF0
-           *  00010000  0x10
-        ****  11110000  0xF0
-
-        ****  11110000  0xF0
-        *  *  10010000  0x90
-        ****  11110000  0xF0
-        *  *  10010000  0x90
-        *  *  10010000  0x90
-
-        ***   11100000  0xE0
-        *  *  10010000  0x90
-        ***   11100000  0xE0
-        *  *  10010000  0x90
-        ***   11100000  0xE0
-
-        ****  11110000  0xF0
-        *     10000000  0x80
-        *     10000000  0x80
-        *     10000000  0x80
-        ****  11110000  0xF0
-
-        ***   11100000  0xE0
-        *  *  10010000  0x90
-        *  *  10010000  0x90
-        *  *  10010000  0x90
-        ***   11100000  0xE0
-
-        ****  11110000  0xF0
-        *     10000000  0x80
-        ****  11110000  0xF0
-        *     10000000  0x80
-        ****  11110000  0xF0
-
-        ****  11110000  0xF0
-        *     10000000  0x80
-        ****  11110000  0xF0
-        *     10000000  0x80
-        *     10000000  0x80
-        */
-    }
-
-    return [
-        0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0,
-        0xF0, 0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0,
-        0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0,
-        0xF0, 0x90, 0xF0, 0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0,
-        0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0,
-        0xF0, 0x80, 0xF0, 0x80, 0x80,
-    ];
-}
-
-fn key_to_chip8_key_index(key: Key) -> Option<u8> {
+// Unique file name for hotkey captures, e.g. chip-8-1603029931512.png
+fn capture_filepath(directory: &str, extension: &str) -> String {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+
+    std::path::Path::new(directory)
+        .join(format!("chip-8-{}.{}", timestamp, extension))
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn view(app: &App, model: &Model, frame: Frame) {
+    // Letterbox around the display area
+    frame.clear(BLACK);
+    let draw = app.draw();
+
+    let cell_size = cell_size(&app.main_window(), model.scaling);
+    let display_width = cell_size * WIDTH as f32;
+    let display_height = cell_size * HEIGHT as f32;
+
+    let palette = &model.palettes[model.palette_index];
+
+    if let Some(browser) = model.browser.as_ref() {
+        let rect = Rect::from_w_h(display_width, display_height);
+        browser::draw_menu(&draw, rect, browser, model.thumbnail.as_ref(), palette);
+        draw.to_frame(app, &frame).unwrap();
+        return;
+    }
+
+    model.display_texture.update(
+        &frame,
+        &model.chip8.display,
+        model.phosphor.intensity(),
+        palette,
+    );
+    model.display_texture.draw(
+        &draw,
+        Rect::from_w_h(display_width, display_height),
+        palette,
+        model.pixel_gap,
+    );
+
+    if let Some(load_error) = model.load_error.as_ref() {
+        let [r, g, b] = palette.foreground;
+        draw.text(load_error)
+            .w_h(display_width * 0.9, display_height)
+            .font_size((cell_size * 1.5) as u32)
+            .color(rgb8(r, g, b));
+    }
+
+    if let Some(script) = model.script.as_ref() {
+        let [r, g, b] = palette.foreground;
+        for overlay in script.overlays() {
+            // A display wide box, its left edge and top at the overlay's display cell
+            draw.text(&overlay.text)
+                .x_y(
+                    overlay.x as f32 * cell_size,
+                    display_height / 2.0 - overlay.y as f32 * cell_size - cell_size,
+                )
+                .w_h(display_width, cell_size * 2.0)
+                .font_size((cell_size * 1.5) as u32)
+                .left_justify()
+                .no_line_wrap()
+                .color(rgb8(r, g, b));
+        }
+    }
+
+    if model.show_cheats {
+        draw.rect()
+            .w_h(display_width, display_height)
+            .color(rgba8(0, 0, 0, 220));
+        draw.text(&cheats_panel(model).join("\n"))
+            .w_h(display_width * 0.9, display_height * 0.9)
+            .left_justify()
+            .align_text_top()
+            .font_size(cell_size as u32)
+            .color(WHITE);
+    }
+
+    if model.show_recent_files {
+        draw.rect()
+            .w_h(display_width, display_height)
+            .color(rgba8(0, 0, 0, 220));
+
+        let mut lines = vec![String::from("Recent files (1-9 to open, Esc to close)")];
+        for (i, filepath) in model.recent_files.iter().enumerate() {
+            // The end of the path is what tells files apart
+            let name = std::path::Path::new(filepath)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| filepath.clone());
+            lines.push(format!("{}. {}", i + 1, name));
+        }
+        draw.text(&lines.join("\n"))
+            .w_h(display_width * 0.9, display_height * 0.9)
+            .left_justify()
+            .align_text_top()
+            .font_size((cell_size * 1.5) as u32)
+            .color(WHITE);
+    }
+
+    draw.to_frame(app, &frame).unwrap();
+}
+
+// Size of a display cell, in window points, for the current window size
+fn cell_size(window: &nannou::window::Window, scaling: Scaling) -> f32 {
+    let window_rect = window.rect();
+    let fit_size = (window_rect.w() / WIDTH as f32).min(window_rect.h() / HEIGHT as f32);
+
+    match scaling {
+        Scaling::Fit => fit_size,
+        // Round in physical pixels, points can be fractional on HiDPI screens
+        Scaling::Integer => {
+            let scale_factor = window.scale_factor();
+            (fit_size * scale_factor).floor().max(1.0) / scale_factor
+        }
+    }
+}
+
+fn key_to_chip8_key_index(key: Key, key_bindings: &HashMap<String, u8>) -> Option<u8> {
+    let binding = match key {
+        Key::Up => "up",
+        Key::Down => "down",
+        Key::Left => "left",
+        Key::Right => "right",
+        Key::Space => "a",
+        Key::Return => "b",
+        _ => "",
+    };
+    if let Some(key_index) = key_bindings.get(binding) {
+        return Some(*key_index);
+    }
+
     match key {
         Key::Key1 => Some(0x1),
         Key::Key2 => Some(0x2),
@@ -637,28 +917,189 @@ fn key_to_chip8_key_index(key: Key) -> Option<u8> {
     }
 }
 
-fn key_pressed(_app: &App, chip8: &mut Chip8, key: Key) {
-    if let Some(key_index) = key_to_chip8_key_index(key) {
-        if let Some(hold_for_key) = chip8.hold_for_key {
-            chip8.registers[hold_for_key as usize] = key_index;
+fn key_pressed(app: &App, model: &mut Model, key: Key) {
+    if model.browser.is_some() {
+        browser_key_pressed(app, model, key);
+        return;
+    }
+
+    // The recent files list takes the number keys while it's shown
+    if model.show_recent_files {
+        if let Some(filepath) =
+            number_key_index(key).and_then(|index| model.recent_files.get(index).cloned())
+        {
+            model.show_recent_files = false;
+            open_program(app, model, &filepath);
+            return;
+        }
+        if key == Key::Escape || key == Key::F4 {
+            model.show_recent_files = false;
+            return;
+        }
+    }
+
+    if model.show_cheats && cheats_key_pressed(model, key) {
+        return;
+    }
+
+    if let Some(key_index) = key_to_chip8_key_index(key, &model.key_bindings) {
+        chip8::key_pressed(&mut model.chip8, key_index);
+    } else if key == Key::F1 {
+        model.palette_index = (model.palette_index + 1) % model.palettes.len();
+        println!("Palette: {}", model.palettes[model.palette_index].name);
+    } else if key == Key::P || key == Key::Pause {
+        model.playback.toggle_pause();
+        update_title(app, model);
+    } else if key == Key::N {
+        model.playback.step_frame();
+    } else if key == Key::I && model.playback.paused {
+        chip8::step_instruction(&mut model.chip8);
+        model.phosphor.update(&model.chip8.display);
+        let pc = model.chip8.pc as usize;
+        println!(
+            "PC {:#05X}, next opcode {:02X}{:02X}",
+            pc,
+            model.chip8.memory.get(pc).copied().unwrap_or_default(),
+            model.chip8.memory.get(pc + 1).copied().unwrap_or_default()
+        );
+    } else if key == Key::Tab && !model.playback.fast_forward {
+        // Key repeat sends more presses while it's held
+        model.playback.fast_forward = true;
+        update_title(app, model);
+    } else if key == Key::L {
+        model.playback.toggle_slow_motion();
+        update_title(app, model);
+    } else if key == Key::F2 {
+        open_browser(app, model);
+    } else if key == Key::F4 {
+        model.show_recent_files = true;
+        model.show_cheats = false;
+    } else if key == Key::F7 {
+        model.show_cheats = true;
+        model.show_recent_files = false;
+    } else if key == Key::F5 {
+        chip8::soft_reset(&mut model.chip8, model.load_address);
+        println!("Soft reset");
+    } else if key == Key::F6 {
+        let filepath = model.program.clone();
+        open_program(app, model, &filepath);
+    } else if key == Key::F9 {
+        save_screenshot(model);
+    } else if key == Key::F10 {
+        toggle_recording(model);
+    } else if key == Key::F11 {
+        let window = app.main_window();
+        window.set_fullscreen(window.fullscreen().is_none());
+    }
+}
+
+fn key_released(app: &App, model: &mut Model, key: Key) {
+    if let Some(key_index) = key_to_chip8_key_index(key, &model.key_bindings) {
+        chip8::key_released(&mut model.chip8, key_index);
+    } else if key == Key::Tab {
+        model.playback.fast_forward = false;
+        update_title(app, model);
+    }
+}
+
+// Load a ROM in any of the formats of rom.rs, or assemble it first if it's a .cp8asm source file
+fn load_program(filepath: &str) -> Result<rom::Rom, String> {
+    if filepath.ends_with(".cp8asm") {
+        Ok(rom::Rom {
+            bytes: assembler::assemble(filepath)
+                .map_err(|err| format!("Error assembling {}", err))?,
+            options: None,
+        })
+    } else {
+        rom::load(filepath).map_err(|err| format!("Error loading ROM at {} : {}", filepath, err))
+    }
+}
+
+// Load the program, fill in the options the user didn't set with the ones embedded
+// in the ROM file, then with what the ROM database knows about it, and set up the machine
+fn load_chip8(options: &mut options::Options) -> Result<(Chip8, Option<RomInfo>), String> {
+    let rom = load_program(&options.program)?;
+    let instructions = rom.bytes;
+
+    if let Some(embedded) = rom.options.as_ref() {
+        fill_options(options, embedded);
+    }
+
+    let rom_info = open_database(options)?.lookup(&instructions);
+    if let Some(rom_info) = rom_info.as_ref() {
+        println!("ROM database: {}", rom_info.title_line());
+        if let Some(platform) = rom_info.platform.as_ref() {
+            println!("Platform: {}", platform);
+        }
+
+        fill_options(options, &rom_info.options());
+    }
+
+    let mut chip8 = Chip8::load(
+        &instructions,
+        options.load_address.unwrap_or(chip8::LOAD_ADDRESS),
+        options.memory_size.unwrap_or(chip8::MEMORY_SIZE),
+    )
+    .map_err(|err| format!("Error loading {} : {}", options.program, err))?;
+    chip8.quirks = options.quirks.unwrap_or_default();
+    chip8.cycles_per_frame = options.cycles_per_frame.unwrap_or(chip8::CYCLES_PER_FRAME);
+
+    Ok((chip8, rom_info))
+}
+
+// The bundled ROM database, or the one of --database
+fn open_database(options: &options::Options) -> Result<Database, String> {
+    match options.database_path.as_ref() {
+        Some(directory) => {
+            Database::load(directory).map_err(|err| format!("Error reading ROM database : {}", err))
         }
-        chip8.keys[key_index as usize] = true;
+        None => Ok(Database::bundled()),
     }
 }
 
-fn key_released(_app: &App, chip8: &mut Chip8, key: Key) {
-    if let Some(key_index) = key_to_chip8_key_index(key) {
-        chip8.keys[key_index as usize] = false;
+fn fill_options(options: &mut options::Options, embedded: &rom::EmbeddedOptions) {
+    options.quirks = options.quirks.or(embedded.quirks);
+    options.cycles_per_frame = options.cycles_per_frame.or(embedded.cycles_per_frame);
+    options.load_address = options.load_address.or(embedded.load_address);
+    options.memory_size = options.memory_size.or(embedded.memory_size);
+    if let Some((background, foreground)) = embedded.colors {
+        if options.foreground.is_none() && options.background.is_none() {
+            options.background = Some(background);
+            options.foreground = Some(foreground);
+        }
     }
 }
 
-#[allow(dead_code)]
-fn load_rom_from_file(filepath: &str) -> Vec<u8> {
-    match std::fs::read(filepath) {
-        Ok(instructions) => instructions,
-        Err(err) => {
-            println!("Error reading ROM at {} : {}", filepath, err);
-            panic!();
+// --cheats, if set
+fn load_cheats(options: &options::Options) -> Result<Vec<Cheat>, String> {
+    match options.cheats_path.as_ref() {
+        Some(filepath) => {
+            let text = std::fs::read_to_string(filepath)
+                .map_err(|err| format!("Error reading cheats {} : {}", filepath, err))?;
+            cheat::parse(&text).map_err(|err| format!("Error in cheats {} : {}", filepath, err))
         }
+        None => Ok(vec![]),
     }
 }
+
+// --script, if set
+fn load_script(options: &options::Options) -> Result<Option<Script>, String> {
+    options.script_path.as_deref().map(Script::load).transpose()
+}
+
+// For the headless and terminal modes, that have nowhere else to show it, and files the
+// window can't run without
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn create_wav_recorder(filepath: &str) -> Result<WavRecorder, String> {
+    WavRecorder::create(filepath, WAVE_LENGTH)
+        .map_err(|err| format!("Error creating WAV file at {} : {}", filepath, err))
+}
+
+fn create_recorder(filepath: &str, palette: &Palette, scale: u32) -> Result<Recorder, String> {
+    Recorder::create(filepath, palette, scale)
+        .map_err(|err| format!("Error creating recording at {} : {}", filepath, err))
+}
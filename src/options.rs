@@ -0,0 +1,438 @@
+use chip_8::chip8::{Quirks, MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+use chip_8::palette::{self, Rgb};
+use chip_8::phosphor::PersistenceMode;
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: chip-8 [OPTIONS] [PROGRAM]
+
+PROGRAM is a ROM file, or a .cp8asm source file that is assembled on load, and again
+in the window whenever it or a file it pulls in with `INCLUDE file.cp8asm` is saved.
+Defaults to assembly_programs/clock.cp8asm. ROMs can be raw binaries, Octo cartridge
+GIFs (compiled with octo-cli, their options are applied), Intel HEX or hex text files
+(.hex, .txt), or zip archives holding one of these.
+
+Options:
+    --headless          Run without a window or audio device
+    --browse DIR        Start on a menu of the programs in DIR instead of running PROGRAM
+    --tui               Run in the terminal, drawing the display with block characters
+    --frames N          Number of 60hz frames to emulate in headless mode (default 600)
+    --seed N            Seed for the RND instruction, to reproduce a run exactly
+                        (default: random, 0 in headless mode)
+    --quirks PROFILE    Instruction behaviour the program expects: modern, cosmac (original
+                        COSMAC VIP interpreter) or schip (default: from the ROM database,
+                        or modern)
+    --speed N           Instructions per 60hz frame (default: from the ROM database, or 8,
+                        about 500hz)
+    --load-address ADDR Where the program is loaded and starts, e.g. 0x600 for ETI-660
+                        programs (default: from the ROM file or database, or 0x200)
+    --memory SIZE       Memory size, 4k or up to 64k for XO-CHIP programs (default: from
+                        the ROM file or database, or 4k)
+    --remote ADDR       Remote control server, JSON-RPC over a localhost TCP address
+                        (127.0.0.1:7878) or a Unix socket (unix:/tmp/chip-8.sock), see
+                        src/remote.rs. In headless mode frames only run when requested
+                        and --frames is ignored.
+    --cheats FILE       Cheats applied every frame, toggled with F7, see src/cheat.rs
+    --script FILE       Rhai script called back on each frame, on chosen addresses, on
+                        DRW and on buzzer changes, with text drawn over the display, see
+                        src/script.rs
+    --database DIR      ROM database to read instead of the bundled one, e.g. the database/
+                        directory of https://github.com/chip-8/chip-8-database
+    --wav FILE          Render the buzzer to a WAV file, timed by emulated frames
+    --palette NAME      white, green, amber, lcd, high-contrast or colorblind (default white)
+    --foreground COLOR  Custom #RRGGBB color for lit pixels
+    --background COLOR  Custom #RRGGBB color for unlit pixels
+    --scale N           Initial window size, in window pixels per display pixel (default 10)
+    --scaling MODE      integer (sharp, same size for every pixel) or fit (fill the window)
+    --fast-forward N    Frames emulated per displayed frame while Tab is held, 0 for as
+                        many as the computer can run (default 4)
+    --reload MODE       When a .cp8asm source or a file it includes is saved, restart the
+                        reassembled program, or patch its code in place, keeping registers
+                        and display (restart or patch, default restart, window only)
+    --fullscreen        Start in fullscreen
+    --pixel-gap N       Gap in window pixels left between display cells (default 0)
+    --persistence MODE  Anti-flicker filter: off, fade or or (lit in this or the last frame)
+    --fade-frames N     Frames for a pixel to fade out with --persistence fade (default 4)
+    --screenshot FILE   Save a PNG of the last frame when the headless run ends
+    --record FILE       Record the display from the start, to a .gif or .y4m file
+    --record-format FMT gif or y4m, for recordings started with F10 (default gif)
+    --capture-scale N   Size of a display pixel in screenshots and recordings (default 8)
+    --capture-dir DIR   Where F9 and F10 captures are saved (default .)
+    --config FILE       Read options from FILE instead of the user config file
+    -h, --help          Print this message
+
+Every option can also be set in the config file (~/.config/chip-8/config on Linux),
+one per line without the leading dashes, e.g. `palette amber`.
+Command line options override the config file.
+
+Programs can also be opened by dropping their file onto the window.
+
+Programs found in the ROM database also get their platform's quirks, speed, colors,
+load address and memory size, unless set with the options above.
+
+Controls:
+    1 2 3 4 / Q W E R / A S D F / Z X C V   Chip-8 keypad
+    Arrows, Space, Enter                    Directions, A and B buttons, for programs
+                                            with key bindings in the ROM database
+    P, Pause                                Pause / resume
+    N                                       Advance one frame while paused
+    I                                       Execute one instruction while paused
+    Tab (hold)                              Fast-forward
+    L                                       Slow motion (1/4 speed) on / off
+    F1                                      Cycle color palettes
+    F2                                      Menu of the programs in the --browse directory,
+                                            or the running program's
+    F4                                      Recent files, opened with 1-9
+    F5                                      Soft reset: restart the program, keeping memory
+    F6                                      Hard reset: reload the program from its file
+    F7                                      Cheats, toggled with 1-9, and memory search:
+                                            S new, E equal, C changed, Up increased, Down
+                                            decreased, Enter freezes the candidates left
+    F11                                     Toggle fullscreen
+    F9                                      Save a screenshot
+    F10                                     Start / stop recording";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scaling {
+    // Largest whole number of physical pixels per display pixel that fits the window
+    Integer,
+    // Largest size that fits the window, keeping the aspect ratio
+    Fit,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ReloadMode {
+    // Start the reassembled program over
+    Restart,
+    // Swap the code in memory, the program carries on with its registers and display
+    Patch,
+}
+
+#[derive(Clone)]
+pub struct Options {
+    // Path to the ROM or assembly source to run
+    pub program: String,
+
+    pub headless: bool,
+    pub tui: bool,
+
+    // Window only, see browser.rs
+    pub browse_dir: Option<String>,
+
+    // Only used in headless mode, the window runs until closed
+    pub frames: u32,
+
+    pub seed: Option<u32>,
+
+    // None when not set by the user, the ROM database then decides
+    pub quirks: Option<Quirks>,
+    pub cycles_per_frame: Option<u32>,
+    pub load_address: Option<u16>,
+    pub memory_size: Option<usize>,
+
+    // TCP address or unix:path, for remote.rs
+    pub remote_address: Option<String>,
+
+    // Cheat file, for cheat.rs
+    pub cheats_path: Option<String>,
+
+    // Rhai script, for script.rs
+    pub script_path: Option<String>,
+
+    // Directory of the ROM database, the bundled one when None
+    pub database_path: Option<String>,
+
+    pub wav_path: Option<String>,
+
+    // Name of the preset palette to start with
+    pub palette: String,
+
+    // Custom colors, replacing the ones from the palette
+    pub foreground: Option<Rgb>,
+    pub background: Option<Rgb>,
+
+    pub scale: u32,
+    pub scaling: Scaling,
+    pub reload_mode: ReloadMode,
+    // Frames per displayed frame, 0 for uncapped
+    pub fast_forward: u32,
+    pub fullscreen: bool,
+
+    pub pixel_gap: u32,
+
+    pub persistence: PersistenceMode,
+
+    pub screenshot_path: Option<String>,
+    pub record_path: Option<String>,
+    pub record_format: String,
+    pub capture_scale: u32,
+    pub capture_dir: String,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            program: String::from("assembly_programs/clock.cp8asm"),
+            headless: false,
+            tui: false,
+            browse_dir: None,
+            frames: 600,
+            seed: None,
+            quirks: None,
+            cycles_per_frame: None,
+            load_address: None,
+            memory_size: None,
+            remote_address: None,
+            cheats_path: None,
+            script_path: None,
+            database_path: None,
+            wav_path: None,
+            palette: String::from("white"),
+            foreground: None,
+            background: None,
+            scale: 10,
+            scaling: Scaling::Integer,
+            reload_mode: ReloadMode::Restart,
+            fast_forward: 4,
+            fullscreen: false,
+            pixel_gap: 0,
+            persistence: PersistenceMode::Off,
+            screenshot_path: None,
+            record_path: None,
+            record_format: String::from("gif"),
+            capture_scale: 8,
+            capture_dir: String::from("."),
+        }
+    }
+}
+
+// Parse the config file then the command line, printing the usage and exiting on invalid arguments
+pub fn from_args() -> Options {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = read_config_args(&args).and_then(|mut config_args| {
+        config_args.extend(args);
+        parse(config_args.into_iter())
+    });
+
+    match result {
+        Ok(options) => options,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("{}\n", err);
+            }
+            eprintln!("{}", USAGE);
+            std::process::exit(if err.is_empty() { 0 } else { 1 });
+        }
+    }
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("chip-8"))
+}
+
+// Turn each `name value` line of the config file into `--name value` arguments.
+// A missing default config file is not an error, a missing --config file is.
+fn read_config_args(args: &[String]) -> Result<Vec<String>, String> {
+    let explicit_path = args
+        .iter()
+        .position(|arg| arg == "--config")
+        .map(|i| args.get(i + 1).map(PathBuf::from));
+
+    let path = match explicit_path {
+        Some(Some(path)) => path,
+        Some(None) => return Err(String::from("Missing value for --config")),
+        None => match config_dir() {
+            Some(dir) if dir.join("config").exists() => dir.join("config"),
+            _ => return Ok(vec![]),
+        },
+    };
+
+    let text = std::fs::read_to_string(&path)
+        .map_err(|err| format!("Error reading config at {} : {}", path.display(), err))?;
+
+    let mut config_args = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let name = parts.next().unwrap_or_default();
+        if name == "config" {
+            continue;
+        }
+        config_args.push(format!("--{}", name));
+        if let Some(value) = parts.next() {
+            config_args.push(String::from(value.trim()));
+        }
+    }
+
+    Ok(config_args)
+}
+
+// An empty error means the usage was explicitly requested
+fn parse(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args;
+    let mut fade_frames = 4;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--headless" => options.headless = true,
+            "--tui" => options.tui = true,
+            "--browse" => options.browse_dir = Some(next_value(&mut args, &arg)?),
+            "--frames" => options.frames = parse_number(&next_value(&mut args, &arg)?)?,
+            "--seed" => options.seed = Some(parse_number(&next_value(&mut args, &arg)?)?),
+            "--quirks" => {
+                let value = next_value(&mut args, &arg)?;
+                options.quirks = Some(
+                    Quirks::from_profile(&value)
+                        .ok_or_else(|| format!("Unknown quirks profile: {}", value))?,
+                );
+            }
+            "--speed" => {
+                options.cycles_per_frame = Some(parse_number(&next_value(&mut args, &arg)?)?.max(1))
+            }
+            "--load-address" => {
+                let value = next_value(&mut args, &arg)?;
+                options.load_address = Some(parse_address(&value)?);
+            }
+            "--memory" => {
+                let value = next_value(&mut args, &arg)?;
+                options.memory_size = Some(parse_memory_size(&value)?);
+            }
+            "--remote" => options.remote_address = Some(next_value(&mut args, &arg)?),
+            "--cheats" => options.cheats_path = Some(next_value(&mut args, &arg)?),
+            "--script" => options.script_path = Some(next_value(&mut args, &arg)?),
+            "--database" => options.database_path = Some(next_value(&mut args, &arg)?),
+            "--wav" => options.wav_path = Some(next_value(&mut args, &arg)?),
+            "--palette" => {
+                let value = next_value(&mut args, &arg)?;
+                if !palette::preset_names().contains(&value.as_str()) {
+                    return Err(format!("Unknown palette: {}", value));
+                }
+                options.palette = value;
+            }
+            "--foreground" => {
+                options.foreground = Some(parse_color(&next_value(&mut args, &arg)?)?)
+            }
+            "--background" => {
+                options.background = Some(parse_color(&next_value(&mut args, &arg)?)?)
+            }
+            "--scale" => options.scale = parse_number(&next_value(&mut args, &arg)?)?.max(1),
+            "--scaling" => {
+                let value = next_value(&mut args, &arg)?;
+                options.scaling = match value.as_str() {
+                    "integer" => Scaling::Integer,
+                    "fit" => Scaling::Fit,
+                    _ => return Err(format!("Unknown scaling mode: {}", value)),
+                }
+            }
+            "--reload" => {
+                let value = next_value(&mut args, &arg)?;
+                options.reload_mode = match value.as_str() {
+                    "restart" => ReloadMode::Restart,
+                    "patch" => ReloadMode::Patch,
+                    _ => return Err(format!("Unknown reload mode: {}", value)),
+                }
+            }
+            "--fast-forward" => options.fast_forward = parse_number(&next_value(&mut args, &arg)?)?,
+            "--fullscreen" => options.fullscreen = true,
+            "--pixel-gap" => options.pixel_gap = parse_number(&next_value(&mut args, &arg)?)?,
+            "--persistence" => {
+                let value = next_value(&mut args, &arg)?;
+                options.persistence = match value.as_str() {
+                    "off" => PersistenceMode::Off,
+                    "fade" => PersistenceMode::Fade(0),
+                    "or" => PersistenceMode::OrLastTwo,
+                    _ => return Err(format!("Unknown persistence mode: {}", value)),
+                }
+            }
+            "--fade-frames" => {
+                let value = next_value(&mut args, &arg)?;
+                fade_frames = match value.parse() {
+                    Ok(frames) if frames > 0 => frames,
+                    _ => return Err(format!("Invalid fade frame count: {}", value)),
+                }
+            }
+            "--screenshot" => options.screenshot_path = Some(next_value(&mut args, &arg)?),
+            "--record" => options.record_path = Some(next_value(&mut args, &arg)?),
+            "--record-format" => {
+                let value = next_value(&mut args, &arg)?;
+                if value != "gif" && value != "y4m" {
+                    return Err(format!("Unknown recording format: {}", value));
+                }
+                options.record_format = value;
+            }
+            "--capture-scale" => {
+                options.capture_scale = parse_number(&next_value(&mut args, &arg)?)?.max(1)
+            }
+            "--capture-dir" => options.capture_dir = next_value(&mut args, &arg)?,
+            // Already read by read_config_args
+            "--config" => {
+                next_value(&mut args, &arg)?;
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ => options.program = arg,
+        }
+    }
+
+    // --fade-frames can come before or after --persistence
+    if let PersistenceMode::Fade(_) = options.persistence {
+        options.persistence = PersistenceMode::Fade(fade_frames);
+    }
+
+    Ok(options)
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {}", option))
+}
+
+fn parse_number(value: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number: {}", value))
+}
+
+// 0x600 or 1536
+fn parse_address(value: &str) -> Result<u16, String> {
+    let address = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    address.map_err(|_| format!("Invalid address: {}", value))
+}
+
+// 4k, 64k or a number of bytes
+fn parse_memory_size(value: &str) -> Result<usize, String> {
+    let size = match value.strip_suffix('k').or_else(|| value.strip_suffix('K')) {
+        Some(kilobytes) => kilobytes
+            .parse::<usize>()
+            .ok()
+            .and_then(|kilobytes| kilobytes.checked_mul(1024)),
+        None => value.parse().ok(),
+    };
+    match size {
+        Some(size) if (MEMORY_SIZE..=XO_CHIP_MEMORY_SIZE).contains(&size) => Ok(size),
+        _ => Err(format!("Invalid memory size: {}", value)),
+    }
+}
+
+fn parse_color(value: &str) -> Result<Rgb, String> {
+    palette::parse_color(value).ok_or_else(|| format!("Invalid color: {}", value))
+}
+
+#[test]
+fn test_parse_memory_size() {
+    assert_eq!(parse_memory_size("4k"), Ok(MEMORY_SIZE));
+    assert_eq!(parse_memory_size("65536"), Ok(XO_CHIP_MEMORY_SIZE));
+    assert!(parse_memory_size("2k").is_err());
+    assert!(parse_memory_size("18014398509481984k").is_err());
+}
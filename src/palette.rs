@@ -0,0 +1,82 @@
+pub type Rgb = [u8; 3];
+
+#[derive(Clone)]
+pub struct Palette {
+    pub name: String,
+    pub background: Rgb,
+    pub foreground: Rgb,
+}
+
+// name, background, foreground
+const PRESETS: [(&str, Rgb, Rgb); 6] = [
+    ("white", [0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF]),
+    ("green", [0x0A, 0x1A, 0x0A], [0x33, 0xFF, 0x66]),
+    ("amber", [0x1A, 0x0F, 0x00], [0xFF, 0xB0, 0x00]),
+    ("lcd", [0x9B, 0xBC, 0x0F], [0x0F, 0x38, 0x0F]),
+    ("high-contrast", [0x00, 0x00, 0x00], [0xFF, 0xFF, 0x00]),
+    // Blue / yellow from the Okabe-Ito set, distinguishable with every type of color blindness
+    ("colorblind", [0x00, 0x3D, 0x73], [0xF0, 0xE4, 0x42]),
+];
+
+pub fn presets() -> Vec<Palette> {
+    PRESETS
+        .iter()
+        .map(|(name, background, foreground)| Palette {
+            name: String::from(*name),
+            background: *background,
+            foreground: *foreground,
+        })
+        .collect()
+}
+
+pub fn preset_names() -> Vec<&'static str> {
+    PRESETS.iter().map(|(name, _, _)| *name).collect()
+}
+
+// Mix background and foreground for a pixel intensity from 0 (off) to 255 (fully lit)
+pub fn blend(palette: &Palette, intensity: u8) -> Rgb {
+    let mut color = palette.background;
+    for (channel, foreground) in color.iter_mut().zip(palette.foreground.iter()) {
+        let background = *channel as i32;
+        *channel = (background + (*foreground as i32 - background) * intensity as i32 / 255) as u8;
+    }
+    color
+}
+
+// Red, green, blue and an opaque alpha for each pixel intensity, as textures take them
+pub fn to_rgba(palette: &Palette, intensity: &[u8]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(intensity.len() * 4);
+    for px_intensity in intensity {
+        rgba.extend_from_slice(&blend(palette, *px_intensity));
+        rgba.push(0xFF);
+    }
+    rgba
+}
+
+// Parse a #RRGGBB (or RRGGBB) hex color
+pub fn parse_color(text: &str) -> Option<Rgb> {
+    let hex = text.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+#[test]
+fn test_parse_color() {
+    assert_eq!(parse_color("#33FF66"), Some([0x33, 0xFF, 0x66]));
+    assert_eq!(parse_color("0a1b2c"), Some([0x0A, 0x1B, 0x2C]));
+    assert_eq!(parse_color("#FFF"), None);
+    assert_eq!(parse_color("#GGGGGG"), None);
+}
+
+#[test]
+fn test_to_rgba() {
+    let palette = &presets()[1];
+    assert_eq!(
+        to_rgba(palette, &[0, 255]),
+        [0x0A, 0x1A, 0x0A, 0xFF, 0x33, 0xFF, 0x66, 0xFF]
+    );
+}
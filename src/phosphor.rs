@@ -0,0 +1,95 @@
+use crate::chip8::{HEIGHT, WIDTH};
+use crate::display::Display;
+
+const DISPLAY_SIZE: usize = WIDTH as usize * HEIGHT as usize;
+
+// Chip-8 programs erase and redraw sprites with XOR, so a moving sprite is often missing
+// from the display buffer for a frame. These filters hide that flicker the way the
+// slow phosphor of old CRTs did.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PersistenceMode {
+    // Show the display buffer as is
+    Off,
+    // Pixels turn on at full brightness and fade out over the given number of frames
+    Fade(u8),
+    // A pixel is lit if it was lit in this frame or the previous one
+    OrLastTwo,
+}
+
+// Post-process the display once per emulated frame, independently of the frontend,
+// so windowed, headless and captured output all look the same.
+pub struct Phosphor {
+    mode: PersistenceMode,
+
+    // Display of the previous frame, for OrLastTwo
+    previous: Display,
+
+    // 0 (off) to 255 (fully lit), for each pixel
+    intensity: [u8; DISPLAY_SIZE],
+}
+
+impl Phosphor {
+    pub fn new(mode: PersistenceMode) -> Phosphor {
+        Phosphor {
+            mode,
+            previous: Display::new(),
+            intensity: [0; DISPLAY_SIZE],
+        }
+    }
+
+    pub fn update(&mut self, display: &Display) {
+        let pixels = display.pixels().zip(self.previous.pixels());
+        for (intensity, (lit, previous_lit)) in self.intensity.iter_mut().zip(pixels) {
+            *intensity = match self.mode {
+                PersistenceMode::Off => lit as u8 * 255,
+                PersistenceMode::Fade(frames) => {
+                    if lit {
+                        255
+                    } else {
+                        // Round the step up so the pixel is off after exactly `frames` frames
+                        intensity.saturating_sub(255_u8.div_ceil(frames.max(1)))
+                    }
+                }
+                PersistenceMode::OrLastTwo => (lit || previous_lit) as u8 * 255,
+            };
+        }
+
+        self.previous = *display;
+    }
+
+    pub fn intensity(&self) -> &[u8; DISPLAY_SIZE] {
+        &self.intensity
+    }
+}
+
+#[test]
+fn test_phosphor_fade() {
+    let mut display = Display::new();
+    let mut phosphor = Phosphor::new(PersistenceMode::Fade(3));
+
+    display.set(0, 0, true);
+    phosphor.update(&display);
+    assert_eq!(phosphor.intensity()[0], 255);
+
+    display.set(0, 0, false);
+    phosphor.update(&display);
+    assert_eq!(phosphor.intensity()[0], 170);
+    phosphor.update(&display);
+    assert_eq!(phosphor.intensity()[0], 85);
+    phosphor.update(&display);
+    assert_eq!(phosphor.intensity()[0], 0);
+}
+
+#[test]
+fn test_phosphor_or_last_two() {
+    let mut display = Display::new();
+    let mut phosphor = Phosphor::new(PersistenceMode::OrLastTwo);
+
+    display.set(5, 0, true);
+    phosphor.update(&display);
+    display.set(5, 0, false);
+    phosphor.update(&display);
+    assert_eq!(phosphor.intensity()[5], 255);
+    phosphor.update(&display);
+    assert_eq!(phosphor.intensity()[5], 0);
+}
@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+// How emulated time advances relative to the 60hz window updates: normally one emulated
+// frame per update, none while paused (except for single steps), several while
+// fast-forwarding and one every few updates in slow motion.
+
+// Updates per emulated frame in slow motion
+const SLOW_MOTION_DIVISOR: u32 = 4;
+
+// Host time spent emulating per update when fast-forwarding uncapped, leaving the rest
+// of the 16ms to drawing
+pub const UNCAPPED_TIME_BUDGET: Duration = Duration::from_millis(12);
+
+#[derive(Debug, PartialEq)]
+pub enum Frames {
+    Count(u32),
+    // As many as fit in UNCAPPED_TIME_BUDGET
+    Uncapped,
+}
+
+pub struct Playback {
+    pub paused: bool,
+    // While the key is held
+    pub fast_forward: bool,
+    pub slow_motion: bool,
+
+    // Emulated frames per update while fast-forwarding, 0 for uncapped
+    fast_forward_factor: u32,
+
+    // Updates since the last emulated frame in slow motion
+    slow_motion_updates: u32,
+
+    // Frame advances requested while paused
+    steps: u32,
+}
+
+impl Playback {
+    pub fn new(fast_forward_factor: u32) -> Playback {
+        Playback {
+            paused: false,
+            fast_forward: false,
+            slow_motion: false,
+            fast_forward_factor,
+            slow_motion_updates: 0,
+            steps: 0,
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.steps = 0;
+    }
+
+    pub fn toggle_slow_motion(&mut self) {
+        self.slow_motion = !self.slow_motion;
+        self.slow_motion_updates = 0;
+    }
+
+    // Run exactly one more frame, only while paused
+    pub fn step_frame(&mut self) {
+        if self.paused {
+            self.steps += 1;
+        }
+    }
+
+    // Emulated frames for this update
+    pub fn frames_to_run(&mut self) -> Frames {
+        if self.paused {
+            let steps = self.steps;
+            self.steps = 0;
+            return Frames::Count(steps);
+        }
+
+        if self.fast_forward {
+            return match self.fast_forward_factor {
+                0 => Frames::Uncapped,
+                factor => Frames::Count(factor),
+            };
+        }
+
+        if self.slow_motion {
+            self.slow_motion_updates += 1;
+            if self.slow_motion_updates < SLOW_MOTION_DIVISOR {
+                return Frames::Count(0);
+            }
+            self.slow_motion_updates = 0;
+        }
+
+        Frames::Count(1)
+    }
+
+    // For the window title, None at normal speed
+    pub fn status(&self) -> Option<String> {
+        if self.paused {
+            Some(String::from("Paused"))
+        } else if self.fast_forward {
+            match self.fast_forward_factor {
+                0 => Some(String::from("Fast-forward")),
+                factor => Some(format!("Fast-forward {}x", factor)),
+            }
+        } else if self.slow_motion {
+            Some(format!("Slow motion 1/{}", SLOW_MOTION_DIVISOR))
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn test_frames_to_run() {
+    let mut playback = Playback::new(4);
+    assert_eq!(playback.frames_to_run(), Frames::Count(1));
+
+    playback.toggle_pause();
+    assert_eq!(playback.frames_to_run(), Frames::Count(0));
+    playback.step_frame();
+    playback.step_frame();
+    assert_eq!(playback.frames_to_run(), Frames::Count(2));
+    assert_eq!(playback.frames_to_run(), Frames::Count(0));
+    playback.toggle_pause();
+
+    playback.fast_forward = true;
+    assert_eq!(playback.frames_to_run(), Frames::Count(4));
+    assert_eq!(playback.status().as_deref(), Some("Fast-forward 4x"));
+    playback.fast_forward = false;
+
+    playback.toggle_slow_motion();
+    let frames: Vec<Frames> = (0..8).map(|_| playback.frames_to_run()).collect();
+    assert_eq!(
+        frames
+            .iter()
+            .filter(|frames| **frames == Frames::Count(1))
+            .count(),
+        2
+    );
+
+    assert_eq!(Playback::new(0).status(), None);
+}
@@ -0,0 +1,56 @@
+use crate::options;
+use std::path::Path;
+
+// Programs opened in the window, most recent first, kept between sessions in
+// ~/.config/chip-8/recent (one path per line)
+
+const MAX_RECENT_FILES: usize = 9;
+
+pub fn load() -> Vec<String> {
+    let text = options::config_dir()
+        .and_then(|dir| std::fs::read_to_string(dir.join("recent")).ok())
+        .unwrap_or_default();
+
+    text.lines()
+        .filter(|line| !line.is_empty())
+        .take(MAX_RECENT_FILES)
+        .map(String::from)
+        .collect()
+}
+
+// Move filepath to the top of the list and save it. Failing to save only loses the history.
+pub fn add(recent_files: &mut Vec<String>, filepath: &str) {
+    // Absolute, the next session can start from another directory
+    let filepath = Path::new(filepath)
+        .canonicalize()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| String::from(filepath));
+    push(recent_files, filepath);
+
+    if let Some(dir) = options::config_dir() {
+        let result = std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::write(dir.join("recent"), recent_files.join("\n") + "\n"));
+        if let Err(err) = result {
+            println!("Error saving recent files in {} : {}", dir.display(), err);
+        }
+    }
+}
+
+fn push(recent_files: &mut Vec<String>, filepath: String) {
+    recent_files.retain(|recent_file| *recent_file != filepath);
+    recent_files.insert(0, filepath);
+    recent_files.truncate(MAX_RECENT_FILES);
+}
+
+#[test]
+fn test_push() {
+    let mut recent_files = vec![String::from("a.ch8"), String::from("b.ch8")];
+    push(&mut recent_files, String::from("b.ch8"));
+    assert_eq!(recent_files, ["b.ch8", "a.ch8"]);
+
+    for i in 0..20 {
+        push(&mut recent_files, format!("{}.ch8", i));
+    }
+    assert_eq!(recent_files.len(), MAX_RECENT_FILES);
+    assert_eq!(recent_files[0], "19.ch8");
+}
@@ -0,0 +1,508 @@
+use chip_8::chip8::{self, Chip8, HEIGHT, WIDTH};
+use chip_8::state;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+// Remote control of the emulator, for test harnesses and bots: a JSON-RPC 2.0 server,
+// one request and one response per line, on a localhost TCP port or a Unix socket.
+// Notifications (requests without an id) get no response, and a line that isn't JSON
+// closes the connection.
+//
+//     --remote 127.0.0.1:7878      --remote unix:/tmp/chip-8.sock
+//
+//     > {"jsonrpc": "2.0", "id": 1, "method": "run_frames", "params": {"count": 60}}
+//     < {"jsonrpc":"2.0","id":1,"result":null}
+//
+// Connections are served on their own threads. Requests are passed to the frontend's
+// loop, which executes them between frames, so they never race with the emulation.
+//
+// Methods:
+//     load_rom        {"path": "games/pong.ch8"}
+//     run_frames      {"count": 60}                 Emulate frames right away, up to a minute
+//     step            {"count": 1}                  Execute instructions, timers don't tick,
+//                                                   up to a minute's worth at default speed
+//     pause, resume                                 Window only, the headless frontend only
+//                                                   runs when asked to
+//     quit                                          Headless only, the window is closed
+//                                                   by its user
+//     press_key       {"key": 5}                    Chip-8 keys 0 to 15
+//     release_key     {"key": 5}
+//     read_memory     {"address": 512, "length": 16}   => [bytes]
+//     write_memory    {"address": 512, "bytes": [0, 224]}
+//     get_registers                                 => {"v", "i", "pc", "sp", "stack",
+//                                                       "delay_timer", "sound_timer"}
+//     set_registers   any of the fields of get_registers but "stack" and "sp"
+//     get_framebuffer                               => {"width", "height", "pixels": [0 or 1]}
+//     save_state                                    => {"state": hex string}
+//     load_state      {"state": hex string}
+
+// What a frontend has to provide, everything else works on the machine directly
+pub trait Frontend {
+    fn chip8(&mut self) -> &mut Chip8;
+
+    // One 60hz frame, with the frontend's captures
+    fn run_frame(&mut self);
+
+    fn load_program(&mut self, filepath: &str) -> Result<(), String>;
+
+    fn set_paused(&mut self, paused: bool) -> Result<(), String>;
+
+    fn quit(&mut self) -> Result<(), String>;
+}
+
+pub struct Request {
+    method: String,
+    params: Value,
+    reply: Sender<Result<Value, RpcError>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RpcError {
+    code: i64,
+    message: String,
+}
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+// Requests are executed between frames: longer ones would freeze the window
+const MAX_FRAMES: u64 = 60 * 60;
+const MAX_STEPS: u64 = MAX_FRAMES * chip8::CYCLES_PER_FRAME as u64;
+
+fn invalid_params(message: &str) -> RpcError {
+    RpcError {
+        code: INVALID_PARAMS,
+        message: String::from(message),
+    }
+}
+
+// Listen on address, returning the requests to execute from the frontend's loop
+pub fn start(address: &str) -> Result<Receiver<Request>, String> {
+    let (requests, receiver) = channel();
+
+    #[cfg(unix)]
+    if let Some(path) = address.strip_prefix("unix:") {
+        remove_stale_socket(path)
+            .map_err(|err| format!("Error listening on {} : {}", address, err))?;
+        let listener = std::os::unix::net::UnixListener::bind(path)
+            .map_err(|err| format!("Error listening on {} : {}", address, err))?;
+        std::thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                let requests = requests.clone();
+                std::thread::spawn(move || {
+                    if let Ok(reader) = stream.try_clone() {
+                        serve(reader, stream, requests);
+                    }
+                });
+            }
+        });
+        return Ok(receiver);
+    }
+
+    // Anyone who can connect can load any file and write memory
+    let socket_address: std::net::SocketAddr = address.parse().map_err(|_err| {
+        format!(
+            "Error listening on {} : expected an address like 127.0.0.1:7878",
+            address
+        )
+    })?;
+    if !socket_address.ip().is_loopback() {
+        return Err(format!(
+            "Error listening on {} : only localhost addresses are allowed",
+            address
+        ));
+    }
+    let listener = std::net::TcpListener::bind(socket_address)
+        .map_err(|err| format!("Error listening on {} : {}", address, err))?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            let requests = requests.clone();
+            std::thread::spawn(move || {
+                if let Ok(reader) = stream.try_clone() {
+                    serve(reader, stream, requests);
+                }
+            });
+        }
+    });
+    Ok(receiver)
+}
+
+// A socket left behind by a previous run is replaced, any other file is kept
+#[cfg(unix)]
+fn remove_stale_socket(path: &str) -> Result<(), String> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            std::fs::remove_file(path).map_err(|err| err.to_string())
+        }
+        Ok(_metadata) => Err(String::from("the file exists and isn't a socket")),
+        // Nothing there, or bind reports why
+        Err(_err) => Ok(()),
+    }
+}
+
+// Answer the requests of one connection until it's closed. A line that isn't JSON closes
+// it: it's another protocol, e.g. a browser posting a form to the port, whose next lines
+// mustn't run as requests.
+fn serve(reader: impl Read, mut writer: impl Write, requests: Sender<Request>) {
+    for line in BufReader::new(reader).lines().map_while(Result::ok) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                let response = error_response(Value::Null, PARSE_ERROR, &err.to_string());
+                let _ = writeln!(writer, "{}", response);
+                break;
+            }
+        };
+        // Notifications, without an id, are executed but not answered
+        let notification = request.get("id").is_none();
+        let response = handle_request(request, &requests);
+        if !notification && writeln!(writer, "{}", response).is_err() {
+            break;
+        }
+    }
+}
+
+fn handle_request(request: Value, requests: &Sender<Request>) -> Value {
+    let id = request["id"].clone();
+    let method = match request["method"].as_str() {
+        Some(method) => String::from(method),
+        None => return error_response(id, INVALID_REQUEST, "missing method"),
+    };
+
+    let (reply, result) = channel();
+    let request = Request {
+        method,
+        params: request["params"].clone(),
+        reply,
+    };
+    let result = match requests.send(request) {
+        Ok(()) => result.recv().unwrap_or_else(|_| {
+            Err(RpcError {
+                code: SERVER_ERROR,
+                message: String::from("the emulator stopped"),
+            })
+        }),
+        Err(_) => Err(RpcError {
+            code: SERVER_ERROR,
+            message: String::from("the emulator stopped"),
+        }),
+    };
+
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(err) => error_response(id, err.code, &err.message),
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+// Execute a request on the frontend's thread and send back the result
+pub fn handle(request: Request, frontend: &mut dyn Frontend) {
+    let result = execute(&request.method, &request.params, frontend);
+    // The connection may be gone already, nobody is left to tell
+    let _ = request.reply.send(result);
+}
+
+fn execute(method: &str, params: &Value, frontend: &mut dyn Frontend) -> Result<Value, RpcError> {
+    let number = |name: &str| {
+        params[name]
+            .as_u64()
+            .ok_or_else(|| invalid_params(&format!("{} must be a number", name)))
+    };
+    let count = |max: u64| match number("count")? {
+        count if count > max => Err(invalid_params(&format!("count must be at most {}", max))),
+        count => Ok(count),
+    };
+    let server_error = |message: String| RpcError {
+        code: SERVER_ERROR,
+        message,
+    };
+
+    match method {
+        "load_rom" => {
+            let path = params["path"]
+                .as_str()
+                .ok_or_else(|| invalid_params("path must be a string"))?;
+            frontend.load_program(path).map_err(server_error)?;
+        }
+        "run_frames" => {
+            for _frame in 0..count(MAX_FRAMES)? {
+                frontend.run_frame();
+            }
+        }
+        "step" => {
+            for _instruction in 0..count(MAX_STEPS)? {
+                chip8::step_instruction(frontend.chip8());
+            }
+        }
+        "pause" => frontend.set_paused(true).map_err(server_error)?,
+        "resume" => frontend.set_paused(false).map_err(server_error)?,
+        "press_key" | "release_key" => {
+            let key = number("key")?;
+            if key > 0xF {
+                return Err(invalid_params("key must be 0 to 15"));
+            }
+            if method == "press_key" {
+                chip8::key_pressed(frontend.chip8(), key as u8);
+            } else {
+                chip8::key_released(frontend.chip8(), key as u8);
+            }
+        }
+        "read_memory" => {
+            let range = memory_range(frontend.chip8(), number("address")?, number("length")?)?;
+            return Ok(json!(frontend.chip8().memory[range]));
+        }
+        "write_memory" => {
+            let bytes = byte_array(&params["bytes"], "bytes")?;
+            let range = memory_range(frontend.chip8(), number("address")?, bytes.len() as u64)?;
+            frontend.chip8().memory[range].copy_from_slice(&bytes);
+        }
+        "get_registers" => {
+            let chip8 = frontend.chip8();
+            return Ok(json!({
+                "v": chip8.registers,
+                "i": chip8.register_i,
+                "pc": chip8.pc,
+                "sp": chip8.sp,
+                "stack": chip8.stack,
+                "delay_timer": chip8.timer_delay,
+                "sound_timer": chip8.timer_sound,
+            }));
+        }
+        "set_registers" => set_registers(frontend.chip8(), params)?,
+        "get_framebuffer" => {
+            return Ok(json!({
+                "width": WIDTH,
+                "height": HEIGHT,
+                "pixels": frontend.chip8().display.to_bytes(),
+            }));
+        }
+        "save_state" => {
+            let state = state::save_state(frontend.chip8());
+            return Ok(json!({ "state": to_hex(&state) }));
+        }
+        "load_state" => {
+            let state = params["state"]
+                .as_str()
+                .and_then(from_hex)
+                .ok_or_else(|| invalid_params("state must be a hex string"))?;
+            state::load_state(frontend.chip8(), &state)
+                .map_err(|err| server_error(err.to_string()))?;
+        }
+        "quit" => frontend.quit().map_err(server_error)?,
+        _ => {
+            return Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unknown method {}", method),
+            })
+        }
+    }
+
+    Ok(Value::Null)
+}
+
+fn memory_range(
+    chip8: &Chip8,
+    address: u64,
+    length: u64,
+) -> Result<std::ops::Range<usize>, RpcError> {
+    let end = address.saturating_add(length);
+    if end > chip8.memory.len() as u64 {
+        return Err(invalid_params(&format!(
+            "memory is {} bytes",
+            chip8.memory.len()
+        )));
+    }
+    Ok(address as usize..end as usize)
+}
+
+fn byte_array(value: &Value, name: &str) -> Result<Vec<u8>, RpcError> {
+    let error = || invalid_params(&format!("{} must be an array of bytes", name));
+    value
+        .as_array()
+        .ok_or_else(error)?
+        .iter()
+        .map(|byte| match byte.as_u64() {
+            Some(byte) if byte <= 0xFF => Ok(byte as u8),
+            _ => Err(error()),
+        })
+        .collect()
+}
+
+fn set_registers(chip8: &mut Chip8, params: &Value) -> Result<(), RpcError> {
+    let value = |name: &str, max: u64| match &params[name] {
+        Value::Null => Ok(None),
+        value => match value.as_u64() {
+            Some(value) if value <= max => Ok(Some(value)),
+            _ => Err(invalid_params(&format!("{} must be 0 to {}", name, max))),
+        },
+    };
+
+    // Checked before anything is changed
+    let registers = match &params["v"] {
+        Value::Null => None,
+        value => Some(byte_array(value, "v")?),
+    };
+    if registers
+        .as_ref()
+        .is_some_and(|registers| registers.len() != 16)
+    {
+        return Err(invalid_params("v must have 16 registers"));
+    }
+    let register_i = value("i", 0xFFFF)?;
+    let pc = value("pc", 0xFFFF)?;
+    let timer_delay = value("delay_timer", 0xFF)?;
+    let timer_sound = value("sound_timer", 0xFF)?;
+
+    if let Some(registers) = registers {
+        chip8.registers.copy_from_slice(&registers);
+    }
+    if let Some(register_i) = register_i {
+        chip8.register_i = register_i as u16;
+    }
+    if let Some(pc) = pc {
+        chip8.pc = pc as u16;
+    }
+    if let Some(timer_delay) = timer_delay {
+        chip8.timer_delay = timer_delay as u8;
+    }
+    if let Some(timer_sound) = timer_sound {
+        chip8.timer_sound = timer_sound as u8;
+    }
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+struct TestFrontend {
+    chip8: Chip8,
+    frames: u32,
+}
+
+#[cfg(test)]
+impl Frontend for TestFrontend {
+    fn chip8(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+
+    fn run_frame(&mut self) {
+        chip8::run_frame(&mut self.chip8);
+        self.frames += 1;
+    }
+
+    fn load_program(&mut self, filepath: &str) -> Result<(), String> {
+        Err(format!("can't load {}", filepath))
+    }
+
+    fn set_paused(&mut self, _paused: bool) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn quit(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_remote_requests() {
+    let (requests, receiver) = channel();
+    let responder = std::thread::spawn(move || {
+        let mut frontend = TestFrontend {
+            chip8: Chip8::new(include_bytes!("../tests/roms/clock.ch8")),
+            frames: 0,
+        };
+        for request in receiver.iter() {
+            handle(request, &mut frontend);
+        }
+        frontend.frames
+    });
+
+    let input = [
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "run_frames", "params": {"count": 3}}"#,
+        r#"{"jsonrpc": "2.0", "id": 2, "method": "read_memory", "params": {"address": 512, "length": 2}}"#,
+        r#"{"jsonrpc": "2.0", "id": 3, "method": "set_registers", "params": {"pc": 1024, "i": 5}}"#,
+        r#"{"jsonrpc": "2.0", "id": 4, "method": "get_registers"}"#,
+        r#"{"jsonrpc": "2.0", "id": 5, "method": "write_memory", "params": {"address": 4095, "bytes": [1, 2]}}"#,
+        r#"{"jsonrpc": "2.0", "id": 6, "method": "fly"}"#,
+        r#"{"jsonrpc": "2.0", "id": 7, "method": "run_frames", "params": {"count": 1000000000000000000}}"#,
+        r#"{"jsonrpc": "2.0", "method": "run_frames", "params": {"count": 1}}"#,
+        r#"not json"#,
+        r#"{"jsonrpc": "2.0", "id": 8, "method": "run_frames", "params": {"count": 1}}"#,
+    ]
+    .join("\n");
+    let mut output = vec![];
+    serve(input.as_bytes(), &mut output, requests);
+    // The notification ran, the request after the line that isn't JSON didn't
+    assert_eq!(responder.join().unwrap(), 4);
+
+    let responses: Vec<Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(responses[0]["result"], Value::Null);
+    assert_eq!(responses[1]["result"], json!([0x67, 0x0C]));
+    assert_eq!(responses[3]["result"]["pc"], 1024);
+    assert_eq!(responses[3]["result"]["i"], 5);
+    assert_eq!(responses[4]["error"]["code"], INVALID_PARAMS);
+    assert_eq!(responses[5]["error"]["code"], METHOD_NOT_FOUND);
+    assert_eq!(responses[6]["error"]["code"], INVALID_PARAMS);
+    assert_eq!(responses[7]["error"]["code"], PARSE_ERROR);
+    assert_eq!(responses.len(), 8);
+}
+
+#[test]
+fn test_hex() {
+    assert_eq!(to_hex(&[0x00, 0xE0, 0xFF]), "00e0ff");
+    assert_eq!(from_hex("00e0ff"), Some(vec![0x00, 0xE0, 0xFF]));
+    assert_eq!(from_hex("00e"), None);
+}
+
+#[test]
+fn test_localhost_only() {
+    assert!(start("0.0.0.0:0").is_err());
+    assert!(start("192.168.1.2:0").is_err());
+    assert!(start("localhost").is_err());
+    assert!(start("127.0.0.1:0").is_ok());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket_over_file() {
+    let path = std::env::temp_dir().join(format!("chip-8-remote-{}.txt", std::process::id()));
+    std::fs::write(&path, "notes").unwrap();
+    let address = format!("unix:{}", path.to_str().unwrap());
+    assert!(start(&address).is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "notes");
+    std::fs::remove_file(&path).unwrap();
+
+    // A socket from a previous run is replaced
+    assert!(start(&address).is_ok());
+    assert!(start(&address).is_ok());
+    std::fs::remove_file(&path).unwrap();
+}
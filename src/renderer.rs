@@ -0,0 +1,114 @@
+use chip_8::chip8::{HEIGHT, WIDTH};
+use chip_8::display::Display;
+use chip_8::palette::{self, Palette, Rgb};
+use nannou::prelude::*;
+use std::cell::RefCell;
+
+// The display is a WIDTH x HEIGHT texture drawn as one quad, scaled up with nearest
+// filtering, instead of one rect per lit pixel. Its pixels are only uploaded again when
+// they changed, most frames of most programs leave the display as it was.
+pub struct DisplayTexture {
+    texture: wgpu::Texture,
+    // What the texture was drawn from, None before the first upload
+    uploaded: RefCell<Option<Uploaded>>,
+}
+
+// The display rows are compared first, they differ whenever a frame drew something. The
+// intensities change without them while pixels fade.
+struct Uploaded {
+    rows: Vec<u64>,
+    intensity: Vec<u8>,
+    colors: (Rgb, Rgb),
+}
+
+impl Uploaded {
+    fn matches(&self, display: &Display, intensity: &[u8], palette: &Palette) -> bool {
+        self.rows == display.rows()
+            && self.intensity == intensity
+            && self.colors == (palette.background, palette.foreground)
+    }
+}
+
+impl DisplayTexture {
+    pub fn new(device: &wgpu::Device) -> DisplayTexture {
+        let texture = wgpu::TextureBuilder::new()
+            .size([WIDTH as u32, HEIGHT as u32])
+            .format(wgpu::TextureFormat::Rgba8UnormSrgb)
+            .usage(wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::SAMPLED)
+            .build(device);
+
+        DisplayTexture {
+            texture,
+            uploaded: RefCell::new(None),
+        }
+    }
+
+    // Upload the phosphor intensities of display when they changed. The upload goes in the
+    // frame's commands, before the drawing of draw.to_frame.
+    pub fn update(&self, frame: &Frame, display: &Display, intensity: &[u8], palette: &Palette) {
+        let unchanged = self
+            .uploaded
+            .borrow()
+            .as_ref()
+            .is_some_and(|uploaded| uploaded.matches(display, intensity, palette));
+        if unchanged {
+            return;
+        }
+
+        let rgba = palette::to_rgba(palette, intensity);
+        let device = frame.device_queue_pair().device();
+        self.texture
+            .upload_data(device, &mut frame.command_encoder(), &rgba);
+        *self.uploaded.borrow_mut() = Some(Uploaded {
+            rows: display.rows().to_vec(),
+            intensity: intensity.to_vec(),
+            colors: (palette.background, palette.foreground),
+        });
+    }
+
+    // Draw the texture in rect, with pixel_gap window points between cells
+    pub fn draw(&self, draw: &Draw, rect: Rect, palette: &Palette, pixel_gap: f32) {
+        let sampler = wgpu::SamplerBuilder::new()
+            .mag_filter(wgpu::FilterMode::Nearest)
+            .min_filter(wgpu::FilterMode::Nearest)
+            .into_descriptor();
+        draw.sampler(sampler)
+            .texture(&self.texture)
+            .xy(rect.xy())
+            .wh(rect.wh());
+
+        if pixel_gap > 0.0 {
+            draw_gaps(draw, rect, palette, pixel_gap);
+        }
+    }
+}
+
+// Lines of background color over the cell edges: a few dozen rects whatever the display
+// shows. Pixels stay at least 1 point wide, and the edge cells keep half a gap outside.
+fn draw_gaps(draw: &Draw, rect: Rect, palette: &Palette, pixel_gap: f32) {
+    let cell_size = rect.w() / WIDTH as f32;
+    let gap = cell_size - (cell_size - pixel_gap).max(1.0);
+    if gap <= 0.0 {
+        return;
+    }
+    let [r, g, b] = palette.background;
+
+    for column in 0..=WIDTH {
+        let x = rect.left() + column as f32 * cell_size;
+        let left = (x - gap / 2.0).max(rect.left());
+        let right = (x + gap / 2.0).min(rect.right());
+        draw.rect()
+            .x_y((left + right) / 2.0, rect.y())
+            .w_h(right - left, rect.h())
+            .color(rgb8(r, g, b));
+    }
+    for row in 0..=HEIGHT {
+        let y = rect.top() - row as f32 * cell_size;
+        let top = (y + gap / 2.0).min(rect.top());
+        let bottom = (y - gap / 2.0).max(rect.bottom());
+        draw.rect()
+            .x_y(rect.x(), (top + bottom) / 2.0)
+            .w_h(rect.w(), top - bottom)
+            .color(rgb8(r, g, b));
+    }
+}
@@ -0,0 +1,452 @@
+use chip_8::chip8::{Quirks, MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+use chip_8::palette::{self, Rgb};
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+
+// ROM files in the formats programs are shared in, recognized by their content first,
+// then by their extension:
+//     Octo cartridge  .gif   Program and options hidden in the pixels of an image
+//     Zip archive     .zip   The first ROM found inside, in any of these formats but zip,
+//                            up to 64KB
+//     Intel HEX       .hex   `:LLAAAATT...CC` records, as written by EPROM tools
+//     Hex text        .txt   Bytes or opcodes as hex digits, e.g. `00E0 A22A 600C`
+//     Raw             other  The bytes as they are loaded in memory
+
+pub struct Rom {
+    pub bytes: Vec<u8>,
+
+    // Options embedded in the file, only Octo cartridges have them
+    pub options: Option<EmbeddedOptions>,
+}
+
+#[derive(Default)]
+pub struct EmbeddedOptions {
+    pub quirks: Option<Quirks>,
+    pub cycles_per_frame: Option<u32>,
+    // background, foreground
+    pub colors: Option<(Rgb, Rgb)>,
+    pub load_address: Option<u16>,
+    pub memory_size: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Zip(String),
+    NoRomInArchive,
+    // 1-based line number
+    Hex { line: usize, message: String },
+    Cartridge(String),
+    OctoCompiler(String),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{}", err),
+            LoadError::Zip(err) => write!(f, "invalid zip archive: {}", err),
+            LoadError::NoRomInArchive => write!(f, "no ROM found in the zip archive"),
+            LoadError::Hex { line, message } => write!(f, "line {}: {}", line, message),
+            LoadError::Cartridge(err) => write!(f, "invalid Octo cartridge: {}", err),
+            LoadError::OctoCompiler(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(err: std::io::Error) -> LoadError {
+        LoadError::Io(err)
+    }
+}
+
+const ROM_EXTENSIONS: [&str; 5] = ["ch8", "c8", "gif", "hex", "txt"];
+
+pub fn load(filepath: &str) -> Result<Rom, LoadError> {
+    decode(filepath, std::fs::read(filepath)?)
+}
+
+fn decode(filename: &str, data: Vec<u8>) -> Result<Rom, LoadError> {
+    let extension = Path::new(filename)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        load_octo_cartridge(&data)
+    } else if data.starts_with(b"PK\x03\x04") {
+        load_zip(data)
+    } else if extension == "hex" || extension == "ihx" || extension == "txt" {
+        let text = String::from_utf8_lossy(&data);
+        let bytes = if text.trim_start().starts_with(':') {
+            parse_intel_hex(&text)?
+        } else {
+            parse_hex_text(&text)?
+        };
+        Ok(Rom {
+            bytes,
+            options: None,
+        })
+    } else {
+        Ok(Rom {
+            bytes: data,
+            options: None,
+        })
+    }
+}
+
+fn load_zip(data: Vec<u8>) -> Result<Rom, LoadError> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(data)).map_err(|err| LoadError::Zip(err.to_string()))?;
+
+    let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+    names.sort();
+    let name = names
+        .into_iter()
+        .find(|name| {
+            let extension = Path::new(name)
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            ROM_EXTENSIONS.contains(&extension.as_str())
+        })
+        .ok_or(LoadError::NoRomInArchive)?;
+
+    let file = archive
+        .by_name(&name)
+        .map_err(|err| LoadError::Zip(err.to_string()))?;
+    // Not decompressed past what memory can hold, against zip bombs
+    let mut data = vec![];
+    file.take(XO_CHIP_MEMORY_SIZE as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.len() > XO_CHIP_MEMORY_SIZE {
+        return Err(LoadError::Zip(format!("{} is larger than 64KB", name)));
+    }
+    if data.starts_with(b"PK\x03\x04") {
+        return Err(LoadError::Zip(format!("{} is another zip archive", name)));
+    }
+
+    println!("Loading {} from the archive", name);
+    decode(&name, data)
+}
+
+// Data records are placed at their address. Addresses from 0x200 are taken as memory
+// addresses (a dump of the loaded program), lower ones as offsets in the ROM.
+fn parse_intel_hex(text: &str) -> Result<Vec<u8>, LoadError> {
+    let mut records = vec![];
+    let mut base_address = 0u32;
+
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: &str| LoadError::Hex {
+            line: i + 1,
+            message: String::from(message),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let record = line
+            .strip_prefix(':')
+            .ok_or_else(|| error("record doesn't start with ':'"))?;
+        let bytes = parse_hex_bytes(record).ok_or_else(|| error("invalid hex digits"))?;
+
+        if bytes.len() < 5 || bytes.len() != 5 + bytes[0] as usize {
+            return Err(error("wrong record length"));
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(error("wrong checksum"));
+        }
+
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            // Data
+            0x00 => {
+                // More than the 64KB an XO-CHIP can address is not a Chip-8 program
+                let address = base_address
+                    .checked_add(address)
+                    .filter(|address| {
+                        address
+                            .checked_add(data.len() as u32)
+                            .is_some_and(|end| end <= 0x10000)
+                    })
+                    .ok_or_else(|| error("address out of range"))?;
+                records.push((address, data.to_vec()))
+            }
+            // End of file
+            0x01 => break,
+            // Extended segment address, in 16 byte paragraphs
+            0x02 if data.len() == 2 => {
+                base_address = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4
+            }
+            // Extended linear address, upper 16 bits
+            0x04 if data.len() == 2 => {
+                base_address = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16
+            }
+            // Start addresses mean nothing for Chip-8
+            0x03 | 0x05 => {}
+            _ => return Err(error("unsupported record type")),
+        }
+    }
+
+    let start = records
+        .iter()
+        .map(|(address, _)| *address)
+        .min()
+        .unwrap_or(0);
+    let origin = if start >= 0x200 { 0x200 } else { 0 };
+
+    let mut bytes = vec![];
+    for (address, data) in records {
+        let offset = (address - origin) as usize;
+        if bytes.len() < offset + data.len() {
+            bytes.resize(offset + data.len(), 0);
+        }
+        bytes[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    Ok(bytes)
+}
+
+// Hex digits in any grouping: `00E0 A22A`, `0x00, 0xE0` or `00 E0 A2 2A`.
+// Comments start with # ; or //, and a leading `0200:` address column is skipped.
+fn parse_hex_text(text: &str) -> Result<Vec<u8>, LoadError> {
+    let mut bytes = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line = ["#", ";", "//"]
+            .iter()
+            .fold(line, |line, comment| line.split(comment).next().unwrap());
+
+        for token in line.split(|c: char| c.is_whitespace() || c == ',') {
+            if token.is_empty() || token.ends_with(':') {
+                continue;
+            }
+
+            let digits = token
+                .strip_prefix("0x")
+                .or_else(|| token.strip_prefix("0X"))
+                .unwrap_or(token);
+            let token_bytes = parse_hex_bytes(digits).ok_or_else(|| LoadError::Hex {
+                line: i + 1,
+                message: format!("invalid hex value {}", token),
+            })?;
+            bytes.extend(token_bytes);
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn parse_hex_bytes(digits: &str) -> Option<Vec<u8>> {
+    if digits.is_empty() || !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+// Octo cartridges are GIFs with a payload in the low bits of the colors: each pixel holds
+// a nibble (1 bit of red, 2 of green, 1 of blue), every 2 pixels a byte. The payload is a
+// 32 bit big endian length followed by JSON: {"program": "<Octo source>", "options": {...}}
+// https://github.com/JohnEarnest/Octo/tree/gh-pages/docs
+fn load_octo_cartridge(data: &[u8]) -> Result<Rom, LoadError> {
+    let payload = decode_octo_cartridge(data)?;
+    let json: serde_json::Value =
+        serde_json::from_slice(&payload).map_err(|err| LoadError::Cartridge(err.to_string()))?;
+
+    let source = json["program"]
+        .as_str()
+        .ok_or_else(|| LoadError::Cartridge(String::from("no program")))?;
+
+    Ok(Rom {
+        bytes: compile_octo(source)?,
+        options: Some(octo_options(&json["options"])),
+    })
+}
+
+fn decode_octo_cartridge(data: &[u8]) -> Result<Vec<u8>, LoadError> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options
+        .read_info(data)
+        .map_err(|err| LoadError::Cartridge(err.to_string()))?;
+    let global_palette = decoder.global_palette().map(|palette| palette.to_vec());
+
+    let mut bytes = vec![];
+    while let Some(frame) = decoder
+        .read_next_frame()
+        .map_err(|err| LoadError::Cartridge(err.to_string()))?
+    {
+        let palette = frame
+            .palette
+            .as_ref()
+            .or(global_palette.as_ref())
+            .ok_or_else(|| LoadError::Cartridge(String::from("no palette")))?;
+
+        let nibble = |index: u8| -> Option<u8> {
+            let color = palette.get(index as usize * 3..index as usize * 3 + 3)?;
+            Some(((color[0] & 1) << 3) | ((color[1] & 3) << 1) | (color[2] & 1))
+        };
+
+        for pixels in frame.buffer.chunks_exact(2) {
+            match (nibble(pixels[0]), nibble(pixels[1])) {
+                (Some(high), Some(low)) => bytes.push((high << 4) | low),
+                _ => return Err(LoadError::Cartridge(String::from("pixel outside palette"))),
+            }
+        }
+    }
+
+    if bytes.len() < 4 {
+        return Err(LoadError::Cartridge(String::from("no payload")));
+    }
+    let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    if bytes.len() < 4 + length {
+        return Err(LoadError::Cartridge(String::from("truncated payload")));
+    }
+
+    Ok(bytes[4..4 + length].to_vec())
+}
+
+// Cartridges hold Octo source, not bytecode, and there's no Octo compiler in this crate.
+// The command line compiler of c-octo (https://github.com/JohnEarnest/c-octo) is used
+// when it's installed. Both files go in a new directory with a random name, only the user
+// can open, so nothing planted in the shared temporary directory is written through.
+fn compile_octo(source: &str) -> Result<Vec<u8>, LoadError> {
+    let directory =
+        std::env::temp_dir().join(format!("chip-8-cartridge-{:016x}", rand::random::<u64>()));
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(&directory)?;
+
+    let result = compile_octo_in(&directory, source);
+    let _ = std::fs::remove_dir_all(&directory);
+    result
+}
+
+fn compile_octo_in(directory: &Path, source: &str) -> Result<Vec<u8>, LoadError> {
+    let source_path = directory.join("cartridge.8o");
+    let rom_path = directory.join("cartridge.ch8");
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&source_path)?
+        .write_all(source.as_bytes())?;
+
+    let result = std::process::Command::new("octo-cli")
+        .arg(&source_path)
+        .arg(&rom_path)
+        .output();
+
+    match result {
+        Ok(output) if output.status.success() => Ok(std::fs::read(&rom_path)?),
+        Ok(output) => Err(LoadError::OctoCompiler(format!(
+            "octo-cli failed to compile the cartridge: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+        Err(_) => Err(LoadError::OctoCompiler(String::from(
+            "Octo cartridges contain source code, install octo-cli from c-octo to compile it",
+        ))),
+    }
+}
+
+// Octo writes booleans as true/false or 1/0, and numbers as numbers or strings
+fn octo_options(options: &serde_json::Value) -> EmbeddedOptions {
+    let flag = |name: &str| match &options[name] {
+        serde_json::Value::Bool(value) => *value,
+        serde_json::Value::Number(value) => value.as_u64() != Some(0),
+        _ => false,
+    };
+    let color = |name: &str| options[name].as_str().and_then(palette::parse_color);
+
+    EmbeddedOptions {
+        quirks: Some(Quirks {
+            shift_vx: flag("shiftQuirks"),
+            load_store_increment_i: !flag("loadStoreQuirks"),
+            jump_vx: flag("jumpQuirks"),
+            clip_sprites: flag("clipQuirks"),
+            vf_reset: flag("logicQuirks"),
+            display_wait: flag("vBlankQuirks"),
+        }),
+        cycles_per_frame: match &options["tickrate"] {
+            serde_json::Value::Number(value) => value.as_u64().map(|value| value as u32),
+            serde_json::Value::String(value) => value.parse().ok(),
+            _ => None,
+        },
+        colors: color("backgroundColor").zip(color("fillColor")),
+        // Octo programs always start at 0x200, only the space they're allowed to use varies:
+        // 3216, 3583 or 3584 bytes for CHIP-8 and SCHIP, 65024 for XO-CHIP
+        load_address: None,
+        memory_size: options["maxSize"]
+            .as_u64()
+            .or_else(|| options["maxSize"].as_str()?.parse().ok())
+            .map(|max_size| {
+                if max_size as usize > MEMORY_SIZE - 0x200 {
+                    XO_CHIP_MEMORY_SIZE
+                } else {
+                    MEMORY_SIZE
+                }
+            }),
+    }
+}
+
+#[test]
+fn test_parse_hex() {
+    // 00E0 A22A at 0x200
+    let intel_hex = ":0402000000E0A22A4E\n:00000001FF\n";
+    assert_eq!(
+        parse_intel_hex(intel_hex).unwrap(),
+        [0x00, 0xE0, 0xA2, 0x2A]
+    );
+    assert!(parse_intel_hex(":0402000000E0A22A4F\n").is_err());
+    // Extended linear address 0xFFFF, then data at 0xFFFF
+    assert!(parse_intel_hex(":02000004FFFFFC\n:01FFFF000001\n").is_err());
+
+    let text = "0200: 00E0 A22A ; clear, I\n0x60, 0x0C # V0 = 12";
+    assert_eq!(
+        parse_hex_text(text).unwrap(),
+        [0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C]
+    );
+    assert!(parse_hex_text("00E").is_err());
+}
+
+#[test]
+fn test_decode_octo_cartridge() {
+    // Test cartridge of the decart crate (MIT), https://github.com/tobiasvl/decart
+    let payload = decode_octo_cartridge(include_bytes!("../tests/roms/octo-cart.gif")).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+    assert_eq!(json["program"], ": main");
+
+    let options = octo_options(&json["options"]);
+    assert_eq!(options.cycles_per_frame, Some(7));
+    assert_eq!(
+        options.colors,
+        Some(([0x99, 0x66, 0x00], [0xFF, 0xCC, 0x00]))
+    );
+    let quirks = options.quirks.unwrap();
+    assert!(quirks.clip_sprites && quirks.display_wait && quirks.vf_reset);
+    assert!(!quirks.shift_vx && quirks.load_store_increment_i);
+    assert_eq!(options.memory_size, Some(MEMORY_SIZE));
+}
+
+#[test]
+fn test_load_zip() {
+    let zip = |name: &str, data: &[u8]| {
+        let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        writer
+            .start_file(name, zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap().into_inner()
+    };
+    assert_eq!(
+        load_zip(zip("a.ch8", &[0x12, 0x00])).unwrap().bytes,
+        [0x12, 0x00]
+    );
+    assert!(load_zip(zip("a.ch8", &vec![0; XO_CHIP_MEMORY_SIZE + 1])).is_err());
+    assert!(load_zip(zip("a.ch8", &zip("b.ch8", &[0x12, 0x00]))).is_err());
+}
@@ -0,0 +1,359 @@
+use chip_8::chip8::{self, Chip8};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+// Rhai scripts run alongside the emulated program, from --script FILE. The top level of
+// the script runs once when it is loaded, before the program starts (the place for
+// watch_pc and set_var); these functions, when the script defines them,
+// are then called back:
+//
+//   on_frame()       after each emulated frame
+//   on_pc(pc)        before the instruction at an address passed to watch_pc(addr)
+//   on_draw(x, y, n) before each DRW, with the values of Vx and Vy
+//   on_buzzer(on)    when the buzzer starts or stops, checked at the end of each frame
+//
+// Scripts read and change the machine with reg(x), set_reg(x, value), reg_i(),
+// set_reg_i(value), pc(), set_pc(addr), delay_timer(), set_delay_timer(value),
+// sound_timer(), set_sound_timer(value), peek(addr), poke(addr, value), pixel(x, y),
+// key(k), press(k) and release(k). Rhai functions can't see the script's variables, so
+// set_var(name, value) and get_var(name) keep values between callbacks. frame() counts the
+// emulated frames. text(x, y, message) shows a line over the display, at display
+// coordinates, until clear_text() or another text at the same place.
+//
+// A script error stops the callbacks and is shown over the display. So does a callback, or
+// the top level, running more than MAX_OPERATIONS operations: the emulator doesn't hang on
+// an endless loop.
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+pub struct Overlay {
+    pub x: i64,
+    pub y: i64,
+    pub text: String,
+}
+
+// What the functions registered in the engine work on
+struct Shared {
+    // The emulated machine while a callback runs, swapped with the frontend's
+    chip8: Chip8,
+    watched_pcs: HashSet<u16>,
+    vars: HashMap<String, Dynamic>,
+    overlays: Vec<Overlay>,
+    frame: i64,
+}
+
+// Callbacks the script defines
+struct Callbacks {
+    on_frame: bool,
+    on_pc: bool,
+    on_draw: bool,
+    on_buzzer: bool,
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    shared: Rc<RefCell<Shared>>,
+    callbacks: Callbacks,
+    buzzer_on: bool,
+    error: Option<String>,
+}
+
+impl Script {
+    pub fn load(filepath: &str) -> Result<Script, String> {
+        let source = std::fs::read_to_string(filepath)
+            .map_err(|err| format!("Error reading script {} : {}", filepath, err))?;
+        Script::compile(&source).map_err(|err| format!("Error in script {} : {}", filepath, err))
+    }
+
+    fn compile(source: &str) -> Result<Script, String> {
+        let shared = Rc::new(RefCell::new(Shared {
+            chip8: Chip8::new(&[]),
+            watched_pcs: HashSet::new(),
+            vars: HashMap::new(),
+            overlays: vec![],
+            frame: 0,
+        }));
+
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        register_functions(&mut engine, &shared);
+
+        let ast = engine.compile(source).map_err(|err| err.to_string())?;
+        let defines = |name: &str, params: usize| {
+            ast.iter_functions()
+                .any(|function| function.name == name && function.params.len() == params)
+        };
+        let callbacks = Callbacks {
+            on_frame: defines("on_frame", 0),
+            on_pc: defines("on_pc", 1),
+            on_draw: defines("on_draw", 3),
+            on_buzzer: defines("on_buzzer", 1),
+        };
+
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|err| err.to_string())?;
+
+        Ok(Script {
+            engine,
+            ast,
+            scope,
+            shared,
+            callbacks,
+            buzzer_on: false,
+            error: None,
+        })
+    }
+
+    // chip8::run_frame, with the script's callbacks
+    pub fn run_frame(&mut self, chip8: &mut Chip8) {
+        if self.error.is_some() {
+            chip8::run_frame(chip8);
+            return;
+        }
+
+        if self.callbacks.on_pc || self.callbacks.on_draw {
+            chip8::run_frame_with_hook(chip8, &mut |chip8| self.before_instruction(chip8));
+        } else {
+            chip8::run_frame(chip8);
+        }
+        self.shared.borrow_mut().frame += 1;
+
+        let buzzer_on = chip8::is_buzzer_on(chip8);
+        if buzzer_on != self.buzzer_on {
+            self.buzzer_on = buzzer_on;
+            if self.callbacks.on_buzzer {
+                self.call(chip8, "on_buzzer", (buzzer_on,));
+            }
+        }
+
+        if self.callbacks.on_frame {
+            self.call(chip8, "on_frame", ());
+        }
+    }
+
+    fn before_instruction(&mut self, chip8: &mut Chip8) {
+        let pc = chip8.pc;
+        if self.callbacks.on_pc && self.shared.borrow().watched_pcs.contains(&pc) {
+            self.call(chip8, "on_pc", (pc as i64,));
+        }
+
+        // The on_pc callback can move pc
+        let pc = chip8.pc as usize;
+        if self.callbacks.on_draw && pc + 1 < chip8.memory.len() && chip8.memory[pc] >> 4 == 0xD {
+            let x = chip8.registers[(chip8.memory[pc] & 0xF) as usize];
+            let y = chip8.registers[(chip8.memory[pc + 1] >> 4) as usize];
+            let n = chip8.memory[pc + 1] & 0xF;
+            self.call(chip8, "on_draw", (x as i64, y as i64, n as i64));
+        }
+    }
+
+    fn call(&mut self, chip8: &mut Chip8, name: &str, args: impl rhai::FuncArgs) {
+        if self.error.is_some() {
+            return;
+        }
+
+        std::mem::swap(chip8, &mut self.shared.borrow_mut().chip8);
+        // The top level already ran when the script was loaded
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            name,
+            args,
+        );
+        std::mem::swap(chip8, &mut self.shared.borrow_mut().chip8);
+
+        if let Err(err) = result {
+            let message = format!("Script error in {} : {}", name, err);
+            println!("{}", message);
+            self.error = Some(message);
+        }
+    }
+
+    // Lines to draw over the display, the script's error last
+    pub fn overlays(&self) -> Vec<Overlay> {
+        let mut overlays: Vec<Overlay> = self
+            .shared
+            .borrow()
+            .overlays
+            .iter()
+            .map(|overlay| Overlay {
+                x: overlay.x,
+                y: overlay.y,
+                text: overlay.text.clone(),
+            })
+            .collect();
+        if let Some(error) = self.error.as_ref() {
+            overlays.push(Overlay {
+                x: 0,
+                y: chip8::HEIGHT as i64 - 4,
+                text: error.clone(),
+            });
+        }
+        overlays
+    }
+}
+
+fn register_functions(engine: &mut Engine, shared: &Rc<RefCell<Shared>>) {
+    let s = shared.clone();
+    engine.register_fn("reg", move |x: i64| -> ScriptResult<i64> {
+        let x = index(x, 16, "register")?;
+        Ok(s.borrow().chip8.registers[x] as i64)
+    });
+    let s = shared.clone();
+    engine.register_fn("set_reg", move |x: i64, value: i64| -> ScriptResult<()> {
+        let x = index(x, 16, "register")?;
+        s.borrow_mut().chip8.registers[x] = value as u8;
+        Ok(())
+    });
+
+    let s = shared.clone();
+    engine.register_fn("reg_i", move || s.borrow().chip8.register_i as i64);
+    let s = shared.clone();
+    engine.register_fn("set_reg_i", move |value: i64| {
+        s.borrow_mut().chip8.register_i = value as u16;
+    });
+
+    let s = shared.clone();
+    engine.register_fn("pc", move || s.borrow().chip8.pc as i64);
+    let s = shared.clone();
+    engine.register_fn("set_pc", move |addr: i64| -> ScriptResult<()> {
+        let mut shared = s.borrow_mut();
+        let addr = index(addr, shared.chip8.memory.len(), "address")?;
+        shared.chip8.pc = addr as u16;
+        Ok(())
+    });
+
+    let s = shared.clone();
+    engine.register_fn("delay_timer", move || s.borrow().chip8.timer_delay as i64);
+    let s = shared.clone();
+    engine.register_fn("set_delay_timer", move |value: i64| {
+        s.borrow_mut().chip8.timer_delay = value as u8;
+    });
+    let s = shared.clone();
+    engine.register_fn("sound_timer", move || s.borrow().chip8.timer_sound as i64);
+    let s = shared.clone();
+    engine.register_fn("set_sound_timer", move |value: i64| {
+        s.borrow_mut().chip8.timer_sound = value as u8;
+    });
+
+    let s = shared.clone();
+    engine.register_fn("peek", move |addr: i64| -> ScriptResult<i64> {
+        let shared = s.borrow();
+        let addr = index(addr, shared.chip8.memory.len(), "address")?;
+        Ok(shared.chip8.memory[addr] as i64)
+    });
+    let s = shared.clone();
+    engine.register_fn("poke", move |addr: i64, value: i64| -> ScriptResult<()> {
+        let mut shared = s.borrow_mut();
+        let addr = index(addr, shared.chip8.memory.len(), "address")?;
+        shared.chip8.memory[addr] = value as u8;
+        Ok(())
+    });
+
+    let s = shared.clone();
+    engine.register_fn("pixel", move |x: i64, y: i64| -> ScriptResult<bool> {
+        let x = index(x, chip8::WIDTH as usize, "x")?;
+        let y = index(y, chip8::HEIGHT as usize, "y")?;
+        Ok(s.borrow().chip8.display.get(x, y))
+    });
+
+    let s = shared.clone();
+    engine.register_fn("key", move |k: i64| -> ScriptResult<bool> {
+        let k = index(k, 16, "key")?;
+        Ok(s.borrow().chip8.keys[k])
+    });
+    let s = shared.clone();
+    engine.register_fn("press", move |k: i64| -> ScriptResult<()> {
+        let k = index(k, 16, "key")?;
+        chip8::key_pressed(&mut s.borrow_mut().chip8, k as u8);
+        Ok(())
+    });
+    let s = shared.clone();
+    engine.register_fn("release", move |k: i64| -> ScriptResult<()> {
+        let k = index(k, 16, "key")?;
+        chip8::key_released(&mut s.borrow_mut().chip8, k as u8);
+        Ok(())
+    });
+
+    let s = shared.clone();
+    engine.register_fn("frame", move || s.borrow().frame);
+
+    let s = shared.clone();
+    engine.register_fn("watch_pc", move |addr: i64| {
+        s.borrow_mut().watched_pcs.insert(addr as u16);
+    });
+
+    let s = shared.clone();
+    engine.register_fn("set_var", move |name: &str, value: Dynamic| {
+        s.borrow_mut().vars.insert(String::from(name), value);
+    });
+    let s = shared.clone();
+    engine.register_fn("get_var", move |name: &str| {
+        s.borrow().vars.get(name).cloned().unwrap_or(Dynamic::UNIT)
+    });
+
+    let s = shared.clone();
+    engine.register_fn("text", move |x: i64, y: i64, text: &str| {
+        let mut shared = s.borrow_mut();
+        shared
+            .overlays
+            .retain(|overlay| overlay.x != x || overlay.y != y);
+        shared.overlays.push(Overlay {
+            x,
+            y,
+            text: String::from(text),
+        });
+    });
+    let s = shared.clone();
+    engine.register_fn("clear_text", move || s.borrow_mut().overlays.clear());
+}
+
+fn index(value: i64, len: usize, what: &str) -> ScriptResult<usize> {
+    if value >= 0 && (value as usize) < len {
+        Ok(value as usize)
+    } else {
+        Err(format!("{} {} out of range", what, value).into())
+    }
+}
+
+#[test]
+fn test_script() {
+    let source = r#"
+        set_var("draws", 0);
+        watch_pc(0x200);
+
+        fn on_pc(pc) { set_var("start", pc); }
+        fn on_draw(x, y, n) { set_var("draws", get_var("draws") + 1); }
+        fn on_frame() { text(1, 2, `${frame()} ${get_var("start")} ${get_var("draws") > 0}`); }
+    "#;
+    let mut script = Script::compile(source).unwrap();
+    let mut chip8 = Chip8::new(&std::fs::read("tests/roms/clock.ch8").unwrap());
+    for _frame in 0..10 {
+        script.run_frame(&mut chip8);
+    }
+    let overlays = script.overlays();
+    assert_eq!(overlays.len(), 1);
+    assert_eq!((overlays[0].x, overlays[0].y), (1, 2));
+    assert_eq!(overlays[0].text, "10 512 true");
+
+    let mut script = Script::compile("fn on_frame() { reg(16) }").unwrap();
+    script.run_frame(&mut chip8);
+    assert!(script.overlays()[0].text.contains("out of range"));
+
+    assert!(Script::compile("fn on_frame( {").is_err());
+
+    let mut script = Script::compile("fn on_frame() { loop {} }").unwrap();
+    script.run_frame(&mut chip8);
+    assert!(script.overlays()[0].text.contains("operations"));
+    assert!(Script::compile("loop {}").is_err());
+}
@@ -0,0 +1,202 @@
+// Save states: a snapshot of the machine in a little endian binary format.
+// The size only depends on the memory size, so it's fixed for a given program.
+// Quirks and speed are configuration and stay as they are when a state is loaded.
+// Keys are live input, they aren't saved either.
+
+use crate::chip8::{Chip8, MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+use crate::display::{self, Display};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+// 2: memory size added, memory up to 64KB
+const VERSION: u8 = 2;
+
+// Header: magic, version, memory size
+const HEADER_SIZE: usize = MAGIC.len() + 1 + 4;
+
+// Everything but the memory
+const FIXED_SIZE: usize = HEADER_SIZE
+    + display::SIZE // display, one byte per pixel
+    + 16 // registers
+    + 2 // I
+    + 2 // timers
+    + 2 // pc
+    + 2 // sp
+    + 16 * 2 // stack
+    + 1 // needs_clear
+    + 1 // hold_for_key, 0xFF when not holding
+    + 4 // rng_state
+    + 1; // wait_for_frame
+
+pub fn state_size(chip8: &Chip8) -> usize {
+    FIXED_SIZE + chip8.memory.len()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+    WrongSize(usize),
+    NotAState,
+    UnsupportedVersion(u8),
+}
+
+impl std::fmt::Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StateError::WrongSize(size) => {
+                write!(f, "state is {} bytes, doesn't match its memory size", size)
+            }
+            StateError::NotAState => write!(f, "not a chip-8 save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+        }
+    }
+}
+
+pub fn save_state(chip8: &Chip8) -> Vec<u8> {
+    let mut state = Vec::with_capacity(state_size(chip8));
+
+    state.extend_from_slice(MAGIC);
+    state.push(VERSION);
+    state.extend_from_slice(&(chip8.memory.len() as u32).to_le_bytes());
+    state.extend_from_slice(&chip8.display.to_bytes());
+    state.extend_from_slice(&chip8.memory);
+    state.extend_from_slice(&chip8.registers);
+    state.extend_from_slice(&chip8.register_i.to_le_bytes());
+    state.push(chip8.timer_sound);
+    state.push(chip8.timer_delay);
+    state.extend_from_slice(&chip8.pc.to_le_bytes());
+    state.extend_from_slice(&chip8.sp.to_le_bytes());
+    for address in chip8.stack.iter() {
+        state.extend_from_slice(&address.to_le_bytes());
+    }
+    state.push(chip8.needs_clear as u8);
+    state.push(chip8.hold_for_key.unwrap_or(0xFF));
+    state.extend_from_slice(&chip8.rng_state.to_le_bytes());
+    state.push(chip8.wait_for_frame as u8);
+
+    state
+}
+
+// The machine is left untouched when the state is invalid
+pub fn load_state(chip8: &mut Chip8, state: &[u8]) -> Result<(), StateError> {
+    if state.len() < HEADER_SIZE || &state[..MAGIC.len()] != MAGIC {
+        return Err(StateError::NotAState);
+    }
+    if state[MAGIC.len()] != VERSION {
+        return Err(StateError::UnsupportedVersion(state[MAGIC.len()]));
+    }
+
+    let mut reader = Reader {
+        data: state,
+        position: MAGIC.len() + 1,
+    };
+
+    let memory_size = u32::from_le_bytes([reader.u8(), reader.u8(), reader.u8(), reader.u8()]);
+    if (memory_size as usize) < MEMORY_SIZE
+        || memory_size as usize > XO_CHIP_MEMORY_SIZE
+        || state.len() != FIXED_SIZE + memory_size as usize
+    {
+        return Err(StateError::WrongSize(state.len()));
+    }
+    // Before rng_state and wait_for_frame. Fx0A waits with one of V0 to VF.
+    let hold_for_key = match state[state.len() - 6] {
+        0xFF => None,
+        register if register < 16 => Some(register),
+        _ => return Err(StateError::NotAState),
+    };
+
+    chip8.display = Display::from_bytes(reader.bytes(display::SIZE));
+    // In place when the size matches: frontends keep pointers into memory
+    let memory = reader.bytes(memory_size as usize);
+    if chip8.memory.len() == memory.len() {
+        chip8.memory.copy_from_slice(memory);
+    } else {
+        chip8.memory = memory.to_vec();
+    }
+    chip8.registers.copy_from_slice(reader.bytes(16));
+    chip8.register_i = reader.u16();
+    chip8.timer_sound = reader.u8();
+    chip8.timer_delay = reader.u8();
+    chip8.pc = reader.u16();
+    chip8.sp = reader.u16();
+    for address in chip8.stack.iter_mut() {
+        *address = reader.u16();
+    }
+    chip8.needs_clear = reader.u8() != 0;
+    reader.u8();
+    chip8.hold_for_key = hold_for_key;
+    chip8.rng_state = u32::from_le_bytes([reader.u8(), reader.u8(), reader.u8(), reader.u8()]);
+    chip8.wait_for_frame = reader.u8() != 0;
+
+    Ok(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> &'a [u8] {
+        let bytes = &self.data[self.position..self.position + count];
+        self.position += count;
+        bytes
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes([self.u8(), self.u8()])
+    }
+}
+
+#[test]
+fn test_save_and_load_state() {
+    let rom = include_bytes!("../tests/roms/random.ch8");
+    let mut chip8 = Chip8::new(rom);
+    crate::chip8::seed_rng(&mut chip8, 1234);
+    for _ in 0..30 {
+        crate::chip8::run_frame(&mut chip8);
+    }
+
+    let state = save_state(&chip8);
+    assert_eq!(state.len(), state_size(&chip8));
+
+    // Both machines must follow the same path from there, RND included
+    let mut restored = Chip8::new(&[]);
+    load_state(&mut restored, &state).unwrap();
+    for _ in 0..30 {
+        crate::chip8::run_frame(&mut chip8);
+        crate::chip8::run_frame(&mut restored);
+    }
+    assert_eq!(save_state(&restored), save_state(&chip8));
+
+    assert_eq!(
+        load_state(&mut restored, &state[..state.len() - 1]),
+        Err(StateError::WrongSize(state.len() - 1))
+    );
+}
+
+#[test]
+fn test_load_state_holding_for_key() {
+    let mut chip8 = Chip8::new(&[0xF5, 0x0A]);
+    crate::chip8::run_frame(&mut chip8);
+    let mut state = save_state(&chip8);
+    let hold_for_key = state.len() - 6;
+    assert_eq!(state[hold_for_key], 5);
+
+    let mut restored = Chip8::new(&[0x12, 0x00]);
+    state[hold_for_key] = 0x20;
+    assert_eq!(
+        load_state(&mut restored, &state),
+        Err(StateError::NotAState)
+    );
+    assert_eq!(restored.memory[0x200..0x202], [0x12, 0x00]);
+
+    state[hold_for_key] = 0xF;
+    load_state(&mut restored, &state).unwrap();
+    crate::chip8::key_pressed(&mut restored, 3);
+    assert_eq!(restored.registers[0xF], 3);
+}
@@ -0,0 +1,43 @@
+// Buzzer tone generated from emulated time, not wall-clock time: every emulated frame
+// produces exactly SAMPLE_RATE / 60 samples, so the same program always sounds the same.
+// Used for WAV recordings and by embedders that have to push audio themselves.
+
+pub const SAMPLE_RATE: u32 = 44100;
+pub const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / 60) as usize;
+const AMPLITUDE: f32 = 0.25;
+
+pub struct Tone {
+    // Frequency of the buzzer tone, in hz
+    frequency: u32,
+
+    // Position in the sine wave, in samples since the buzzer started
+    phase: u32,
+}
+
+impl Tone {
+    pub fn new(frequency: u32) -> Tone {
+        Tone {
+            frequency,
+            phase: 0,
+        }
+    }
+
+    // One emulated frame (1/60s) of mono samples, between -1.0 and 1.0
+    pub fn next_frame(&mut self, buzzer_on: bool) -> [f32; SAMPLES_PER_FRAME] {
+        let mut samples = [0.0; SAMPLES_PER_FRAME];
+
+        if buzzer_on {
+            for sample in samples.iter_mut() {
+                let t = self.phase as f32 / SAMPLE_RATE as f32;
+                self.phase += 1;
+                *sample =
+                    (t * self.frequency as f32 * 2.0 * std::f32::consts::PI).sin() * AMPLITUDE;
+            }
+        } else {
+            // Restart the wave from zero on the next beep to avoid a click
+            self.phase = 0;
+        }
+
+        samples
+    }
+}
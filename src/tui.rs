@@ -0,0 +1,249 @@
+use crate::options::Options;
+use chip_8::cheat;
+use chip_8::chip8::{self, Chip8, HEIGHT, WIDTH};
+use chip_8::palette;
+use chip_8::phosphor::Phosphor;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Color, Print, SetBackgroundColor, SetForegroundColor};
+use crossterm::{cursor, queue, terminal};
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+// Terminals report key presses and auto-repeats, but never key releases.
+// A fresh press is held long enough to bridge the auto-repeat delay (~500ms),
+// then each repeat keeps the key down a little longer. The key is released
+// when the repeats stop.
+const KEY_PRESS_FRAMES: u8 = 30;
+const KEY_REPEAT_FRAMES: u8 = 4;
+
+const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / 60);
+
+// Display on the left, 2 display rows per terminal row, registers on the right.
+// Meant for SSH sessions where the nannou window can't be opened.
+pub fn run(mut options: Options) {
+    let (mut chip8, rom_info) =
+        crate::load_chip8(&mut options).unwrap_or_else(|err| crate::exit_with_error(&err));
+    let options = &options;
+    let key_bindings = rom_info
+        .as_ref()
+        .map(|rom_info| rom_info.keys.clone())
+        .unwrap_or_default();
+    let title = rom_info.map(|rom_info| rom_info.title_line());
+    chip8::seed_rng(&mut chip8, options.seed.unwrap_or_else(rand::random));
+    let mut phosphor = Phosphor::new(options.persistence);
+    // Always on, toggling and searching are only in the window
+    let cheats = crate::load_cheats(options).unwrap_or_else(|err| crate::exit_with_error(&err));
+    // Text overlays are only drawn in the window
+    let mut script = crate::load_script(options).unwrap_or_else(|err| crate::exit_with_error(&err));
+
+    let (palettes, mut palette_index) = crate::create_palettes(options);
+
+    // Frames left before each key is released
+    let mut key_frames = [0u8; 16];
+    let mut buzzer_was_on = false;
+
+    let mut stdout = std::io::stdout();
+    // The terminal is back to normal before an error is shown
+    let result = Terminal::enter(&mut stdout).and_then(|_terminal| {
+        if let Some(title) = title {
+            queue!(stdout, cursor::MoveTo(0, HEIGHT as u16 / 2), Print(title))?;
+        }
+
+        let mut next_frame = Instant::now();
+        'emulation: loop {
+            while event::poll(next_frame.saturating_duration_since(Instant::now()))? {
+                if let Event::Key(KeyEvent { code, modifiers }) = event::read()? {
+                    match code {
+                        KeyCode::Esc => break 'emulation,
+                        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                            break 'emulation
+                        }
+                        KeyCode::F(1) => palette_index = (palette_index + 1) % palettes.len(),
+                        _ => {
+                            if let Some(key_index) =
+                                key_code_to_chip8_key_index(code, &key_bindings)
+                            {
+                                let frames = &mut key_frames[key_index as usize];
+                                *frames = if *frames > 0 {
+                                    KEY_REPEAT_FRAMES
+                                } else {
+                                    KEY_PRESS_FRAMES
+                                };
+                                chip8::key_pressed(&mut chip8, key_index);
+                            }
+                        }
+                    }
+                }
+            }
+            next_frame += FRAME_DURATION;
+            // After a stall (slow link, suspended terminal) the missed frames are skipped, not
+            // run back to back
+            if next_frame < Instant::now() {
+                next_frame = Instant::now();
+            }
+
+            for (key_index, frames) in key_frames.iter_mut().enumerate() {
+                if *frames > 0 {
+                    *frames -= 1;
+                    if *frames == 0 {
+                        chip8::key_released(&mut chip8, key_index as u8);
+                    }
+                }
+            }
+
+            cheat::apply(&cheats, &mut chip8);
+            match script.as_mut() {
+                Some(script) => script.run_frame(&mut chip8),
+                None => chip8::run_frame(&mut chip8),
+            }
+            phosphor.update(&chip8.display);
+
+            // No audio device over SSH, ring the terminal bell instead
+            let buzzer_on = chip8::is_buzzer_on(&chip8);
+            if buzzer_on && !buzzer_was_on {
+                queue!(stdout, Print('\x07'))?;
+            }
+            buzzer_was_on = buzzer_on;
+
+            draw(&mut stdout, &chip8, &phosphor, &palettes[palette_index])?;
+        }
+        Ok(())
+    });
+    if let Err(err) = result {
+        crate::exit_with_error(&format!("Error in the terminal : {}", err));
+    }
+}
+
+// Raw mode and the alternate screen, left when dropped: on errors and panics too
+struct Terminal;
+
+impl Terminal {
+    fn enter(stdout: &mut std::io::Stdout) -> crossterm::Result<Terminal> {
+        terminal::enable_raw_mode()?;
+        let terminal = Terminal;
+        queue!(
+            stdout,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+        Ok(terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = std::io::stdout();
+        let _ = queue!(stdout, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn draw(
+    stdout: &mut std::io::Stdout,
+    chip8: &Chip8,
+    phosphor: &Phosphor,
+    palette: &palette::Palette,
+) -> crossterm::Result<()> {
+    let intensity = phosphor.intensity();
+    let color = |px_intensity: u8| {
+        let [r, g, b] = palette::blend(palette, px_intensity);
+        Color::Rgb { r, g, b }
+    };
+
+    // Upper half block: foreground is the top pixel, background the bottom one. Colors are
+    // only sent when they differ from the previous cell's, over a slow link it adds up.
+    for row in 0..(HEIGHT / 2) {
+        queue!(stdout, cursor::MoveTo(0, row as u16))?;
+        let (mut foreground, mut background) = (None, None);
+        for column in 0..WIDTH as usize {
+            let top = color(intensity[(row as usize * 2) * WIDTH as usize + column]);
+            let bottom = color(intensity[(row as usize * 2 + 1) * WIDTH as usize + column]);
+            if foreground != Some(top) {
+                queue!(stdout, SetForegroundColor(top))?;
+                foreground = Some(top);
+            }
+            if background != Some(bottom) {
+                queue!(stdout, SetBackgroundColor(bottom))?;
+                background = Some(bottom);
+            }
+            queue!(stdout, Print('▀'))?;
+        }
+        queue!(
+            stdout,
+            SetForegroundColor(Color::Reset),
+            SetBackgroundColor(Color::Reset)
+        )?;
+    }
+
+    let register_column = WIDTH as u16 + 2;
+    for (i, register) in chip8.registers.iter().enumerate() {
+        let (x, y) = (register_column + (i as u16 / 8) * 9, i as u16 % 8);
+        queue!(
+            stdout,
+            cursor::MoveTo(x, y),
+            Print(format!("V{:X} {:02X}", i, register))
+        )?;
+    }
+
+    let status = [
+        format!("PC {:03X}", chip8.pc),
+        format!("I  {:03X}", chip8.register_i),
+        format!("SP {:X} ", chip8.sp),
+        format!("DT {:02X} ST {:02X}", chip8.timer_delay, chip8.timer_sound),
+        String::from("Esc: quit"),
+    ];
+    for (i, line) in status.iter().enumerate() {
+        queue!(
+            stdout,
+            cursor::MoveTo(register_column, 9 + i as u16),
+            Print(line)
+        )?;
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
+
+/*
+1 2 3 C  =>  1 2 3 4
+4 5 6 D  =>  q w e r
+7 8 9 E  =>  a s d f
+A 0 B F  =>  z x c v
+*/
+fn key_code_to_chip8_key_index(code: KeyCode, key_bindings: &HashMap<String, u8>) -> Option<u8> {
+    let c = match code {
+        KeyCode::Char(c) => c,
+        KeyCode::Up => return key_bindings.get("up").copied(),
+        KeyCode::Down => return key_bindings.get("down").copied(),
+        KeyCode::Left => return key_bindings.get("left").copied(),
+        KeyCode::Right => return key_bindings.get("right").copied(),
+        KeyCode::Enter => return key_bindings.get("b").copied(),
+        _ => return None,
+    };
+    if c == ' ' {
+        return key_bindings.get("a").copied();
+    }
+
+    match c.to_ascii_lowercase() {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None,
+    }
+}
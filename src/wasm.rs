@@ -0,0 +1,73 @@
+// JavaScript API of the emulator core, built with:
+//     wasm-pack build --target web
+// The page drives the timing: call runFrame() 60 times per second (requestAnimationFrame),
+// draw framebuffer() and play a tone while buzzer() is true.
+
+use crate::chip8::{self, Chip8, HEIGHT, LOAD_ADDRESS, MEMORY_SIZE, WIDTH};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct Machine {
+    chip8: Chip8,
+}
+
+#[wasm_bindgen]
+impl Machine {
+    // Throws when the ROM is empty or doesn't fit in memory
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<Machine, JsValue> {
+        Ok(Machine { chip8: load(rom)? })
+    }
+
+    // Restart with another program. The keys and the RND seed are reset too.
+    // Throws like the constructor, the current program keeps running then.
+    #[wasm_bindgen(js_name = loadRom)]
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        self.chip8 = load(rom)?;
+        Ok(())
+    }
+
+    pub fn seed(&mut self, seed: u32) {
+        chip8::seed_rng(&mut self.chip8, seed);
+    }
+
+    #[wasm_bindgen(js_name = runFrame)]
+    pub fn run_frame(&mut self) {
+        chip8::run_frame(&mut self.chip8);
+    }
+
+    // One byte per pixel, 0 or 1, row by row. Returned to JavaScript as a Uint8Array copy.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.chip8.display.to_bytes()
+    }
+
+    pub fn width(&self) -> u32 {
+        WIDTH as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        HEIGHT as u32
+    }
+
+    // key is the Chip-8 key, 0x0 to 0xF
+    #[wasm_bindgen(js_name = setKey)]
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        if key > 0xF {
+            return;
+        }
+
+        if pressed {
+            chip8::key_pressed(&mut self.chip8, key);
+        } else {
+            chip8::key_released(&mut self.chip8, key);
+        }
+    }
+
+    pub fn buzzer(&self) -> bool {
+        chip8::is_buzzer_on(&self.chip8)
+    }
+}
+
+fn load(rom: &[u8]) -> Result<Chip8, JsValue> {
+    Chip8::load(rom, LOAD_ADDRESS, MEMORY_SIZE).map_err(|err| JsValue::from_str(&err.to_string()))
+}
@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant, SystemTime};
+
+// Polls the modification time of a program's source files, to reassemble it when one of
+// them is saved. Polling is plenty for a handful of files edited by hand.
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct SourceWatcher {
+    // None when the file can't be read, e.g. while an editor replaces it
+    files: Vec<(String, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl SourceWatcher {
+    pub fn new(filepaths: Vec<String>) -> SourceWatcher {
+        let mut watcher = SourceWatcher {
+            files: vec![],
+            last_poll: Instant::now(),
+        };
+        watcher.set_files(filepaths);
+        watcher
+    }
+
+    // Includes can be added or removed by an edit
+    pub fn set_files(&mut self, filepaths: Vec<String>) {
+        self.files = filepaths
+            .into_iter()
+            .map(|filepath| {
+                let modified = modified_time(&filepath);
+                (filepath, modified)
+            })
+            .collect();
+    }
+
+    // True once for each change, at most every POLL_INTERVAL
+    pub fn changed(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = false;
+        for (filepath, modified) in self.files.iter_mut() {
+            let current = modified_time(filepath);
+            if current.is_some() && current != *modified {
+                changed = true;
+            }
+            *modified = current;
+        }
+        changed
+    }
+}
+
+fn modified_time(filepath: &str) -> Option<SystemTime> {
+    std::fs::metadata(filepath)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
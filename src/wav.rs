@@ -0,0 +1,38 @@
+use chip_8::tone::{Tone, SAMPLE_RATE};
+use std::fs::File;
+use std::io::BufWriter;
+
+pub struct WavRecorder {
+    writer: hound::WavWriter<BufWriter<File>>,
+    tone: Tone,
+}
+
+impl WavRecorder {
+    pub fn create(filepath: &str, frequency: u32) -> Result<WavRecorder, hound::Error> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        Ok(WavRecorder {
+            writer: hound::WavWriter::create(filepath, spec)?,
+            tone: Tone::new(frequency),
+        })
+    }
+
+    // Append one emulated frame (1/60s) of audio
+    pub fn push_frame(&mut self, buzzer_on: bool) -> Result<(), hound::Error> {
+        for sample in self.tone.next_frame(buzzer_on).iter() {
+            self.writer
+                .write_sample((sample * i16::MAX as f32) as i16)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finalize(self) -> Result<(), hound::Error> {
+        self.writer.finalize()
+    }
+}
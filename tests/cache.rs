@@ -0,0 +1,84 @@
+// The decoded instruction cache runs programs exactly like the interpreter: both machines
+// are compared, save state against save state, after every frame.
+
+mod common;
+
+use chip_8::assembler;
+use chip_8::cache::{self, DecodeCache};
+use chip_8::chip8::{self, Chip8, Quirks, QUIRK_PROFILES};
+
+fn run_lockstep(name: &str, rom: &[u8], quirks: Quirks) -> Chip8 {
+    let mut cache = DecodeCache::new();
+    common::run_lockstep(name, rom, quirks, chip8::CYCLES_PER_FRAME, |chip8| {
+        cache::run_frame(chip8, &mut cache)
+    })
+}
+
+#[test]
+fn test_programs_in_lockstep() {
+    for (name, rom) in common::programs() {
+        for profile in QUIRK_PROFILES.iter() {
+            run_lockstep(&name, &rom, Quirks::from_profile(profile).unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_store_over_own_code() {
+    // LD [I] turns ADD V3, 1 into ADD V3, 5 the first time through the loop
+    let rom = assembler::assemble_text(
+        "
+            LD V0, 0x73
+            LD V1, 0x05
+            LD I, add
+        add:
+            ADD V3, 1
+            LD I, V1
+            JP add
+        ",
+        "store.cp8asm",
+    )
+    .unwrap();
+    for profile in QUIRK_PROFILES.iter() {
+        let chip8 = run_lockstep("store", &rom, Quirks::from_profile(profile).unwrap());
+        assert_eq!(chip8.memory[0x206..0x208], [0x73, 0x05]);
+    }
+
+    // Through the loop once, then the new ADD
+    let mut chip8 = Chip8::new(&rom);
+    let mut cache = DecodeCache::new();
+    for _i in 0..7 {
+        cache::step_instruction(&mut chip8, &mut cache);
+    }
+    assert_eq!(chip8.registers[3], 6);
+}
+
+#[test]
+fn test_bcd_over_own_code() {
+    let rom = assembler::assemble_text(common::BCD_OVER_OWN_CODE, "bcd.cp8asm").unwrap();
+    for profile in QUIRK_PROFILES.iter() {
+        let chip8 = run_lockstep("bcd", &rom, Quirks::from_profile(profile).unwrap());
+        assert_ne!(chip8.registers[4], 0, "{}", profile);
+    }
+}
+
+#[test]
+fn test_invalidate() {
+    // JP 0x200 until the program is patched to LD V0, 1
+    let mut chip8 = Chip8::new(&[0x12, 0x00]);
+    let mut cache = DecodeCache::new();
+    cache::step_instruction(&mut chip8, &mut cache);
+    chip8.memory[0x200..0x202].copy_from_slice(&[0x60, 0x01]);
+    cache.invalidate();
+    cache::step_instruction(&mut chip8, &mut cache);
+    assert_eq!(chip8.registers[0], 1);
+
+    // Only the bytes written, as cheats do
+    let mut chip8 = Chip8::new(&[0x12, 0x00]);
+    let mut cache = DecodeCache::new();
+    cache::step_instruction(&mut chip8, &mut cache);
+    chip8.memory[0x201] = 0x02;
+    cache.forget(0x201);
+    cache::step_instruction(&mut chip8, &mut cache);
+    assert_eq!(chip8.pc, 0x202);
+}
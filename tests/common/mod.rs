@@ -0,0 +1,138 @@
+// Programs run by both the native and the wasm tests, with the framebuffer expected after
+// the given number of frames. Both builds are checked against the same files, so they
+// can't drift apart.
+//
+// Also the lockstep runs of the faster runners (cache.rs, jit.rs) against the interpreter.
+// Not every test file uses every function here.
+#![allow(dead_code)]
+
+use chip_8::assembler;
+use chip_8::chip8::{self, Chip8, Quirks};
+use chip_8::state;
+
+pub struct Case {
+    pub name: &'static str,
+    pub rom: &'static [u8],
+    pub seed: u32,
+    pub frames: u32,
+    pub expected: &'static str,
+}
+
+pub const CASES: [Case; 3] = [
+    Case {
+        name: "clock",
+        rom: include_bytes!("../roms/clock.ch8"),
+        seed: 0,
+        frames: 200,
+        expected: include_str!("../framebuffers/clock.txt"),
+    },
+    Case {
+        name: "numbers",
+        rom: include_bytes!("../roms/numbers.ch8"),
+        seed: 0,
+        frames: 10,
+        expected: include_str!("../framebuffers/numbers.txt"),
+    },
+    Case {
+        name: "random",
+        rom: include_bytes!("../roms/random.ch8"),
+        seed: 1234,
+        frames: 60,
+        expected: include_str!("../framebuffers/random.txt"),
+    },
+];
+
+// One line per display row, '#' for lit pixels and '.' for unlit ones
+pub fn framebuffer_to_text(framebuffer: &[u8], width: usize) -> String {
+    framebuffer
+        .chunks(width)
+        .map(|row| {
+            let mut line: String = row
+                .iter()
+                .map(|px| if *px == 1 { '#' } else { '.' })
+                .collect();
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+const LOCKSTEP_FRAMES: u32 = 300;
+
+// The ROMs, example programs and conformance programs, assembled
+pub fn programs() -> Vec<(String, Vec<u8>)> {
+    let mut programs = vec![];
+    for directory in &["tests/roms", "assembly_programs", "tests/conformance"] {
+        let mut filepaths: Vec<_> = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path().to_str().unwrap().to_string())
+            .collect();
+        filepaths.sort();
+        for filepath in filepaths {
+            if filepath.ends_with(".ch8") {
+                programs.push((filepath.clone(), std::fs::read(&filepath).unwrap()));
+            } else if filepath.ends_with(".cp8asm") {
+                programs.push((filepath.clone(), assembler::assemble(&filepath).unwrap()));
+            }
+        }
+    }
+    programs
+}
+
+// Run a machine with run and one with chip8::run_frame for LOCKSTEP_FRAMES frames, with a
+// key pressed now and then, comparing their save states after every frame. Returns the
+// machine run with run.
+pub fn run_lockstep(
+    name: &str,
+    rom: &[u8],
+    quirks: Quirks,
+    cycles_per_frame: u32,
+    mut run: impl FnMut(&mut Chip8),
+) -> Chip8 {
+    let mut expected = Chip8::new(rom);
+    expected.quirks = quirks;
+    expected.cycles_per_frame = cycles_per_frame;
+    let mut chip8 = Chip8::new(rom);
+    chip8.quirks = quirks;
+    chip8.cycles_per_frame = cycles_per_frame;
+
+    for frame in 0..LOCKSTEP_FRAMES {
+        let key = (frame / 20) as usize % 16;
+        let pressed = frame % 20 < 5;
+        expected.keys[key] = pressed;
+        chip8.keys[key] = pressed;
+        if pressed {
+            chip8::key_pressed(&mut expected, key as u8);
+            chip8::key_pressed(&mut chip8, key as u8);
+        }
+
+        chip8::run_frame(&mut expected);
+        run(&mut chip8);
+        assert!(
+            state::save_state(&chip8) == state::save_state(&expected),
+            "{} with {:?} at {} cycles per frame, frame {}",
+            name,
+            quirks,
+            cycles_per_frame,
+            frame
+        );
+    }
+    chip8
+}
+
+// LD B writes the hundreds digit of V0 over the byte of LD V3, the tens and ones over the
+// SYS after it, so V4 adds up the hundreds digits
+pub const BCD_OVER_OWN_CODE: &str = "
+        LD V0, 0
+        LD V1, 1
+    loop:
+        ADD V0, 1
+        LD I, digit
+        ADD I, V1
+        LD B, V0
+    digit:
+        LD V3, 0
+        SYS 0x000
+        ADD V4, V3
+        JP loop
+";
@@ -0,0 +1,122 @@
+// Inputs that made the fuzz targets in fuzz/ panic: each one must now run to the end
+
+use chip_8::assembler;
+use chip_8::chip8::{self, Chip8, LOAD_ADDRESS, MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+use chip_8::display;
+use chip_8::state;
+
+fn run(program: &[u8], setup: impl FnOnce(&mut Chip8)) -> Chip8 {
+    let mut chip8 = Chip8::new(program);
+    setup(&mut chip8);
+    for _frame in 0..4 {
+        chip8::run_frame(&mut chip8);
+    }
+    chip8
+}
+
+#[test]
+fn test_ret_with_empty_stack() {
+    // RET
+    let mut chip8 = Chip8::new(&[0x00, 0xEE]);
+    chip8::step_instruction(&mut chip8);
+    assert_eq!(chip8.sp, 15);
+    run(&[0x00, 0xEE], |_chip8| {});
+}
+
+#[test]
+fn test_call_with_full_stack() {
+    // CALL 0x200, forever
+    let chip8 = run(&[0x22, 0x00], |_chip8| {});
+    assert_eq!(chip8.sp, 32 % 16);
+}
+
+#[test]
+fn test_add_i_overflow() {
+    // LD V0, 0xFF; ADD I, V0; JP 0x202, 16 additions in 4 frames
+    let chip8 = run(&[0x60, 0xFF, 0xF0, 0x1E, 0x12, 0x02], |chip8| {
+        chip8.register_i = 0xFFF0
+    });
+    assert_eq!(chip8.register_i, 0x0FE0);
+}
+
+#[test]
+fn test_drw_past_memory() {
+    // LD I, 0xFFF; DRW V0, V0, 15
+    let chip8 = run(&[0xAF, 0xFF, 0xD0, 0x0F], |_chip8| {});
+    // The sprite wraps around to the digit sprites at 0x000
+    assert!(chip8.display.get(0, 1));
+}
+
+#[test]
+fn test_bcd_store_load_past_memory() {
+    // LD I, 0xFFE; LD B, V0; LD [I], VF; LD VF, [I]
+    let chip8 = run(
+        &[0xAF, 0xFE, 0xF0, 0x33, 0xFF, 0x55, 0xFF, 0x65],
+        |_chip8| {},
+    );
+    // Over the digit sprites at 0x000
+    assert_eq!(chip8.memory[..14], [0; 14]);
+}
+
+#[test]
+fn test_i_overflow_with_load_store_increment() {
+    // LD V0, 0xFF; ADD I, V0 257 times up to 0xFFFF, then LD [I], VF
+    let mut program = vec![0x60, 0xFF];
+    for _i in 0..257 {
+        program.extend_from_slice(&[0xF0, 0x1E]);
+    }
+    program.extend_from_slice(&[0xFF, 0x55]);
+    let mut chip8 = Chip8::new(&program);
+    chip8.quirks.load_store_increment_i = true;
+    for _frame in 0..40 {
+        chip8::run_frame(&mut chip8);
+    }
+    assert_eq!(chip8.register_i, 0x000F);
+}
+
+#[test]
+fn test_pc_at_end_of_memory() {
+    // JP 0xFFF, then a skip from the last instruction of 64KB
+    let mut chip8 = run(&[0x1F, 0xFF], |_chip8| {});
+    chip8::run_next_cpu_cycle(&mut chip8);
+    assert_eq!(chip8.pc, 0xFFF);
+
+    let mut chip8 = Chip8::load(&[0x00, 0xE0], LOAD_ADDRESS, XO_CHIP_MEMORY_SIZE).unwrap();
+    chip8.pc = 0xFFFC;
+    chip8.memory[0xFFFC..].copy_from_slice(&[0x30, 0x00, 0x30, 0x00]);
+    chip8::run_next_cpu_cycle(&mut chip8);
+    assert_eq!(chip8.pc, 0x0000);
+    chip8.pc = 0xFFFF;
+    chip8::run_next_cpu_cycle(&mut chip8);
+    assert_eq!(chip8.pc, 0xFFFF);
+}
+
+#[test]
+fn test_state_with_small_memory() {
+    // A state of 4KB with its memory size and its memory, after the header and the
+    // display, cut down to 0 and 2 bytes
+    let mut chip8 = Chip8::new(&[0x12, 0x00]);
+    let full = state::save_state(&chip8);
+    let memory_start = 9 + display::SIZE;
+    for memory_size in [0usize, 2].iter() {
+        let mut small = full.clone();
+        small[5..9].copy_from_slice(&(*memory_size as u32).to_le_bytes());
+        small.splice(
+            memory_start..memory_start + MEMORY_SIZE,
+            vec![0; *memory_size],
+        );
+        assert!(state::load_state(&mut chip8, &small).is_err());
+        chip8::run_frame(&mut chip8);
+    }
+}
+
+#[test]
+fn test_assembler_missing_operands() {
+    for line in &["JP", "LD", "LD I,", "LD V1", "LD V1,", "LD DT,"] {
+        assert!(
+            assembler::assemble_text(line, "crasher.cp8asm").is_err(),
+            "{}",
+            line
+        );
+    }
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use chip_8::assembler;
+use chip_8::chip8::{self, Chip8, Quirks, HEIGHT, QUIRK_PROFILES, WIDTH};
+
+#[test]
+fn test_native_framebuffers() {
+    for case in common::CASES.iter() {
+        let mut chip8 = Chip8::new(case.rom);
+        chip8::seed_rng(&mut chip8, case.seed);
+
+        for _frame in 0..case.frames {
+            chip8::run_frame(&mut chip8);
+        }
+
+        let framebuffer = common::framebuffer_to_text(&chip8.display.to_bytes(), WIDTH as usize);
+        if std::env::var("UPDATE_FRAMEBUFFERS").is_ok() {
+            let filepath = format!("tests/framebuffers/{}.txt", case.name);
+            std::fs::write(filepath, &framebuffer).unwrap();
+        } else {
+            assert_eq!(framebuffer, case.expected, "{}", case.name);
+        }
+    }
+}
+
+// Test programs run under every quirk profile. They check the same behaviours as the
+// public test suites (https://github.com/Timendus/chip8-test-suite), whose ROMs aren't in
+// this repository: a .ch8 file can be listed here as well as a .cp8asm source.
+//
+// The expected framebuffers never come from the interpreter. Programs that show values
+// list them at the top of their source, 4 per row as show.cp8asm draws them, a value
+// that depends on the quirks as modern/cosmac/schip: the expected framebuffer is drawn
+// from that list. The others have one drawn by hand in tests/framebuffers/conformance.
+struct Program {
+    filename: &'static str,
+    frames: u32,
+    // Frame, Chip-8 key and whether it's pressed or released, before that frame runs
+    keys: &'static [(u32, u8, bool)],
+}
+
+const PROGRAMS: [Program; 5] = [
+    Program {
+        filename: "logo.cp8asm",
+        frames: 60,
+        keys: &[],
+    },
+    Program {
+        filename: "opcodes.cp8asm",
+        frames: 120,
+        keys: &[],
+    },
+    Program {
+        filename: "flags.cp8asm",
+        frames: 120,
+        keys: &[],
+    },
+    Program {
+        filename: "quirks.cp8asm",
+        frames: 120,
+        keys: &[],
+    },
+    Program {
+        filename: "keypad.cp8asm",
+        frames: 120,
+        keys: &[(10, 0xA, true), (12, 0xA, false), (30, 0x5, true)],
+    },
+];
+
+// The digits 0 to 9 of the font, from the specification
+// http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#2.4
+const DIGITS: [[u8; 5]; 10] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0],
+    [0x20, 0x60, 0x20, 0x20, 0x70],
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0],
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0],
+    [0x90, 0x90, 0xF0, 0x10, 0x10],
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x10, 0x20, 0x40, 0x40],
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0],
+];
+
+// The values listed in the comments at the top of source, for the profile at
+// profile_index in QUIRK_PROFILES: rows of 3 digit numbers, or of modern/cosmac/schip
+fn expected_values(source: &str, profile_index: usize) -> Vec<u8> {
+    let mut values = vec![];
+    for line in source.lines() {
+        let comment = match line.strip_prefix(';') {
+            Some(comment) => comment,
+            None => break,
+        };
+        for token in comment.split_whitespace() {
+            let value = token.split('/').nth(if token.contains('/') {
+                profile_index
+            } else {
+                0
+            });
+            match value.filter(|value| value.len() == 3).map(str::parse) {
+                Some(Ok(value)) => values.push(value),
+                _ => break,
+            }
+        }
+    }
+    values
+}
+
+// The values as show.cp8asm draws them: 3 digits per value, 16 pixels apart, 4 per row
+fn draw_values(values: &[u8]) -> String {
+    let width = WIDTH as usize;
+    let mut pixels = vec![0; width * HEIGHT as usize];
+    for (index, value) in values.iter().enumerate() {
+        let (left, top) = (index % 4 * 16, index / 4 * 6);
+        let digits = [value / 100, value / 10 % 10, value % 10];
+        for (position, digit) in digits.iter().enumerate() {
+            for (row, bits) in DIGITS[*digit as usize].iter().enumerate() {
+                for column in 0..8 {
+                    if bits & (0x80 >> column) != 0 {
+                        pixels[(top + row) * width + left + position * 5 + column] = 1;
+                    }
+                }
+            }
+        }
+    }
+    common::framebuffer_to_text(&pixels, width)
+}
+
+#[test]
+fn test_expected_values() {
+    let source = "; values:\n;   001  002/003/004     text 005\n;   006\n    CLS\n; 007";
+    assert_eq!(expected_values(source, 0), [1, 2, 6]);
+    assert_eq!(expected_values(source, 1), [1, 3, 6]);
+    assert_eq!(expected_values(source, 2), [1, 4, 6]);
+
+    let framebuffer = draw_values(&[120]);
+    let lines: Vec<&str> = framebuffer.lines().collect();
+    assert_eq!(lines[0][..16], *"..#..####.####..");
+    assert_eq!(lines[4][..16], *".###.####.####..");
+}
+
+#[test]
+fn test_conformance() {
+    let mut failures = vec![];
+    for program in PROGRAMS.iter() {
+        let filepath = format!("tests/conformance/{}", program.filename);
+        let rom = if filepath.ends_with(".cp8asm") {
+            assembler::assemble(&filepath).unwrap()
+        } else {
+            std::fs::read(&filepath).unwrap()
+        };
+        let name = program.filename.split('.').next().unwrap();
+        let source = if filepath.ends_with(".cp8asm") {
+            std::fs::read_to_string(&filepath).unwrap()
+        } else {
+            String::new()
+        };
+
+        for (profile_index, profile) in QUIRK_PROFILES.iter().enumerate() {
+            let mut chip8 = Chip8::new(&rom);
+            chip8.quirks = Quirks::from_profile(profile).unwrap();
+
+            for frame in 0..program.frames {
+                for (_frame, key, pressed) in program.keys.iter().filter(|key| key.0 == frame) {
+                    if *pressed {
+                        chip8::key_pressed(&mut chip8, *key);
+                    } else {
+                        chip8::key_released(&mut chip8, *key);
+                    }
+                }
+                chip8::run_frame(&mut chip8);
+            }
+
+            let framebuffer =
+                common::framebuffer_to_text(&chip8.display.to_bytes(), WIDTH as usize);
+            let values = expected_values(&source, profile_index);
+            let expected = if values.is_empty() {
+                let expected_path = format!("tests/framebuffers/conformance/{}.txt", name);
+                std::fs::read_to_string(&expected_path).unwrap()
+            } else {
+                draw_values(&values)
+            };
+            if framebuffer != expected {
+                failures.push(format!("{} with the {} quirks", program.filename, profile));
+            }
+        }
+    }
+    // All of them at once, a quirk or instruction change usually breaks several
+    assert!(
+        failures.is_empty(),
+        "Unexpected framebuffers: {:?}",
+        failures
+    );
+}
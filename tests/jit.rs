@@ -0,0 +1,222 @@
+// The compiled blocks run programs exactly like the interpreter: both machines are
+// compared, save state against save state, after every frame.
+//
+// cargo test --features jit --test jit
+
+#![cfg(all(feature = "jit", not(target_arch = "wasm32")))]
+
+mod common;
+
+use chip_8::assembler;
+use chip_8::chip8::{self, Chip8, Quirks, QUIRK_PROFILES};
+use chip_8::jit::{self, Jit};
+use chip_8::state;
+use proptest::prelude::*;
+
+// The compiled machine, with the blocks it was run with
+fn run_lockstep(name: &str, rom: &[u8], quirks: Quirks, cycles_per_frame: u32) -> (Chip8, Jit) {
+    let mut jit = Jit::new();
+    let chip8 = common::run_lockstep(name, rom, quirks, cycles_per_frame, |chip8| {
+        jit::run_frame(chip8, &mut jit)
+    });
+    (chip8, jit)
+}
+
+#[test]
+fn test_programs_in_lockstep() {
+    for (name, rom) in common::programs() {
+        for profile in QUIRK_PROFILES.iter() {
+            // Blocks cut short by the end of the frame, and run whole
+            for cycles_per_frame in [chip8::CYCLES_PER_FRAME, 1000].iter() {
+                let quirks = Quirks::from_profile(profile).unwrap();
+                run_lockstep(&name, &rom, quirks, *cycles_per_frame);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_hot_blocks_are_compiled() {
+    let rom = std::fs::read("tests/roms/clock.ch8").unwrap();
+    let (_chip8, jit) = run_lockstep("clock", &rom, Quirks::default(), 1000);
+    assert!(jit.compiled_blocks() > 0);
+}
+
+#[test]
+fn test_store_over_own_code() {
+    // Once the loop is compiled, LD [I] turns ADD V3, 1 in its middle into ADD V3, 5
+    let rom = assembler::assemble_text(
+        "
+            LD V0, 0x73
+            LD V1, 0x05
+            LD I, add
+        loop:
+            ADD V2, 1
+        add:
+            ADD V3, 1
+            SE V2, 100
+            JP loop
+            LD I, V1
+            JP loop
+        ",
+        "store.cp8asm",
+    )
+    .unwrap();
+    for profile in QUIRK_PROFILES.iter() {
+        let quirks = Quirks::from_profile(profile).unwrap();
+        for cycles_per_frame in [chip8::CYCLES_PER_FRAME, 1000].iter() {
+            let (chip8, _jit) = run_lockstep("store", &rom, quirks, *cycles_per_frame);
+            assert_eq!(chip8.memory[0x208..0x20A], [0x73, 0x05]);
+        }
+    }
+}
+
+#[test]
+fn test_bcd_over_own_code() {
+    let rom = assembler::assemble_text(common::BCD_OVER_OWN_CODE, "bcd.cp8asm").unwrap();
+    for profile in QUIRK_PROFILES.iter() {
+        let quirks = Quirks::from_profile(profile).unwrap();
+        let (chip8, _jit) = run_lockstep("bcd", &rom, quirks, chip8::CYCLES_PER_FRAME);
+        assert_ne!(chip8.registers[4], 0, "{}", profile);
+        run_lockstep("bcd", &rom, quirks, 1000);
+    }
+}
+
+#[test]
+fn test_invalidate() {
+    // JP 0x200 until the program is patched to LD V0, 1
+    let mut chip8 = Chip8::new(&[0x12, 0x00]);
+    let mut jit = Jit::new();
+    jit::run_frame(&mut chip8, &mut jit);
+    jit::run_frame(&mut chip8, &mut jit);
+    assert_eq!(jit.compiled_blocks(), 1);
+    chip8.memory[0x200..0x202].copy_from_slice(&[0x60, 0x01]);
+    jit.invalidate();
+    jit::step_instruction(&mut chip8, &mut jit);
+    assert_eq!(chip8.registers[0], 1);
+
+    // Only the bytes written, as cheats do
+    let mut chip8 = Chip8::new(&[0x12, 0x00]);
+    let mut jit = Jit::new();
+    jit::run_frame(&mut chip8, &mut jit);
+    jit::run_frame(&mut chip8, &mut jit);
+    chip8.memory[0x201] = 0x02;
+    jit.forget(0x201);
+    assert_eq!(jit.compiled_blocks(), 0);
+    jit::step_instruction(&mut chip8, &mut jit);
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn test_step_instruction() {
+    // One instruction at a time, even from a compiled block
+    let rom = assembler::assemble_text(
+        "
+        loop:
+            ADD V0, 1
+            ADD V1, 2
+            JP loop
+        ",
+        "step.cp8asm",
+    )
+    .unwrap();
+    let mut expected = Chip8::new(&rom);
+    let mut chip8 = Chip8::new(&rom);
+    let mut jit = Jit::new();
+    for _i in 0..200 {
+        chip8::step_instruction(&mut expected);
+        jit::step_instruction(&mut chip8, &mut jit);
+        assert!(state::save_state(&chip8) == state::save_state(&expected));
+    }
+    assert!(jit.compiled_blocks() > 0);
+}
+
+// Instructions a block can be made of
+fn compiled_opcode() -> impl Strategy<Value = u16> {
+    prop_oneof![
+        (0..16u16, any::<u8>()).prop_map(|(x, kk)| 0x6000 | x << 8 | kk as u16),
+        (0..16u16, any::<u8>()).prop_map(|(x, kk)| 0x7000 | x << 8 | kk as u16),
+        (
+            0..16u16,
+            0..16u16,
+            prop::sample::select(vec![0, 1, 2, 3, 4, 5, 6, 7, 0xE])
+        )
+            .prop_map(|(x, y, n)| 0x8000 | x << 8 | y << 4 | n),
+        (0..0x1000u16).prop_map(|nnn| 0xA000 | nnn),
+        (0..16u16, any::<u8>()).prop_map(|(x, kk)| 0xC000 | x << 8 | kk as u16),
+        (
+            0..16u16,
+            prop::sample::select(vec![0x07, 0x15, 0x18, 0x1E, 0x29, 0x65])
+        )
+            .prop_map(|(x, kk)| 0xF000 | x << 8 | kk),
+        Just(0x00E0u16),
+        Just(0x0000u16),
+    ]
+}
+
+// Ends the first block of the loop
+fn ending_opcode() -> impl Strategy<Value = u16> {
+    prop_oneof![
+        (0..16u16, any::<u8>()).prop_map(|(x, kk)| 0x3000 | x << 8 | kk as u16),
+        (0..16u16, any::<u8>()).prop_map(|(x, kk)| 0x4000 | x << 8 | kk as u16),
+        (0..16u16, 0..16u16).prop_map(|(x, y)| 0x5000 | x << 8 | y << 4),
+        (0..16u16, 0..16u16).prop_map(|(x, y)| 0x9000 | x << 8 | y << 4),
+        (0..16u16).prop_map(|x| 0xE09E | x << 8),
+        (0..16u16).prop_map(|x| 0xE0A1 | x << 8),
+    ]
+}
+
+fn quirks() -> impl Strategy<Value = Quirks> {
+    any::<[bool; 6]>().prop_map(|flags| Quirks {
+        shift_vx: flags[0],
+        load_store_increment_i: flags[1],
+        jump_vx: flags[2],
+        clip_sprites: flags[3],
+        vf_reset: flags[4],
+        display_wait: flags[5],
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(200))]
+
+    // A loop over random instructions, with a skip in the middle
+    #[test]
+    fn test_random_loops(
+        first in prop::collection::vec(compiled_opcode(), 1..20),
+        skip in ending_opcode(),
+        second in prop::collection::vec(compiled_opcode(), 1..20),
+        registers in any::<[u8; 16]>(),
+        keys in any::<[bool; 16]>(),
+        seed in any::<u32>(),
+        quirks in quirks(),
+        cycles_per_frame in 1..100u32,
+    ) {
+        let mut rom: Vec<u8> = first.iter().chain([skip].iter()).chain(second.iter())
+            .flat_map(|opcode| opcode.to_be_bytes().to_vec())
+            .collect();
+        rom.extend_from_slice(&[0x12, 0x00]);
+
+        let new_chip8 = || {
+            let mut chip8 = Chip8::new(&rom);
+            chip8.registers = registers;
+            chip8.keys = keys;
+            chip8.quirks = quirks;
+            chip8.cycles_per_frame = cycles_per_frame;
+            chip8::seed_rng(&mut chip8, seed);
+            chip8
+        };
+        let mut expected = new_chip8();
+        let mut chip8 = new_chip8();
+        let mut jit = Jit::new();
+        for frame in 0..30 {
+            chip8::run_frame(&mut expected);
+            jit::run_frame(&mut chip8, &mut jit);
+            prop_assert!(
+                state::save_state(&chip8) == state::save_state(&expected),
+                "frame {}",
+                frame
+            );
+        }
+    }
+}
@@ -0,0 +1,459 @@
+// One test per instruction, through the core's public API
+
+use chip_8::chip8::{self, Chip8, Quirks, HEIGHT, LOAD_ADDRESS, WIDTH};
+use chip_8::display::{self, Display};
+
+// A machine with the opcodes at 0x200, set up then stepped through all of them in order
+fn execute(opcodes: &[u16], setup: impl FnOnce(&mut Chip8)) -> Chip8 {
+    let mut chip8 = load(opcodes);
+    setup(&mut chip8);
+    for _opcode in opcodes {
+        chip8::step_instruction(&mut chip8);
+    }
+    chip8
+}
+
+fn load(opcodes: &[u16]) -> Chip8 {
+    let program: Vec<u8> = opcodes
+        .iter()
+        .flat_map(|opcode| opcode.to_be_bytes().to_vec())
+        .collect();
+    Chip8::new(&program)
+}
+
+fn cosmac() -> Quirks {
+    Quirks::from_profile("cosmac").unwrap()
+}
+
+fn lit_pixels(chip8: &Chip8) -> usize {
+    chip8.display.pixels().filter(|lit| *lit).count()
+}
+
+#[test]
+fn test_00e0_cls() {
+    let chip8 = execute(&[0x00E0], |chip8| {
+        chip8.display = Display::from_bytes(&[1; display::SIZE])
+    });
+    assert_eq!(lit_pixels(&chip8), 0);
+}
+
+#[test]
+fn test_00ee_ret() {
+    let mut chip8 = load(&[0x2206, 0x0000, 0x0000, 0x00EE]);
+    chip8::step_instruction(&mut chip8);
+    chip8::step_instruction(&mut chip8);
+    assert_eq!(chip8.pc, 0x202);
+    assert_eq!(chip8.sp, 0);
+}
+
+#[test]
+fn test_0nnn_sys() {
+    let chip8 = execute(&[0x0123], |_chip8| {});
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn test_1nnn_jp() {
+    let chip8 = execute(&[0x1ABC], |_chip8| {});
+    assert_eq!(chip8.pc, 0xABC);
+}
+
+#[test]
+fn test_2nnn_call() {
+    let chip8 = execute(&[0x0000, 0x2ABC], |_chip8| {});
+    assert_eq!(chip8.pc, 0xABC);
+    assert_eq!(chip8.sp, 1);
+    assert_eq!(chip8.stack[1], 0x204);
+}
+
+#[test]
+fn test_3xkk_se() {
+    let chip8 = execute(&[0x3342], |chip8| chip8.registers[3] = 0x42);
+    assert_eq!(chip8.pc, 0x204);
+    let chip8 = execute(&[0x3342], |chip8| chip8.registers[3] = 0x41);
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn test_4xkk_sne() {
+    let chip8 = execute(&[0x4342], |chip8| chip8.registers[3] = 0x42);
+    assert_eq!(chip8.pc, 0x202);
+    let chip8 = execute(&[0x4342], |chip8| chip8.registers[3] = 0x41);
+    assert_eq!(chip8.pc, 0x204);
+}
+
+#[test]
+fn test_5xy0_se() {
+    let chip8 = execute(&[0x5340], |chip8| {
+        chip8.registers[3] = 7;
+        chip8.registers[4] = 7;
+    });
+    assert_eq!(chip8.pc, 0x204);
+    let chip8 = execute(&[0x5340], |chip8| chip8.registers[3] = 7);
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn test_6xkk_ld() {
+    let chip8 = execute(&[0x6A42], |_chip8| {});
+    assert_eq!(chip8.registers[0xA], 0x42);
+}
+
+#[test]
+fn test_7xkk_add() {
+    // Wraps around, without touching VF
+    let chip8 = execute(&[0x7302], |chip8| {
+        chip8.registers[3] = 0xFF;
+        chip8.registers[0xF] = 5;
+    });
+    assert_eq!(chip8.registers[3], 1);
+    assert_eq!(chip8.registers[0xF], 5);
+}
+
+#[test]
+fn test_8xy0_ld() {
+    let chip8 = execute(&[0x8340], |chip8| chip8.registers[4] = 9);
+    assert_eq!(chip8.registers[3], 9);
+}
+
+#[test]
+fn test_8xy1_or() {
+    let setup = |chip8: &mut Chip8| {
+        chip8.registers[3] = 0b1100;
+        chip8.registers[4] = 0b1010;
+        chip8.registers[0xF] = 5;
+    };
+    let chip8 = execute(&[0x8341], setup);
+    assert_eq!(chip8.registers[3], 0b1110);
+    assert_eq!(chip8.registers[0xF], 5);
+
+    let chip8 = execute(&[0x8341], |chip8| {
+        setup(chip8);
+        chip8.quirks = cosmac();
+    });
+    assert_eq!(chip8.registers[0xF], 0);
+}
+
+#[test]
+fn test_8xy2_and() {
+    let chip8 = execute(&[0x8342], |chip8| {
+        chip8.registers[3] = 0b1100;
+        chip8.registers[4] = 0b1010;
+        chip8.registers[0xF] = 5;
+        chip8.quirks = cosmac();
+    });
+    assert_eq!(chip8.registers[3], 0b1000);
+    assert_eq!(chip8.registers[0xF], 0);
+}
+
+#[test]
+fn test_8xy3_xor() {
+    let chip8 = execute(&[0x8343], |chip8| {
+        chip8.registers[3] = 0b1100;
+        chip8.registers[4] = 0b1010;
+        chip8.registers[0xF] = 5;
+    });
+    assert_eq!(chip8.registers[3], 0b0110);
+    assert_eq!(chip8.registers[0xF], 5);
+}
+
+#[test]
+fn test_8xy4_add() {
+    let chip8 = execute(&[0x8344], |chip8| {
+        chip8.registers[3] = 200;
+        chip8.registers[4] = 100;
+    });
+    assert_eq!(chip8.registers[3], 44);
+    assert_eq!(chip8.registers[0xF], 1);
+
+    let chip8 = execute(&[0x8344], |chip8| {
+        chip8.registers[3] = 10;
+        chip8.registers[4] = 20;
+        chip8.registers[0xF] = 1;
+    });
+    assert_eq!(chip8.registers[3], 30);
+    assert_eq!(chip8.registers[0xF], 0);
+
+    // The flag wins over the result
+    let chip8 = execute(&[0x8F44], |chip8| {
+        chip8.registers[0xF] = 200;
+        chip8.registers[4] = 100;
+    });
+    assert_eq!(chip8.registers[0xF], 1);
+}
+
+#[test]
+fn test_8xy5_sub() {
+    let chip8 = execute(&[0x8345], |chip8| {
+        chip8.registers[3] = 30;
+        chip8.registers[4] = 100;
+    });
+    assert_eq!(chip8.registers[3], 186);
+    assert_eq!(chip8.registers[0xF], 0);
+
+    // Equal values don't borrow
+    let chip8 = execute(&[0x8345], |chip8| {
+        chip8.registers[3] = 50;
+        chip8.registers[4] = 50;
+    });
+    assert_eq!(chip8.registers[3], 0);
+    assert_eq!(chip8.registers[0xF], 1);
+
+    let chip8 = execute(&[0x8F45], |chip8| {
+        chip8.registers[0xF] = 100;
+        chip8.registers[4] = 30;
+    });
+    assert_eq!(chip8.registers[0xF], 1);
+}
+
+#[test]
+fn test_8xy6_shr() {
+    let setup = |chip8: &mut Chip8| {
+        chip8.registers[3] = 0b101;
+        chip8.registers[4] = 0b1000;
+    };
+    let chip8 = execute(&[0x8346], setup);
+    assert_eq!(chip8.registers[3], 0b10);
+    assert_eq!(chip8.registers[0xF], 1);
+
+    // Shifts Vy on the VIP
+    let chip8 = execute(&[0x8346], |chip8| {
+        setup(chip8);
+        chip8.quirks = cosmac();
+    });
+    assert_eq!(chip8.registers[3], 0b100);
+    assert_eq!(chip8.registers[0xF], 0);
+
+    let chip8 = execute(&[0x8F06], |chip8| chip8.registers[0xF] = 0b11);
+    assert_eq!(chip8.registers[0xF], 1);
+}
+
+#[test]
+fn test_8xy7_subn() {
+    let chip8 = execute(&[0x8347], |chip8| {
+        chip8.registers[3] = 30;
+        chip8.registers[4] = 100;
+    });
+    assert_eq!(chip8.registers[3], 70);
+    assert_eq!(chip8.registers[0xF], 1);
+
+    let chip8 = execute(&[0x8347], |chip8| {
+        chip8.registers[3] = 100;
+        chip8.registers[4] = 30;
+    });
+    assert_eq!(chip8.registers[3], 186);
+    assert_eq!(chip8.registers[0xF], 0);
+}
+
+#[test]
+fn test_8xye_shl() {
+    let setup = |chip8: &mut Chip8| {
+        chip8.registers[3] = 0x81;
+        chip8.registers[4] = 0x21;
+    };
+    let chip8 = execute(&[0x834E], setup);
+    assert_eq!(chip8.registers[3], 0x02);
+    assert_eq!(chip8.registers[0xF], 1);
+
+    let chip8 = execute(&[0x834E], |chip8| {
+        setup(chip8);
+        chip8.quirks = cosmac();
+    });
+    assert_eq!(chip8.registers[3], 0x42);
+    assert_eq!(chip8.registers[0xF], 0);
+}
+
+#[test]
+fn test_9xy0_sne() {
+    let chip8 = execute(&[0x9340], |chip8| chip8.registers[3] = 7);
+    assert_eq!(chip8.pc, 0x204);
+    let chip8 = execute(&[0x9340], |_chip8| {});
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn test_annn_ld_i() {
+    let chip8 = execute(&[0xAABC], |_chip8| {});
+    assert_eq!(chip8.register_i, 0xABC);
+}
+
+#[test]
+fn test_bnnn_jp_v0() {
+    let setup = |chip8: &mut Chip8| {
+        chip8.registers[0] = 0x10;
+        chip8.registers[3] = 0x20;
+    };
+    let chip8 = execute(&[0xB300], setup);
+    assert_eq!(chip8.pc, 0x310);
+
+    // Bxnn with Vx on the SCHIP
+    let chip8 = execute(&[0xB300], |chip8| {
+        setup(chip8);
+        chip8.quirks = Quirks::from_profile("schip").unwrap();
+    });
+    assert_eq!(chip8.pc, 0x320);
+}
+
+#[test]
+fn test_cxkk_rnd() {
+    let run = |seed| execute(&[0xC30F, 0xC4FF], |chip8| chip8::seed_rng(chip8, seed)).registers;
+    assert_eq!(run(1), run(1));
+    assert!(run(1)[3] <= 0x0F);
+}
+
+#[test]
+fn test_dxyn_drw() {
+    // The 0 digit, its 14 pixels
+    let chip8 = execute(&[0xD125], |_chip8| {});
+    assert_eq!(lit_pixels(&chip8), 14);
+    assert_eq!(chip8.registers[0xF], 0);
+
+    // Drawn twice, erased with a collision
+    let chip8 = execute(&[0xD125, 0xD125], |_chip8| {});
+    assert_eq!(lit_pixels(&chip8), 0);
+    assert_eq!(chip8.registers[0xF], 1);
+
+    // VF is cleared when nothing collides
+    let chip8 = execute(&[0xD125], |chip8| chip8.registers[0xF] = 1);
+    assert_eq!(chip8.registers[0xF], 0);
+
+    // Wraps around the edges, unless clipped
+    let setup = |chip8: &mut Chip8| {
+        chip8.registers[1] = WIDTH - 2;
+        chip8.registers[2] = HEIGHT - 2;
+    };
+    let chip8 = execute(&[0xD125], setup);
+    assert!(chip8.display.get(1, 0));
+    assert_eq!(lit_pixels(&chip8), 14);
+    let chip8 = execute(&[0xD125], |chip8| {
+        setup(chip8);
+        chip8.quirks = cosmac();
+    });
+    assert!(!chip8.display.get(1, 0));
+    assert_eq!(lit_pixels(&chip8), 3);
+}
+
+#[test]
+fn test_ex9e_skp() {
+    let chip8 = execute(&[0xE39E], |chip8| {
+        chip8.registers[3] = 0xA;
+        chip8::key_pressed(chip8, 0xA);
+    });
+    assert_eq!(chip8.pc, 0x204);
+    let chip8 = execute(&[0xE39E], |chip8| chip8.registers[3] = 0xA);
+    assert_eq!(chip8.pc, 0x202);
+}
+
+#[test]
+fn test_exa1_sknp() {
+    let chip8 = execute(&[0xE3A1], |chip8| {
+        chip8.registers[3] = 0xA;
+        chip8::key_pressed(chip8, 0xA);
+    });
+    assert_eq!(chip8.pc, 0x202);
+    let chip8 = execute(&[0xE3A1], |chip8| chip8.registers[3] = 0xA);
+    assert_eq!(chip8.pc, 0x204);
+}
+
+#[test]
+fn test_fx07_ld_vx_dt() {
+    let chip8 = execute(&[0xF307], |chip8| chip8.timer_delay = 42);
+    assert_eq!(chip8.registers[3], 42);
+}
+
+#[test]
+fn test_fx0a_ld_vx_k() {
+    let mut chip8 = execute(&[0xF30A, 0x6401], |_chip8| {});
+    assert_eq!(chip8.hold_for_key, Some(3));
+
+    // Nothing runs until a key is pressed
+    chip8::run_frame(&mut chip8);
+    assert_eq!(chip8.pc, 0x202);
+    chip8::key_pressed(&mut chip8, 0xB);
+    assert_eq!(chip8.registers[3], 0xB);
+    assert_eq!(chip8.hold_for_key, None);
+    chip8::step_instruction(&mut chip8);
+    assert_eq!(chip8.registers[4], 1);
+
+    // The wait also stops the rest of the frame
+    let mut chip8 = load(&[0xF30A, 0x6401]);
+    chip8::run_frame(&mut chip8);
+    assert_eq!(chip8.pc, 0x202);
+    assert_eq!(chip8.registers[4], 0);
+}
+
+#[test]
+fn test_fx15_ld_dt_vx() {
+    let chip8 = execute(&[0xF315], |chip8| chip8.registers[3] = 42);
+    assert_eq!(chip8.timer_delay, 42);
+}
+
+#[test]
+fn test_fx18_ld_st_vx() {
+    let chip8 = execute(&[0xF318], |chip8| chip8.registers[3] = 42);
+    assert_eq!(chip8.timer_sound, 42);
+    assert!(chip8::is_buzzer_on(&chip8));
+}
+
+#[test]
+fn test_fx1e_add_i_vx() {
+    let chip8 = execute(&[0xF31E], |chip8| {
+        chip8.register_i = 0x300;
+        chip8.registers[3] = 0x42;
+    });
+    assert_eq!(chip8.register_i, 0x342);
+}
+
+#[test]
+fn test_fx29_ld_f_vx() {
+    let chip8 = execute(&[0xF329], |chip8| chip8.registers[3] = 0xA);
+    assert_eq!(chip8.register_i, 50);
+    // Only the low nibble picks the digit
+    let chip8 = execute(&[0xF329], |chip8| chip8.registers[3] = 0x1A);
+    assert_eq!(chip8.register_i, 50);
+}
+
+#[test]
+fn test_fx33_ld_b_vx() {
+    let chip8 = execute(&[0xF333], |chip8| {
+        chip8.register_i = 0x300;
+        chip8.registers[3] = 137;
+    });
+    assert_eq!(chip8.memory[0x300..0x303], [1, 3, 7]);
+}
+
+#[test]
+fn test_fx55_ld_i_vx() {
+    let setup = |chip8: &mut Chip8| {
+        chip8.register_i = 0x300;
+        chip8.registers[..3].copy_from_slice(&[1, 2, 3]);
+        chip8.registers[3] = 4;
+    };
+    let chip8 = execute(&[0xF255], setup);
+    assert_eq!(chip8.memory[0x300..0x304], [1, 2, 3, 0]);
+    assert_eq!(chip8.register_i, 0x300);
+
+    let chip8 = execute(&[0xF255], |chip8| {
+        setup(chip8);
+        chip8.quirks = cosmac();
+    });
+    assert_eq!(chip8.register_i, 0x303);
+}
+
+#[test]
+fn test_fx65_ld_vx_i() {
+    let setup = |chip8: &mut Chip8| {
+        chip8.register_i = 0x300;
+        chip8.memory[0x300..0x304].copy_from_slice(&[1, 2, 3, 4]);
+    };
+    let chip8 = execute(&[0xF265], setup);
+    assert_eq!(chip8.registers[..4], [1, 2, 3, 0]);
+    assert_eq!(chip8.register_i, 0x300);
+
+    let chip8 = execute(&[0xF265], |chip8| {
+        setup(chip8);
+        chip8.quirks = cosmac();
+    });
+    assert_eq!(chip8.register_i, 0x303);
+    assert_eq!(chip8.pc, LOAD_ADDRESS + 2);
+}
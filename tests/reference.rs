@@ -0,0 +1,288 @@
+// Differential test: single instructions on random machine states, compared with a small
+// reference interpreter written from the specification, independently of chip8.rs.
+// http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#3.1 and the quirks of chip8::Quirks
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use chip_8::cache::{self, DecodeCache};
+use chip_8::chip8::{self, Chip8, Quirks, HEIGHT, WIDTH};
+use chip_8::display::Display;
+use proptest::prelude::*;
+
+const DISPLAY_SIZE: usize = WIDTH as usize * HEIGHT as usize;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Machine {
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    sp: u16,
+    stack: [u16; 16],
+    delay: u8,
+    sound: u8,
+    keys: [bool; 16],
+    waiting_for_key: Option<u8>,
+    rng: u32,
+    memory: Vec<u8>,
+    display: Vec<bool>,
+}
+
+impl Machine {
+    fn execute(&mut self, quirks: &Quirks) {
+        let opcode = u16::from_be_bytes([
+            self.memory[self.pc as usize],
+            self.memory[self.pc as usize + 1],
+        ]);
+        self.pc += 2;
+
+        let x = (opcode >> 8 & 0xF) as usize;
+        let y = (opcode >> 4 & 0xF) as usize;
+        let n = (opcode & 0xF) as usize;
+        let kk = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+
+        // As on the COSMAC VIP, 5xyn and 9xyn compare whatever n is
+        match (opcode >> 12, n) {
+            (0x0, _) if opcode == 0x00E0 => self.display = vec![false; DISPLAY_SIZE],
+            (0x0, _) if opcode == 0x00EE => {
+                self.pc = self.stack[self.sp as usize];
+                self.sp -= 1;
+            }
+            (0x1, _) => self.pc = nnn,
+            (0x2, _) => {
+                self.sp += 1;
+                self.stack[self.sp as usize] = self.pc;
+                self.pc = nnn;
+            }
+            (0x3, _) => self.skip_if(self.v[x] == kk),
+            (0x4, _) => self.skip_if(self.v[x] != kk),
+            (0x5, _) => self.skip_if(self.v[x] == self.v[y]),
+            (0x6, _) => self.v[x] = kk,
+            (0x7, _) => self.v[x] = self.v[x].wrapping_add(kk),
+            (0x8, 0x0) => self.v[x] = self.v[y],
+            (0x8, 0x1) => self.logic(x, self.v[x] | self.v[y], quirks),
+            (0x8, 0x2) => self.logic(x, self.v[x] & self.v[y], quirks),
+            (0x8, 0x3) => self.logic(x, self.v[x] ^ self.v[y], quirks),
+            (0x8, 0x4) => {
+                let (result, carry) = self.v[x].overflowing_add(self.v[y]);
+                self.arithmetic(x, result, carry);
+            }
+            (0x8, 0x5) => {
+                let (result, borrow) = self.v[x].overflowing_sub(self.v[y]);
+                self.arithmetic(x, result, !borrow);
+            }
+            (0x8, 0x7) => {
+                let (result, borrow) = self.v[y].overflowing_sub(self.v[x]);
+                self.arithmetic(x, result, !borrow);
+            }
+            (0x8, 0x6) => {
+                let value = self.v[if quirks.shift_vx { x } else { y }];
+                self.arithmetic(x, value >> 1, value & 1 == 1);
+            }
+            (0x8, 0xE) => {
+                let value = self.v[if quirks.shift_vx { x } else { y }];
+                self.arithmetic(x, value << 1, value & 0x80 == 0x80);
+            }
+            (0x9, _) => self.skip_if(self.v[x] != self.v[y]),
+            (0xA, _) => self.i = nnn,
+            (0xB, _) => self.pc = nnn + self.v[if quirks.jump_vx { x } else { 0 }] as u16,
+            (0xC, _) => {
+                // xorshift32, the top byte of the new state
+                self.rng ^= self.rng << 13;
+                self.rng ^= self.rng >> 17;
+                self.rng ^= self.rng << 5;
+                self.v[x] = (self.rng >> 24) as u8 & kk;
+            }
+            (0xD, _) => self.draw(x, y, n, quirks),
+            (0xE, _) if kk == 0x9E => self.skip_if(self.keys[self.v[x] as usize % 16]),
+            (0xE, _) if kk == 0xA1 => self.skip_if(!self.keys[self.v[x] as usize % 16]),
+            (0xF, _) => match kk {
+                0x07 => self.v[x] = self.delay,
+                0x0A => self.waiting_for_key = Some(x as u8),
+                0x15 => self.delay = self.v[x],
+                0x18 => self.sound = self.v[x],
+                0x1E => self.i += self.v[x] as u16,
+                0x29 => self.i = (self.v[x] % 16) as u16 * 5,
+                0x33 => {
+                    let i = self.i as usize;
+                    self.memory[i] = self.v[x] / 100;
+                    self.memory[i + 1] = self.v[x] / 10 % 10;
+                    self.memory[i + 2] = self.v[x] % 10;
+                }
+                0x55 | 0x65 => {
+                    for register in 0..=x {
+                        let address = self.i as usize + register;
+                        if kk == 0x55 {
+                            self.memory[address] = self.v[register];
+                        } else {
+                            self.v[register] = self.memory[address];
+                        }
+                    }
+                    if quirks.load_store_increment_i {
+                        self.i += x as u16 + 1;
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.pc += 2;
+        }
+    }
+
+    fn logic(&mut self, x: usize, result: u8, quirks: &Quirks) {
+        self.v[x] = result;
+        if quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
+    }
+
+    // The flag is written after the result, so it's what VF holds when x is F
+    fn arithmetic(&mut self, x: usize, result: u8, flag: bool) {
+        self.v[x] = result;
+        self.v[0xF] = flag as u8;
+    }
+
+    fn draw(&mut self, x: usize, y: usize, n: usize, quirks: &Quirks) {
+        let (width, height) = (WIDTH as usize, HEIGHT as usize);
+        let left = self.v[x] as usize % width;
+        let top = self.v[y] as usize % height;
+        let mut collision = false;
+
+        for row in 0..n {
+            let bits = self.memory[self.i as usize + row];
+            for column in 0..8 {
+                let (px, py) = (left + column, top + row);
+                if bits & (0x80 >> column) == 0
+                    || (quirks.clip_sprites && (px >= width || py >= height))
+                {
+                    continue;
+                }
+                let pixel = &mut self.display[py % height * width + px % width];
+                collision |= *pixel;
+                *pixel = !*pixel;
+            }
+        }
+        self.v[0xF] = collision as u8;
+    }
+}
+
+// The reference machine in the core's, and back
+fn to_chip8(machine: &Machine, quirks: Quirks) -> Chip8 {
+    let mut chip8 = Chip8::new(&[0]);
+    chip8.registers = machine.v;
+    chip8.register_i = machine.i;
+    chip8.pc = machine.pc;
+    chip8.sp = machine.sp;
+    chip8.stack = machine.stack;
+    chip8.timer_delay = machine.delay;
+    chip8.timer_sound = machine.sound;
+    chip8.keys = machine.keys;
+    chip8.hold_for_key = machine.waiting_for_key;
+    chip8.rng_state = machine.rng;
+    chip8.memory = machine.memory.clone();
+    let pixels: Vec<u8> = machine.display.iter().map(|lit| *lit as u8).collect();
+    chip8.display = Display::from_bytes(&pixels);
+    chip8.quirks = quirks;
+    chip8
+}
+
+fn from_chip8(chip8: &Chip8) -> Machine {
+    Machine {
+        v: chip8.registers,
+        i: chip8.register_i,
+        pc: chip8.pc,
+        sp: chip8.sp,
+        stack: chip8.stack,
+        delay: chip8.timer_delay,
+        sound: chip8.timer_sound,
+        keys: chip8.keys,
+        waiting_for_key: chip8.hold_for_key,
+        rng: chip8.rng_state,
+        memory: chip8.memory.clone(),
+        display: chip8.display.pixels().collect(),
+    }
+}
+
+// Any opcode, with more of the 8xyn and Fxkk ones that do something
+fn opcode() -> impl Strategy<Value = u16> {
+    prop_oneof![
+        any::<u16>(),
+        (
+            0..16u16,
+            0..16u16,
+            prop::sample::select(vec![0, 1, 2, 3, 4, 5, 6, 7, 0xE])
+        )
+            .prop_map(|(x, y, n)| 0x8000 | x << 8 | y << 4 | n),
+        (
+            0..16u16,
+            prop::sample::select(vec![0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65])
+        )
+            .prop_map(|(x, kk)| 0xF000 | x << 8 | kk),
+    ]
+}
+
+fn quirks() -> impl Strategy<Value = Quirks> {
+    any::<[bool; 6]>().prop_map(|flags| Quirks {
+        shift_vx: flags[0],
+        load_store_increment_i: flags[1],
+        jump_vx: flags[2],
+        clip_sprites: flags[3],
+        vf_reset: flags[4],
+        display_wait: flags[5],
+    })
+}
+
+prop_compose! {
+    // Within what the programs the core accepts can do: I with room for 16 bytes after it,
+    // a stack that can take a call and a return
+    fn machine()(
+        v in any::<[u8; 16]>(),
+        i in 0..0xFF0u16,
+        pc in (0x100..0x7FEu16).prop_map(|pc| pc * 2),
+        sp in 1..15u16,
+        stack in any::<[u16; 16]>(),
+        (delay, sound) in any::<(u8, u8)>(),
+        keys in any::<[bool; 16]>(),
+        rng in any::<u32>(),
+        memory in prop::collection::vec(any::<u8>(), chip8::MEMORY_SIZE),
+        display in prop::collection::vec(any::<bool>(), DISPLAY_SIZE),
+        opcode in opcode(),
+    ) -> Machine {
+        let mut machine = Machine {
+            v, i, pc, sp, stack, delay, sound, keys,
+            waiting_for_key: None,
+            rng,
+            memory,
+            display,
+        };
+        machine.memory[pc as usize..pc as usize + 2].copy_from_slice(&opcode.to_be_bytes());
+        machine
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+
+    #[test]
+    fn test_against_reference(machine in machine(), quirks in quirks()) {
+        let mut chip8 = to_chip8(&machine, quirks);
+        chip8::step_instruction(&mut chip8);
+        let mut cached = to_chip8(&machine, quirks);
+        cache::step_instruction(&mut cached, &mut DecodeCache::new());
+
+        let mut expected = machine.clone();
+        expected.execute(&quirks);
+
+        let opcode = u16::from_be_bytes([
+            machine.memory[machine.pc as usize],
+            machine.memory[machine.pc as usize + 1],
+        ]);
+        prop_assert_eq!(from_chip8(&chip8), expected.clone(), "opcode {:04X}", opcode);
+        prop_assert_eq!(from_chip8(&cached), expected, "cached opcode {:04X}", opcode);
+    }
+}
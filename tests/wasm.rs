@@ -0,0 +1,24 @@
+// Run with: wasm-pack test --node -- --no-default-features
+// or: cargo test --target wasm32-unknown-unknown --no-default-features
+#![cfg(target_arch = "wasm32")]
+
+mod common;
+
+use chip_8::wasm::Machine;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn test_wasm_framebuffers() {
+    for case in common::CASES.iter() {
+        let mut machine = Machine::new(case.rom).unwrap();
+        machine.seed(case.seed);
+
+        for _frame in 0..case.frames {
+            machine.run_frame();
+        }
+
+        let framebuffer =
+            common::framebuffer_to_text(&machine.framebuffer(), machine.width() as usize);
+        assert_eq!(framebuffer, case.expected, "{}", case.name);
+    }
+}